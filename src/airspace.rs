@@ -0,0 +1,643 @@
+//! Airspace from OpenAir files, drawn over the chart with its class and vertical limits, since
+//! approach plates leave most of it out.
+//!
+//! Supported records: `AC` class, `AN` name, `AL`/`AH` limits, `DP` points, `V X=` arc centre,
+//! `V D=` arc direction, `DA`/`DB` arcs and `DC` circles. Anything else is skipped.
+
+use std::sync::mpsc::Receiver;
+
+use crate::app::LivechartApp;
+use crate::geo::{self, Georeference};
+use crate::structs::{PixelCoordinate, RealCoordinate};
+
+/// Arcs and circles are drawn with a point every this many degrees.
+const ARC_STEP_DEG: f64 = 5.0;
+
+/// Lower or upper limit of an airspace.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Limit {
+    Surface,
+    /// Above mean sea level.
+    Msl(f32),
+    /// Above ground level.
+    Agl(f32),
+    FlightLevel(u16),
+    Unlimited,
+    /// Whatever the file said, when we couldn't make sense of it.
+    Other(String),
+}
+
+impl Limit {
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim().to_uppercase();
+        let compact: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        if matches!(compact.as_str(), "GND" | "SFC" | "SURFACE" | "0" | "0FT") {
+            return Self::Surface;
+        }
+        if compact.starts_with("UNL") {
+            return Self::Unlimited;
+        }
+        if let Some(level) = compact.strip_prefix("FL") {
+            return level
+                .parse()
+                .map_or_else(|_| Self::Other(text.clone()), Self::FlightLevel);
+        }
+
+        let digits = compact
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(compact.len());
+        let Ok(value) = compact[..digits].parse::<f32>() else {
+            return Self::Other(text);
+        };
+        let mut unit = &compact[digits..];
+        let metres = unit.starts_with('M') && !unit.starts_with("MSL");
+        if metres {
+            unit = &unit[1..];
+        }
+        unit = unit.strip_prefix("FT").unwrap_or(unit);
+        let feet = if metres {
+            value / geo::METRES_PER_FT as f32
+        } else {
+            value
+        };
+        match unit {
+            "" | "MSL" | "AMSL" | "ALT" => Self::Msl(feet),
+            "AGL" | "GND" | "SFC" | "AGND" => Self::Agl(feet),
+            _ => Self::Other(text),
+        }
+    }
+}
+
+impl std::fmt::Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Surface => write!(f, "GND"),
+            Self::Msl(feet) => write!(f, "{feet:.0} ft MSL"),
+            Self::Agl(feet) => write!(f, "{feet:.0} ft AGL"),
+            Self::FlightLevel(level) => write!(f, "FL{level:03}"),
+            Self::Unlimited => write!(f, "UNL"),
+            Self::Other(text) => write!(f, "{text}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Airspace {
+    /// OpenAir class, e.g. `C`, `D`, `R` or `CTR`.
+    pub class: String,
+    pub name: String,
+    pub lower: Limit,
+    pub upper: Limit,
+    pub polygon: Vec<RealCoordinate>,
+}
+
+impl Airspace {
+    fn new(class: &str) -> Self {
+        Self {
+            class: class.trim().to_uppercase(),
+            name: String::new(),
+            lower: Limit::Surface,
+            upper: Limit::Unlimited,
+            polygon: Vec::new(),
+        }
+    }
+
+    /// Outline colour and whether the outline is dashed, roughly as on a sectional.
+    fn style(&self) -> (egui::Color32, bool) {
+        match self.class.as_str() {
+            "A" | "B" => (egui::Color32::from_rgb(40, 90, 210), false),
+            "C" => (egui::Color32::from_rgb(190, 40, 140), false),
+            "D" | "CTR" => (egui::Color32::from_rgb(40, 90, 210), true),
+            "E" => (egui::Color32::from_rgb(190, 40, 140), true),
+            "R" | "P" | "Q" => (egui::Color32::from_rgb(220, 40, 40), false),
+            "W" | "GP" => (egui::Color32::from_rgb(230, 140, 0), true),
+            "RMZ" | "TMZ" => (egui::Color32::from_rgb(120, 120, 120), true),
+            _ => (egui::Color32::from_rgb(120, 120, 120), false),
+        }
+    }
+}
+
+/// `DD:MM:SS` or `DD:MM.mmm`, any part may have decimals.
+fn parse_dms(text: &str) -> Option<f64> {
+    let mut value = 0.0;
+    let mut scale = 1.0;
+    for part in text.trim().split(':') {
+        value += part.trim().parse::<f64>().ok()? / scale;
+        scale *= 60.0;
+    }
+    Some(value)
+}
+
+/// `39:29:54 N 119:46:06 W`, with or without the spaces.
+fn parse_coordinate(text: &str) -> Option<RealCoordinate> {
+    let text = text.trim().to_uppercase();
+    let lat_end = text.find(['N', 'S'])?;
+    let lon_text = &text[lat_end + 1..];
+    let lon_end = lon_text.find(['E', 'W'])?;
+
+    let mut lat = parse_dms(&text[..lat_end])?;
+    let mut lon = parse_dms(&lon_text[..lon_end])?;
+    if text[lat_end..].starts_with('S') {
+        lat = -lat;
+    }
+    if lon_text[lon_end..].starts_with('W') {
+        lon = -lon;
+    }
+    ((-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon))
+        .then_some(RealCoordinate { lat, lon })
+}
+
+/// Points along an arc around `center` from bearing `start` to `end`, both ends included.
+fn arc(
+    center: &RealCoordinate,
+    radius_m: f64,
+    start: f64,
+    end: f64,
+    clockwise: bool,
+) -> Vec<RealCoordinate> {
+    let mut sweep = if clockwise { end - start } else { start - end }.rem_euclid(360.0);
+    if sweep == 0.0 {
+        sweep = 360.0;
+    }
+    let steps = (sweep / ARC_STEP_DEG).ceil().max(1.0) as usize;
+    let direction = if clockwise { 1.0 } else { -1.0 };
+    (0..=steps)
+        .map(|step| {
+            let bearing = start + direction * sweep * step as f64 / steps as f64;
+            geo::destination(center, bearing, radius_m)
+        })
+        .collect()
+}
+
+/// Parses an OpenAir file. Airspaces with fewer than three points are dropped.
+pub fn parse(text: &str) -> Vec<Airspace> {
+    let mut airspaces = Vec::new();
+    let mut current: Option<Airspace> = None;
+    let mut center: Option<RealCoordinate> = None;
+    let mut clockwise = true;
+
+    let mut finish = |airspace: Option<Airspace>| {
+        if let Some(airspace) = airspace.filter(|airspace| airspace.polygon.len() >= 3) {
+            airspaces.push(airspace);
+        }
+    };
+
+    for line in text.lines() {
+        // Comments run to the end of the line
+        let line = line.split('*').next().unwrap_or_default().trim();
+        let Some((command, rest)) = line.split_once(char::is_whitespace) else {
+            continue;
+        };
+        let rest = rest.trim();
+
+        if command.eq_ignore_ascii_case("AC") {
+            finish(current.take());
+            current = Some(Airspace::new(rest));
+            center = None;
+            clockwise = true;
+            continue;
+        }
+        let Some(airspace) = &mut current else {
+            continue;
+        };
+
+        match command.to_uppercase().as_str() {
+            "AN" => airspace.name = rest.to_owned(),
+            "AL" => airspace.lower = Limit::parse(rest),
+            "AH" => airspace.upper = Limit::parse(rest),
+            "DP" => airspace.polygon.extend(parse_coordinate(rest)),
+            "V" => {
+                let Some((variable, value)) = rest.split_once('=') else {
+                    continue;
+                };
+                match variable.trim().to_uppercase().as_str() {
+                    "X" => center = parse_coordinate(value),
+                    "D" => clockwise = value.trim() != "-",
+                    _ => {}
+                }
+            }
+            // Radius in NM, then start and end bearing
+            "DA" => {
+                let values: Vec<f64> = rest
+                    .split(',')
+                    .filter_map(|value| value.trim().parse().ok())
+                    .collect();
+                if let (Some(center), [radius, start, end]) = (&center, values.as_slice()) {
+                    airspace.polygon.extend(arc(
+                        center,
+                        radius * geo::METRES_PER_NM,
+                        *start,
+                        *end,
+                        clockwise,
+                    ));
+                }
+            }
+            // From one point to another around the centre
+            "DB" => {
+                let points: Vec<RealCoordinate> =
+                    rest.split(',').filter_map(parse_coordinate).collect();
+                if let (Some(center), [from, to]) = (&center, points.as_slice()) {
+                    airspace.polygon.extend(arc(
+                        center,
+                        geo::distance_m(center, from),
+                        geo::bearing_deg(center, from),
+                        geo::bearing_deg(center, to),
+                        clockwise,
+                    ));
+                }
+            }
+            "DC" => {
+                if let (Some(center), Ok(radius)) = (&center, rest.parse::<f64>()) {
+                    let mut circle = arc(center, radius * geo::METRES_PER_NM, 0.0, 360.0, true);
+                    circle.pop();
+                    airspace.polygon.extend(circle);
+                }
+            }
+            _ => {}
+        }
+    }
+    finish(current.take());
+    airspaces
+}
+
+/// Airspace outlines in image pixels for one georeference, so they are only projected again
+/// when the control points change.
+pub struct PixelOutlines {
+    georeference: Georeference,
+    outlines: Vec<Outline>,
+}
+
+/// One airspace's polygon in image pixels.
+struct Outline {
+    /// Closed: the first point is repeated at the end.
+    points: Vec<egui::Pos2>,
+    bounds: egui::Rect,
+}
+
+impl PixelOutlines {
+    fn new(airspaces: &[Airspace], georeference: &Georeference) -> Self {
+        let outlines = airspaces
+            .iter()
+            .map(|airspace| {
+                let mut points: Vec<egui::Pos2> = airspace
+                    .polygon
+                    .iter()
+                    .map(|real| {
+                        let pixel = georeference.real_to_pixel(real);
+                        egui::pos2(pixel.x, pixel.y)
+                    })
+                    .collect();
+                if let Some(&first) = points.first() {
+                    points.push(first);
+                }
+                Outline {
+                    bounds: egui::Rect::from_points(&points),
+                    points,
+                }
+            })
+            .collect();
+        Self {
+            georeference: georeference.clone(),
+            outlines,
+        }
+    }
+}
+
+fn contains(polygon: &[egui::Pos2], point: egui::Pos2) -> bool {
+    let mut inside = false;
+    for (index, a) in polygon.iter().enumerate() {
+        let b = polygon[(index + 1) % polygon.len()];
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+impl LivechartApp {
+    /// Loads the airspace file in the background when its path changed, including at startup.
+    pub fn sync_airspace(&mut self, ctx: &egui::Context) {
+        if let Some(receiver) = &self.live.airspace_loading {
+            match receiver.try_recv() {
+                Ok(Ok(airspaces)) => {
+                    self.live.airspace_status = Some(format!("{} airspaces", airspaces.len()));
+                    self.live.airspaces = airspaces;
+                    self.live.airspace_outlines = None;
+                    self.live.airspace_loading = None;
+                }
+                Ok(Err(e)) => {
+                    self.live.airspace_status = Some(e);
+                    self.live.airspace_loading = None;
+                }
+                Err(std::sync::mpsc::TryRecvError::Empty) => {}
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                    self.live.airspace_status = Some("Loading stopped unexpectedly".to_owned());
+                    self.live.airspace_loading = None;
+                }
+            }
+        }
+
+        let path = self.data.airspace_path.clone();
+        if path.is_empty() || self.live.airspace_source.as_ref() == Some(&path) {
+            return;
+        }
+        self.live.airspace_source = Some(path.clone());
+        self.live.airspaces.clear();
+        self.live.airspace_outlines = None;
+        self.live.airspace_loading = Some(load_in_background(path, ctx.clone()));
+        self.live.airspace_status = Some("Loading…".to_owned());
+    }
+
+    /// Airspace outlines on the chart, with the name and limits of the ones under the pointer.
+    pub fn draw_airspace(
+        &mut self,
+        ui: &egui::Ui,
+        image_response: &egui::Response,
+        image_size: (u32, u32),
+        georeference: &Georeference,
+    ) {
+        if self
+            .live
+            .airspace_outlines
+            .as_ref()
+            .map_or(true, |cached| cached.georeference != *georeference)
+        {
+            self.live.airspace_outlines =
+                Some(PixelOutlines::new(&self.live.airspaces, georeference));
+        }
+        let Some(cached) = &self.live.airspace_outlines else {
+            return;
+        };
+
+        let painter = ui.painter_at(image_response.rect);
+        let image = egui::Rect::from_min_size(
+            egui::Pos2::ZERO,
+            egui::vec2(image_size.0 as f32, image_size.1 as f32),
+        );
+        let pointer = image_response
+            .hover_pos()
+            .map(|pos| {
+                let pixel = self.screen_to_pixel(pos, image_response, image_size);
+                egui::pos2(pixel.x, pixel.y)
+            })
+            .filter(|pos| image.contains(*pos));
+
+        let mut hovered = Vec::new();
+        for (airspace, outline) in self.live.airspaces.iter().zip(&cached.outlines) {
+            if !outline.bounds.intersects(image) {
+                continue;
+            }
+            let screen: Vec<egui::Pos2> = outline
+                .points
+                .iter()
+                .map(|pos| {
+                    self.pixel_to_screen(
+                        &PixelCoordinate { x: pos.x, y: pos.y },
+                        image_response,
+                        image_size,
+                    )
+                })
+                .collect();
+            let (color, dashed) = airspace.style();
+            let stroke = egui::Stroke::new(2.0, color);
+
+            // One line around the whole polygon, so the dashes run on across the corners
+            painter.add(egui::Shape::line(
+                screen.clone(),
+                egui::Stroke::new(6.0, color.gamma_multiply(0.2)),
+            ));
+            if dashed {
+                painter.extend(egui::Shape::dashed_line(&screen, stroke, 8.0, 5.0));
+            } else {
+                painter.add(egui::Shape::line(screen, stroke));
+            }
+
+            if pointer
+                .is_some_and(|pos| outline.bounds.contains(pos) && contains(&outline.points, pos))
+            {
+                hovered.push(airspace);
+            }
+        }
+        if hovered.is_empty() {
+            return;
+        }
+        egui::show_tooltip_at_pointer(
+            ui.ctx(),
+            ui.layer_id(),
+            egui::Id::new("airspace_hover"),
+            |ui| {
+                for airspace in hovered {
+                    let (color, _) = airspace.style();
+                    ui.colored_label(color, format!("{} ({})", airspace.name, airspace.class));
+                    ui.label(format!("{} to {}", airspace.lower, airspace.upper));
+                }
+            },
+        );
+    }
+
+    pub fn airspace_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.live.airspace_window_open;
+        egui::Window::new("Airspace")
+            .open(&mut open)
+            .default_width(300.0)
+            .show(ctx, |ui| {
+                if self.live.airspace_new_path.is_empty() {
+                    self.live.airspace_new_path = self.data.airspace_path.clone();
+                }
+                ui.horizontal(|ui| {
+                    ui.label("OpenAir file:");
+                    ui.text_edit_singleline(&mut self.live.airspace_new_path);
+                    if ui.button("Load").clicked() {
+                        self.data.airspace_path = self.live.airspace_new_path.trim().to_owned();
+                        self.data.show_airspace = true;
+                        // Loading the same path again re-reads it
+                        self.live.airspace_source = None;
+                    }
+                });
+                if let Some(status) = &self.live.airspace_status {
+                    ui.weak(status);
+                }
+                ui.checkbox(&mut self.data.show_airspace, "Draw on the chart")
+                    .on_hover_text("Hover an airspace for its name and limits");
+            });
+        self.live.airspace_window_open = open;
+    }
+}
+
+/// Reading and parsing a country's airspace takes a moment, so it happens off the UI thread.
+fn load_in_background(path: String, ctx: egui::Context) -> Receiver<Result<Vec<Airspace>, String>> {
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let spawned = std::thread::Builder::new()
+            .name("airspace".to_owned())
+            .spawn(move || {
+                let airspaces = std::fs::read(&path)
+                    .map(|bytes| parse(&String::from_utf8_lossy(&bytes)))
+                    .map_err(|e| format!("Could not read {path}: {e}"));
+                let _ = sender.send(airspaces);
+                ctx.request_repaint();
+            });
+        if let Err(e) = spawned {
+            log::error!("Could not start the airspace thread: {e}");
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = path;
+        let _ = sender.send(Err("Loading files is not supported on the web".to_owned()));
+        ctx.request_repaint();
+    }
+
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPENAIR: &str = "\
+* Sample airspace, coordinates as in the published files
+AC C
+AN TMA ZURICH 1
+AL 3000 ft AMSL
+AH FL100
+DP 47:30:00 N 008:30:00 E
+DP 47:30:00 N 008:40:00 E
+V X=47:27:30 N 008:35:00 E
+DB 47:25:00 N 008:40:00 E, 47:25:00 N 008:30:00 E
+
+AC R
+AN R-8 SECTOR EAST   * closed on weekends
+AL FL100
+AH FL195
+V X=47:00:00 N 008:00:00 E
+V D=-
+DA 5,90,0
+DP 47:00:00 N 008:00:00 E
+
+AC CTR
+AN CTR TEST
+AL GND
+AH 2500ft AGL
+V X=46:30:00 N 007:30:00 E
+DC 2.5
+
+AC Q
+AN TOO FEW POINTS
+DP 46:00:00 N 007:00:00 E
+DP 46:10:00 N 007:00:00 E
+";
+
+    fn center(lat: f64, lon: f64) -> RealCoordinate {
+        RealCoordinate { lat, lon }
+    }
+
+    #[test]
+    fn parses_names_classes_and_limits() {
+        let airspaces = parse(OPENAIR);
+        let summary: Vec<_> = airspaces
+            .iter()
+            .map(|a| (a.class.as_str(), a.name.as_str(), &a.lower, &a.upper))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    "C",
+                    "TMA ZURICH 1",
+                    &Limit::Msl(3000.0),
+                    &Limit::FlightLevel(100)
+                ),
+                (
+                    "R",
+                    "R-8 SECTOR EAST",
+                    &Limit::FlightLevel(100),
+                    &Limit::FlightLevel(195)
+                ),
+                ("CTR", "CTR TEST", &Limit::Surface, &Limit::Agl(2500.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn draws_arcs_between_points_clockwise_by_default() {
+        let tma = &parse(OPENAIR)[0];
+        assert_eq!(tma.polygon[0], center(47.5, 8.5));
+        assert_eq!(tma.polygon[1], center(47.5, 8.0 + 40.0 / 60.0));
+
+        let middle = center(47.0 + 27.5 / 60.0, 8.0 + 35.0 / 60.0);
+        let from = center(47.0 + 25.0 / 60.0, 8.0 + 40.0 / 60.0);
+        let to = center(47.0 + 25.0 / 60.0, 8.5);
+        let radius = geo::distance_m(&middle, &from);
+        let arc = &tma.polygon[2..];
+        assert!(arc
+            .iter()
+            .all(|point| (geo::distance_m(&middle, point) - radius).abs() < 1.0));
+        assert!(geo::distance_m(arc.last().unwrap(), &to) < 10.0);
+        // Clockwise from the south east to the south west passes south of the centre
+        assert!(arc.iter().any(|point| point.lat < from.lat - 0.01));
+    }
+
+    #[test]
+    fn draws_arcs_by_radius_and_bearings_anticlockwise_after_v_d_minus() {
+        let restricted = &parse(OPENAIR)[1];
+        let middle = center(47.0, 8.0);
+        let arc = &restricted.polygon[..restricted.polygon.len() - 1];
+        assert_eq!(arc.len(), 90 / ARC_STEP_DEG as usize + 1);
+
+        let bearings: Vec<f64> = arc
+            .iter()
+            .map(|point| geo::bearing_deg(&middle, point))
+            .collect();
+        assert!((bearings[0] - 90.0).abs() < 0.1);
+        assert!(bearings.last().unwrap().abs() < 0.1);
+        assert!(bearings.windows(2).all(|pair| pair[1] < pair[0]));
+        assert!(arc
+            .iter()
+            .all(|point| (geo::distance_m(&middle, point) - 5.0 * geo::METRES_PER_NM).abs() < 1.0));
+        assert_eq!(restricted.polygon.last(), Some(&middle));
+    }
+
+    #[test]
+    fn draws_circles() {
+        let ctr = &parse(OPENAIR)[2];
+        assert_eq!(ctr.polygon.len(), (360.0 / ARC_STEP_DEG) as usize);
+        let middle = center(46.5, 7.5);
+        assert!(ctr
+            .polygon
+            .iter()
+            .all(|point| (geo::distance_m(&middle, point) - 2.5 * geo::METRES_PER_NM).abs() < 1.0));
+    }
+
+    #[test]
+    fn parses_coordinates_with_and_without_spaces() {
+        assert_eq!(
+            parse_coordinate("39:29:54 N 119:46:06 W"),
+            parse_coordinate("39:29:54N119:46:06W")
+        );
+        let south_east = parse_coordinate("33:56.5 S 151:10.5 E").unwrap();
+        assert!((south_east.lat + 33.0 + 56.5 / 60.0).abs() < 1e-9);
+        assert!((south_east.lon - 151.0 - 10.5 / 60.0).abs() < 1e-9);
+        assert_eq!(parse_coordinate("91:00:00 N 008:00:00 E"), None);
+    }
+
+    #[test]
+    fn parses_limits() {
+        assert_eq!(Limit::parse("SFC"), Limit::Surface);
+        assert_eq!(Limit::parse("FL 65"), Limit::FlightLevel(65));
+        assert_eq!(Limit::parse("UNLTD"), Limit::Unlimited);
+        assert_eq!(Limit::parse("4500 MSL"), Limit::Msl(4500.0));
+        assert_eq!(Limit::parse("1000 ft GND"), Limit::Agl(1000.0));
+        assert_eq!(Limit::parse("NOTAM"), Limit::Other("NOTAM".to_owned()));
+        let Limit::Msl(feet) = Limit::parse("1500m") else {
+            panic!("metres are above MSL");
+        };
+        assert!((feet - 4921.3).abs() < 0.1);
+        assert_eq!(Limit::FlightLevel(65).to_string(), "FL065");
+    }
+}
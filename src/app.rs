@@ -1,13 +1,18 @@
 use egui::Vec2;
-use image::GenericImageView;
 
-use crate::structs::{CoordinatePair, LiveChartAppData};
+use crate::geo::Georeference;
+use crate::structs::{CoordinatePair, Fix, LiveChartAppData, LiveState};
+
+/// Chart shown until the user opens one from the library.
+pub const CHART_FILE: &str = "test_chart_vertical_ils_plate.png";
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)] // if we add new fields, give them default values when deserializing old state
 pub struct LivechartApp {
     pub data: LiveChartAppData,
+    #[serde(skip)]
+    pub live: LiveState,
 }
 
 //TODO Also clamp saved point positions to prevent overflow on image or dont to display that something with the placement went wrong
@@ -26,6 +31,20 @@ impl LivechartApp {
 
         Default::default()
     }
+
+    /// Entry point for every ownship fix, live or replayed.
+    pub fn ingest_fix(&mut self, fix: Fix) {
+        // Replayed fixes are already recorded, and seeking would write them out of order
+        if self.live.replay.is_none() {
+            self.record_fix(&fix);
+        }
+        self.live.trail.push(
+            fix.clone(),
+            f64::from(self.data.ownship_display.trail_minutes) * 60.0,
+        );
+        self.live.ownship = Some(fix);
+        self.live.ownship_received = Some(crate::utc::now());
+    }
 }
 
 impl eframe::App for LivechartApp {
@@ -52,9 +71,94 @@ impl eframe::App for LivechartApp {
                     });
                     ui.add_space(16.0);
                 }
+
+                if ui.button("Replay").clicked() {
+                    self.live.replay_window_open = !self.live.replay_window_open;
+                }
+
+                let recording_label = if self.live.recorder.is_some() {
+                    "⏺ Recording"
+                } else {
+                    "Recording"
+                };
+                if ui.button(recording_label).clicked() {
+                    self.live.recording_window_open = !self.live.recording_window_open;
+                    self.refresh_sessions();
+                }
+
+                if ui.button("Feeds").clicked() {
+                    self.live.feeds_window_open = !self.live.feeds_window_open;
+                }
+
+                if ui.button("Outputs").clicked() {
+                    self.live.outputs_window_open = !self.live.outputs_window_open;
+                }
+
+                if ui.button("GNSS").clicked() {
+                    self.live.gnss_window_open = !self.live.gnss_window_open;
+                }
+
+                if ui.button("Diagnostics").clicked() {
+                    self.live.diagnostics_window_open = !self.live.diagnostics_window_open;
+                }
+
+                if ui.button("Nav data").clicked() {
+                    self.live.navdb_window_open = !self.live.navdb_window_open;
+                }
+
+                if ui.button("Airspace").clicked() {
+                    self.live.airspace_window_open = !self.live.airspace_window_open;
+                }
+
+                if ui.button("Route").clicked() {
+                    self.live.route_window_open = !self.live.route_window_open;
+                }
+
+                if ui.button("Procedures").clicked() {
+                    self.live.procedures_window_open = !self.live.procedures_window_open;
+                }
+
+                if ui.button("Charts").clicked() {
+                    self.live.library_window_open = !self.live.library_window_open;
+                }
+
+                self.fix_search_menu(ui);
+
+                ui.menu_button("View", |ui| {
+                    self.ownship_display_menu(ui);
+                    ui.separator();
+                    self.traffic_display_menu(ui);
+                    ui.separator();
+                    self.conflict_thresholds_menu(ui);
+                });
             });
         });
 
+        self.sync_feeds(ctx);
+        self.poll_feeds(ctx);
+        self.tick_replay(ctx);
+        self.update_active_leg();
+        self.auto_select_chart();
+        self.assess_traffic();
+        self.sync_outputs();
+        self.sync_navdb(ctx);
+        self.sync_airspace(ctx);
+        self.sync_flight_plan();
+        self.broadcast_outputs(ctx);
+        self.replay_window(ctx);
+        self.recording_window(ctx);
+        self.library_window(ctx);
+        self.feeds_window(ctx);
+        self.outputs_window(ctx);
+        self.gnss_window(ctx);
+        self.diagnostics_window(ctx);
+        self.navdb_window(ctx);
+        self.procedures_window(ctx);
+        self.found_fix_window(ctx);
+        self.route_window(ctx);
+        self.airspace_window(ctx);
+        self.deviation_indicator(ctx);
+
         // Put your widgets into a `SidePanel`, `TopBottomPanel`, `CentralPanel`, `Window` or `Area`.
         if let Some(viewstate) = &self.data.view_state {
             if viewstate.ps_sidebar_shown {
@@ -63,18 +167,27 @@ impl eframe::App for LivechartApp {
         }
 
         egui::CentralPanel::default().show(ctx, |ui| {
-            // Load image dimensions once (could be cached if needed)
-            let image_size = image::open("./test_chart_vertical_ils_plate.png")
-                .unwrap()
-                .dimensions();
+            let image_size = match self.active_chart_size() {
+                Ok(size) => size,
+                Err(error) => {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                    return;
+                }
+            };
 
             let image_size_vec = Vec2::new(image_size.0 as f32, image_size.1 as f32);
 
             // Initialize or update zoom state
             // let zoom_state = self.data.view_state.get_or_insert(ZoomState::default());
 
+            let georeference = Georeference::fit(&self.data.points);
+
             // Calculate display parameters
             let display_params = self.display_zoom_pan(ui, image_size_vec);
+            let display_params =
+                self.follow_ownship(ui, display_params, image_size, georeference.as_ref());
+            let display_params =
+                self.jump_to_found_fix(ui, display_params, image_size, georeference.as_ref());
 
             // Display the image and get the response
             let image_response = self.display_image(ui, display_params);
@@ -87,9 +200,14 @@ impl eframe::App for LivechartApp {
 
             // Handle point creation
             if let Some(coord) = self.add_point(&image_response, image_size) {
+                let real = self.take_procedure_fix();
+                // With nav data, a new point starts out asking which fix it is
+                if real.is_none() && self.live.navdb.is_some() {
+                    self.live.fix_lookup = Some(crate::navdb::FixLookup::new(coord.clone()));
+                }
                 self.data.points.push(CoordinatePair {
                     pixels: coord,
-                    real: None,
+                    real,
                 });
             }
 
@@ -98,6 +216,36 @@ impl eframe::App for LivechartApp {
                 self.draw_pixel_coordinates(&point.pixels, ui, &image_response, image_size);
             }
 
+            // Draw the ownship and its history/prediction on top of the points
+            if let Some(georeference) = &georeference {
+                if self.data.show_airspace {
+                    self.draw_airspace(ui, &image_response, image_size, georeference);
+                }
+                if self.data.show_procedure {
+                    self.draw_procedure(ui, &image_response, image_size, georeference);
+                }
+                self.draw_route(ui, &image_response, image_size, georeference);
+                if self.data.fleet_display.show {
+                    self.draw_fleet(ui, &image_response, image_size, georeference);
+                }
+                if self.data.traffic_display.show {
+                    self.draw_traffic(ui, &image_response, image_size, georeference);
+                }
+                if self.data.ownship_display.show_trail {
+                    self.draw_trail(ui, &image_response, image_size, georeference);
+                }
+                if self.data.ownship_display.show_prediction {
+                    self.draw_predicted_track(ui, &image_response, image_size, georeference);
+                }
+                if self.data.ownship_display.show_accuracy {
+                    self.draw_accuracy_circle(ui, &image_response, image_size, georeference);
+                }
+                self.draw_found_fix(ui, &image_response, image_size, georeference);
+                self.draw_ownship(ui, &image_response, image_size, georeference);
+                self.request_motion_repaint(ctx, &image_response, image_size, georeference);
+                self.draw_off_chart_indicator(ui, &image_response, image_size, georeference);
+            }
+
             // Draw crosshair
             self.paint_crosshair(ui, &image_response);
             ui.response()
@@ -106,6 +254,8 @@ impl eframe::App for LivechartApp {
             // Show reset view button
             self.reset_view_button(ctx);
             self.hide_point_selection_sidebar_button(ctx, ui);
+            self.off_chart_banner(ctx, ui);
+            self.traffic_banner(ctx, ui);
         });
     }
 }
@@ -0,0 +1,125 @@
+#![warn(clippy::all, rust_2018_idioms)]
+#![deny(clippy::panic, clippy::unwrap_used)]
+
+//! Plays a track log or a synthetic path to LiveChart over the network, so every live-position
+//! feature can be tried on one machine without a receiver:
+//!
+//! ```text
+//! feed-emulator --track flight.gpx nmea-udp:127.0.0.1:10110 gdl90-udp:127.0.0.1:4000
+//! feed-emulator --waypoints circuit.txt --speed 110 --noise 5 xplane-udp:127.0.0.1:49003
+//! ```
+
+#[cfg(not(target_arch = "wasm32"))]
+const USAGE: &str = "\
+Usage: feed-emulator (--track FILE | --waypoints FILE) [OPTIONS] OUTPUT...
+
+Flight:
+  --track FILE       GPX, IGC, NMEA, GDL 90 capture or recorded session
+  --waypoints FILE   one `lat, lon, altitude in feet` per line
+  --speed KT         groundspeed along the waypoints (default 90)
+  --noise M          position noise, standard deviation in metres
+  --dropout PERCENT  share of fixes that are not sent
+  --once             stop at the end instead of starting over
+  --rate HZ          fixes per second (default 1)
+
+Outputs:
+  nmea-udp:HOST:PORT    RMC and GGA
+  nmea-tcp:PORT         RMC and GGA to every client that connects
+  gdl90-udp:HOST:PORT   heartbeat, ownship and geometric altitude
+  xplane-udp:HOST:PORT  X-Plane DATA rows 3, 17 and 20";
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() -> std::process::ExitCode {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    match run(std::env::args().skip(1).collect()) {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            std::process::ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn run(args: Vec<String>) -> Result<(), String> {
+    use livechart::emulator::{Flight, Output};
+    use livechart::simulator::{Sample, SimulatorConfig};
+
+    let mut track = None;
+    let mut simulator = SimulatorConfig::default();
+    let mut waypoints = None;
+    let mut rate_hz = 1.0f64;
+    let mut outputs = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{name} needs a value"));
+        let number = |name: &str, text: String| {
+            text.parse::<f32>()
+                .map_err(|_| format!("{name} expects a number, got {text}"))
+        };
+        match arg.as_str() {
+            "--track" => track = Some(value("--track")?),
+            "--waypoints" => waypoints = Some(value("--waypoints")?),
+            "--speed" => simulator.speed_kt = number("--speed", value("--speed")?)?,
+            "--noise" => simulator.noise_m = number("--noise", value("--noise")?)?,
+            "--dropout" => simulator.dropout_percent = number("--dropout", value("--dropout")?)?,
+            "--rate" => rate_hz = f64::from(number("--rate", value("--rate")?)?),
+            "--once" => simulator.repeat = false,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            spec => outputs.push(Output::parse(spec)?),
+        }
+    }
+
+    let mut flight = match (track, waypoints) {
+        (Some(path), None) => Flight::recorded(std::path::Path::new(&path), simulator.repeat)?,
+        (None, Some(path)) => {
+            simulator.waypoints = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Could not read {path}: {e}"))?;
+            Flight::synthetic(&simulator, 0)?
+        }
+        _ => return Err("Give either --track or --waypoints".to_owned()),
+    };
+    if outputs.is_empty() {
+        return Err("Give at least one output".to_owned());
+    }
+    if rate_hz.is_nan() || rate_hz <= 0.0 {
+        return Err("--rate has to be positive".to_owned());
+    }
+    for output in &outputs {
+        log::info!("{}", output.description);
+    }
+
+    let interval = std::time::Duration::from_secs_f64(1.0 / rate_hz);
+    let start = std::time::Instant::now();
+    let mut next = start;
+    loop {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0.0, |d| d.as_secs_f64());
+        match flight.sample(start.elapsed().as_secs_f64(), now) {
+            Sample::Fix(fix) => {
+                for output in &mut outputs {
+                    if let Err(e) = output.send(&fix) {
+                        log::warn!("{e}");
+                    }
+                }
+            }
+            Sample::Dropout => log::debug!("Dropped a fix"),
+            Sample::Finished => {
+                log::info!("Reached the end of the flight");
+                return Ok(());
+            }
+        }
+
+        next += interval;
+        std::thread::sleep(next.saturating_duration_since(std::time::Instant::now()));
+    }
+}
+
+// The emulator needs sockets, there is nothing to run on the web
+#[cfg(target_arch = "wasm32")]
+fn main() {}
@@ -1,7 +1,11 @@
 use egui::{Id, Response, ThemePreference, Ui, Vec2};
 
 use crate::app::LivechartApp;
-use crate::structs::{CoordinatePair, PixelCoordinate, ViewState};
+use crate::geo::{self, Georeference};
+use crate::ownship::{self, Trail};
+use crate::structs::{CoordinatePair, PixelCoordinate, RealCoordinate, ViewState};
+
+const OWNSHIP_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 0, 255);
 
 impl LivechartApp {
     // Paint red line:
@@ -79,6 +83,8 @@ impl LivechartApp {
 
         if pan_delta != Vec2::ZERO {
             viewstate.offset += pan_delta;
+            // Panning by hand means the user wants to look somewhere else
+            viewstate.follow_ownship = false;
         }
     }
 
@@ -125,30 +131,167 @@ impl LivechartApp {
         image_rect
     }
 
+    /// Updates the track-up rotation and moves the image so the ownship sits at the follow
+    /// anchor. Returns the adjusted image rectangle.
+    pub fn follow_ownship(
+        &mut self,
+        ui: &egui::Ui,
+        image_rect: egui::Rect,
+        image_size: (u32, u32),
+        georeference: Option<&Georeference>,
+    ) -> egui::Rect {
+        let look_ahead = self.data.ownship_display.look_ahead;
+        // A focused fleet aircraft takes the ownship's place
+        let fix = self
+            .live
+            .fleet_focus
+            .as_ref()
+            .and_then(|name| self.live.fleet.aircraft.get(name))
+            .map(|aircraft| aircraft.fix.clone())
+            .or_else(|| self.displayed_ownship());
+        let view_state = self.data.view_state.get_or_insert(ViewState::default());
+
+        let (Some(fix), Some(georeference)) = (&fix, georeference) else {
+            view_state.rotation = 0.0;
+            return image_rect;
+        };
+
+        let size = Vec2::new(image_size.0 as f32, image_size.1 as f32);
+        let pixel = georeference.real_to_pixel(&fix.position);
+        let normalized = Vec2::new(pixel.x, pixel.y) / size;
+
+        // Direction of the track on the unrotated image
+        let forward = fix.track_deg.map(|track| {
+            let ahead = georeference.real_to_pixel(&geo::destination(
+                &fix.position,
+                f64::from(track),
+                100.0,
+            ));
+            Vec2::new(ahead.x - pixel.x, ahead.y - pixel.y).normalized()
+        });
+
+        view_state.pivot = normalized;
+        view_state.rotation = match forward {
+            Some(forward) if view_state.track_up => -forward.x.atan2(-forward.y),
+            _ => 0.0,
+        };
+
+        if !view_state.follow_ownship {
+            return image_rect;
+        }
+
+        // Put the ownship behind the centre, against the direction of flight on screen
+        let available_rect = ui.max_rect();
+        let screen_forward = forward
+            .map(|forward| egui::emath::Rot2::from_angle(view_state.rotation) * forward)
+            .unwrap_or(Vec2::ZERO);
+        let anchor = available_rect.center()
+            - screen_forward * look_ahead * available_rect.size().min_elem() / 2.0;
+
+        // The pivot is the ownship, so the rotation doesn't move it
+        let ownship = image_rect.min + normalized * image_rect.size();
+        let delta = anchor - ownship;
+        view_state.offset += delta;
+        image_rect.translate(delta)
+    }
+
     pub fn display_image(&self, ui: &mut egui::Ui, rect: egui::Rect) -> egui::Response {
+        let (rotation, pivot) = self
+            .data
+            .view_state
+            .as_ref()
+            .map_or((0.0, Vec2::splat(0.5)), |v| (v.rotation, v.pivot));
+
+        let source = if self.active_chart_path() == crate::app::CHART_FILE {
+            egui::include_image!("../test_chart_vertical_ils_plate.png")
+        } else {
+            egui::ImageSource::Uri(format!("file://{}", self.active_chart_path()).into())
+        };
+
         ui.put(
             rect,
-            egui::Image::new(egui::include_image!("../test_chart_vertical_ils_plate.png"))
+            egui::Image::new(source)
+                .rotate(rotation, pivot)
                 .sense(egui::Sense::drag().union(egui::Sense::click())),
         )
     }
 
-    pub fn draw_pixel_coordinates(
+    /// Chart rotation as `(rotation, screen position of the pivot)`.
+    fn rotation(&self, image_response: &egui::Response) -> (egui::emath::Rot2, egui::Pos2) {
+        let (rotation, pivot) = self
+            .data
+            .view_state
+            .as_ref()
+            .map_or((0.0, Vec2::splat(0.5)), |v| (v.rotation, v.pivot));
+
+        (
+            egui::emath::Rot2::from_angle(rotation),
+            image_response.rect.min + pivot * image_response.rect.size(),
+        )
+    }
+
+    /// Screen position of a point on the chart image.
+    pub fn pixel_to_screen(
         &self,
         point: &PixelCoordinate,
-        ui: &egui::Ui,
         image_response: &egui::Response,
         image_size: (u32, u32),
-    ) {
+    ) -> egui::Pos2 {
         let norm_x = point.x / image_size.0 as f32;
         let norm_y = point.y / image_size.1 as f32;
 
-        let image_pos = image_response.rect.min
+        let unrotated = image_response.rect.min
             + egui::vec2(
                 norm_x * image_response.rect.width(),
                 norm_y * image_response.rect.height(),
             );
 
+        let (rotation, pivot) = self.rotation(image_response);
+        pivot + rotation * (unrotated - pivot)
+    }
+
+    /// Inverse of [`Self::pixel_to_screen`]. Not clamped to the image.
+    pub fn screen_to_pixel(
+        &self,
+        pos: egui::Pos2,
+        image_response: &egui::Response,
+        image_size: (u32, u32),
+    ) -> PixelCoordinate {
+        // Undo the track-up rotation first
+        let (rotation, pivot) = self.rotation(image_response);
+        let pos = pivot + rotation.inverse() * (pos - pivot);
+
+        let offset = pos - image_response.rect.min;
+        PixelCoordinate {
+            x: offset.x / image_response.rect.width() * image_size.0 as f32,
+            y: offset.y / image_response.rect.height() * image_size.1 as f32,
+        }
+    }
+
+    /// Screen position of a point on the ground, through the chart's georeference.
+    pub fn real_to_screen(
+        &self,
+        real: &RealCoordinate,
+        georeference: &Georeference,
+        image_response: &egui::Response,
+        image_size: (u32, u32),
+    ) -> egui::Pos2 {
+        self.pixel_to_screen(
+            &georeference.real_to_pixel(real),
+            image_response,
+            image_size,
+        )
+    }
+
+    pub fn draw_pixel_coordinates(
+        &self,
+        point: &PixelCoordinate,
+        ui: &egui::Ui,
+        image_response: &egui::Response,
+        image_size: (u32, u32),
+    ) {
+        let image_pos = self.pixel_to_screen(point, image_response, image_size);
+
         let dot_radius = 4.0;
         ui.painter()
             .circle_filled(image_pos, dot_radius, egui::Color32::BLUE);
@@ -161,6 +304,293 @@ impl LivechartApp {
                 .circle_stroke(image_pos, dot_radius * 1.5, (2.0, egui::Color32::RED));
         }
     }
+    pub fn draw_ownship(
+        &self,
+        ui: &egui::Ui,
+        image_response: &egui::Response,
+        image_size: (u32, u32),
+        georeference: &Georeference,
+    ) {
+        let Some(fix) = &self.displayed_ownship() else {
+            return;
+        };
+
+        let pos = self.real_to_screen(&fix.position, georeference, image_response, image_size);
+        let painter = ui.painter_at(image_response.rect);
+        let stroke = egui::Stroke::new(1.5, egui::Color32::BLACK);
+        let fill = OWNSHIP_COLOR;
+
+        let Some(track) = fix.track_deg else {
+            painter.circle(pos, 6.0, fill, stroke);
+            return;
+        };
+
+        // Project a point ahead of the aircraft so the symbol follows skewed or rotated charts
+        let ahead = geo::destination(&fix.position, f64::from(track), 100.0);
+        let ahead = self.real_to_screen(&ahead, georeference, image_response, image_size);
+        let forward = (ahead - pos).normalized();
+        let right = forward.rot90();
+
+        painter.add(egui::Shape::convex_polygon(
+            vec![
+                pos + forward * 12.0,
+                pos - forward * 8.0 + right * 7.0,
+                pos - forward * 4.0,
+                pos - forward * 8.0 - right * 7.0,
+            ],
+            fill,
+            stroke,
+        ));
+    }
+
+    /// Breadcrumb trail that fades out with age.
+    pub fn draw_trail(
+        &self,
+        ui: &egui::Ui,
+        image_response: &egui::Response,
+        image_size: (u32, u32),
+        georeference: &Georeference,
+    ) {
+        let painter = ui.painter_at(image_response.rect);
+        self.paint_trail(
+            &painter,
+            &self.live.trail,
+            OWNSHIP_COLOR,
+            image_response,
+            image_size,
+            georeference,
+        );
+    }
+
+    pub fn paint_trail(
+        &self,
+        painter: &egui::Painter,
+        trail: &Trail,
+        color: egui::Color32,
+        image_response: &egui::Response,
+        image_size: (u32, u32),
+        georeference: &Georeference,
+    ) {
+        let Some(latest) = trail.iter().last() else {
+            return;
+        };
+        let max_age = f64::from(self.data.ownship_display.trail_minutes) * 60.0;
+
+        let points: Vec<(f64, egui::Pos2)> = trail
+            .iter()
+            .map(|fix| {
+                (
+                    latest.time - fix.time,
+                    self.real_to_screen(&fix.position, georeference, image_response, image_size),
+                )
+            })
+            .collect();
+
+        for segment in points.windows(2) {
+            let (age, from) = segment[0];
+            let (_, to) = segment[1];
+            let opacity = (1.0 - age / max_age).clamp(0.1, 1.0) as f32;
+            painter.line_segment(
+                [from, to],
+                egui::Stroke::new(2.5, color.gamma_multiply(opacity)),
+            );
+        }
+    }
+
+    /// Predicted track for the next five minutes, with marks at 1, 2 and 5 minutes.
+    pub fn draw_predicted_track(
+        &self,
+        ui: &egui::Ui,
+        image_response: &egui::Response,
+        image_size: (u32, u32),
+        georeference: &Georeference,
+    ) {
+        let Some(fix) = &self.displayed_ownship() else {
+            return;
+        };
+        let horizon = ownship::PREDICTION_MARKS_S
+            .iter()
+            .copied()
+            .fold(0.0, f64::max);
+        let path = ownship::predict_path(fix, self.live.trail.turn_rate_deg_s(), horizon, 5.0);
+        if path.len() < 2 {
+            return;
+        }
+
+        let painter = ui.painter_at(image_response.rect);
+        let stroke = egui::Stroke::new(1.5, OWNSHIP_COLOR);
+        let screen: Vec<(f64, egui::Pos2)> = path
+            .iter()
+            .map(|(seconds, position)| {
+                (
+                    *seconds,
+                    self.real_to_screen(position, georeference, image_response, image_size),
+                )
+            })
+            .collect();
+
+        painter.add(egui::Shape::dashed_line(
+            &screen.iter().map(|(_, pos)| *pos).collect::<Vec<_>>(),
+            stroke,
+            6.0,
+            4.0,
+        ));
+
+        for window in screen.windows(2) {
+            let (seconds, pos) = window[1];
+            if !ownship::PREDICTION_MARKS_S.contains(&seconds) {
+                continue;
+            }
+
+            let across = (pos - window[0].1).normalized().rot90() * 6.0;
+            painter.line_segment([pos - across, pos + across], stroke);
+            painter.text(
+                pos + across * 1.5,
+                egui::Align2::LEFT_CENTER,
+                format!("{}", seconds / 60.0),
+                egui::FontId::proportional(11.0),
+                OWNSHIP_COLOR,
+            );
+        }
+    }
+
+    /// Arrow at the edge of the visible chart pointing at an ownship that is off the chart or
+    /// scrolled out of view, labelled with bearing and distance from the view centre.
+    pub fn draw_off_chart_indicator(
+        &self,
+        ui: &egui::Ui,
+        image_response: &egui::Response,
+        image_size: (u32, u32),
+        georeference: &Georeference,
+    ) {
+        let Some(fix) = &self.displayed_ownship() else {
+            return;
+        };
+
+        let pixel = georeference.real_to_pixel(&fix.position);
+        let on_chart = (0.0..image_size.0 as f32).contains(&pixel.x)
+            && (0.0..image_size.1 as f32).contains(&pixel.y);
+        let visible = ui.max_rect().intersect(image_response.rect).shrink(20.0);
+        let pos = self.pixel_to_screen(&pixel, image_response, image_size);
+        if (on_chart && visible.contains(pos)) || !visible.is_positive() {
+            return;
+        }
+
+        let centre = visible.center();
+        let direction = (pos - centre).normalized();
+        if !direction.is_finite() {
+            return;
+        }
+
+        // Where the ray from the centre leaves the visible area
+        let half = visible.size() / 2.0;
+        let reach = (half.x / direction.x.abs()).min(half.y / direction.y.abs());
+        let tip = centre + direction * reach;
+        let across = direction.rot90();
+
+        let painter = ui.painter_at(visible.expand(20.0));
+        painter.add(egui::Shape::convex_polygon(
+            vec![
+                tip,
+                tip - direction * 16.0 + across * 8.0,
+                tip - direction * 16.0 - across * 8.0,
+            ],
+            OWNSHIP_COLOR,
+            egui::Stroke::new(1.5, egui::Color32::BLACK),
+        ));
+
+        let from =
+            georeference.pixel_to_real(&self.screen_to_pixel(centre, image_response, image_size));
+        let label = format!(
+            "{:03.0}° {:.1} NM",
+            geo::bearing_deg(&from, &fix.position),
+            geo::distance_m(&from, &fix.position) / geo::METRES_PER_NM
+        );
+        let label_pos = tip - direction * 24.0;
+        let align = egui::Align2([
+            if direction.x > 0.3 {
+                egui::Align::Max
+            } else if direction.x < -0.3 {
+                egui::Align::Min
+            } else {
+                egui::Align::Center
+            },
+            if direction.y > 0.3 {
+                egui::Align::Max
+            } else if direction.y < -0.3 {
+                egui::Align::Min
+            } else {
+                egui::Align::Center
+            },
+        ]);
+        let galley = painter.layout_no_wrap(
+            label,
+            egui::FontId::proportional(13.0),
+            ui.visuals().strong_text_color(),
+        );
+        let label_rect = align.anchor_size(label_pos, galley.size());
+        painter.rect_filled(
+            label_rect.expand(2.0),
+            2.0,
+            ui.visuals().extreme_bg_color.gamma_multiply(0.8),
+        );
+        painter.galley(label_rect.min, galley, egui::Color32::PLACEHOLDER);
+    }
+
+    /// Offers to open the library chart that covers the ownship once it has left this one.
+    pub fn off_chart_banner(&mut self, ctx: &egui::Context, ui: &Ui) {
+        if self.data.auto_select_chart || self.ownship_on_chart() != Some(false) {
+            return;
+        }
+        let Some(position) = self.live.ownship.as_ref().map(|fix| fix.position.clone()) else {
+            return;
+        };
+        let Some(index) = self.best_chart_for(&position) else {
+            return;
+        };
+
+        egui::Area::new(Id::new("off_chart_banner"))
+            .anchor(
+                egui::Align2::CENTER_TOP,
+                egui::vec2(0.0, ui.max_rect().top() + 8.0),
+            )
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(format!(
+                            "The ownship is on {}",
+                            self.data.library[index].name
+                        ));
+                        if ui.button("Open").clicked() {
+                            self.open_chart(index);
+                        }
+                    });
+                });
+            });
+    }
+
+    pub fn ownship_display_menu(&mut self, ui: &mut egui::Ui) {
+        let display = &mut self.data.ownship_display;
+        ui.checkbox(&mut display.show_trail, "Breadcrumb trail");
+        ui.horizontal(|ui| {
+            ui.label("Trail length");
+            ui.add(
+                egui::DragValue::new(&mut display.trail_minutes)
+                    .range(0.5..=60.0)
+                    .speed(0.1)
+                    .suffix(" min"),
+            );
+        });
+        ui.checkbox(&mut display.show_prediction, "Predicted track");
+        ui.checkbox(&mut display.show_accuracy, "Accuracy circle");
+        ui.checkbox(&mut display.smooth_motion, "Smooth motion");
+        ui.horizontal(|ui| {
+            ui.label("Follow look-ahead");
+            ui.add(egui::Slider::new(&mut display.look_ahead, 0.0..=0.8).show_value(false));
+        });
+    }
+
     // TODO: review AI slop below
     pub fn add_point(
         &self,
@@ -169,11 +599,9 @@ impl LivechartApp {
     ) -> Option<PixelCoordinate> {
         if let Some(pos) = image_response.interact_pointer_pos() {
             if image_response.clicked() {
-                let offset = pos - image_response.rect.min;
-                let x = (offset.x / image_response.rect.width() * image_size.0 as f32)
-                    .clamp(0.0, image_size.0 as f32);
-                let y = (offset.y / image_response.rect.height() * image_size.1 as f32)
-                    .clamp(0.0, image_size.1 as f32);
+                let pixel = self.screen_to_pixel(pos, image_response, image_size);
+                let x = pixel.x.clamp(0.0, image_size.0 as f32);
+                let y = pixel.y.clamp(0.0, image_size.1 as f32);
 
                 if !self
                     .data
@@ -195,9 +623,16 @@ impl LivechartApp {
             .fixed_pos(egui::pos2(10.0, ctx.screen_rect().bottom() - 40.0))
             .order(egui::Order::Foreground)
             .show(ctx, |ui| {
-                if ui.button("Reset View").clicked() {
-                    self.data.view_state = None;
-                }
+                ui.horizontal(|ui| {
+                    if ui.button("Reset View").clicked() {
+                        self.data.view_state = None;
+                    }
+
+                    let view_state = self.data.view_state.get_or_insert(ViewState::default());
+                    ui.toggle_value(&mut view_state.follow_ownship, "Follow")
+                        .on_hover_text("Keep the ownship in view. Panning turns this off.");
+                    ui.toggle_value(&mut view_state.track_up, "Track up");
+                });
             });
     }
 
@@ -273,13 +708,54 @@ impl LivechartApp {
         });
     }
 
+    pub fn real_coordinate_editor(&mut self, ui: &mut egui::Ui, point: &CoordinatePair) {
+        let Some(stored) = self
+            .data
+            .points
+            .iter_mut()
+            .find(|p| p.pixels == point.pixels)
+        else {
+            return;
+        };
+
+        match &mut stored.real {
+            Some(real) => {
+                ui.label("Lat");
+                ui.add(
+                    egui::DragValue::new(&mut real.lat)
+                        .range(-90.0..=90.0)
+                        .speed(0.0001)
+                        .fixed_decimals(6),
+                );
+                ui.label("Lon");
+                ui.add(
+                    egui::DragValue::new(&mut real.lon)
+                        .range(-180.0..=180.0)
+                        .speed(0.0001)
+                        .fixed_decimals(6),
+                );
+            }
+            None => {
+                if ui.button("Set coordinate").clicked() {
+                    stored.real = Some(RealCoordinate::default());
+                }
+            }
+        }
+    }
+
     pub fn sidebar(&mut self, ctx: &egui::Context) {
         egui::SidePanel::right("sidebar")
             .default_width(ctx.screen_rect().width() * 0.2) // initial sidebar width
             .resizable(true)
             .show(ctx, |ui: &mut egui::Ui| {
                 egui::containers::scroll_area::ScrollArea::vertical().show(ui, |ui| {
+                    if !self.live.fleet.aircraft.is_empty() {
+                        self.fleet_list(ui);
+                        ui.separator();
+                    }
+
                     ui.heading("Points");
+                    self.chart_airport_editor(ui);
 
                     // Doesn't work / goofy
                     // ui.set_width_range(
@@ -306,6 +782,10 @@ impl LivechartApp {
                                 ui.horizontal_wrapped(|ui| {
                                     self.label_with_delete_button_for_single_point(ui, point);
                                 });
+                                ui.horizontal_wrapped(|ui| {
+                                    self.real_coordinate_editor(ui, point);
+                                    self.fix_lookup_editor(ui, point);
+                                });
                                 ui.separator();
                             }
                         } else {
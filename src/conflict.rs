@@ -0,0 +1,502 @@
+//! Closest point of approach between the ownship and traffic, and the alert level it implies.
+//!
+//! Both aircraft are assumed to keep their current track, groundspeed and vertical rate. That is
+//! crude for manoeuvring traffic but matches what TCAS-like displays do for advisories.
+
+use std::collections::HashMap;
+
+use crate::app::LivechartApp;
+use crate::geo;
+use crate::structs::Fix;
+use crate::traffic::{Target, TargetId};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ThreatLevel {
+    #[default]
+    None,
+    Advisory,
+    Alert,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cpa {
+    /// Seconds from now until the closest point of approach, 0 if diverging.
+    pub time_s: f64,
+    pub horizontal_m: f64,
+    /// Unknown if either altitude is missing.
+    pub vertical_ft: Option<f64>,
+    /// Target relative to the ownship now, east and north in metres.
+    relative_position_m: (f64, f64),
+    relative_velocity_mps: (f64, f64),
+    /// Height of the target above the ownship now.
+    relative_altitude_ft: Option<f64>,
+    relative_vertical_rate_fps: f64,
+}
+
+impl Cpa {
+    /// Whether the target comes inside both limits at some point in the next `within_s` seconds.
+    /// The closest point of approach alone can miss that: when it is later than `within_s`, or
+    /// when the vertical limit is only breached away from it.
+    pub fn breaches(&self, within_s: f64, horizontal_m: f64, vertical_ft: f64) -> bool {
+        let (east, north) = self.relative_position_m;
+        let (east_rate, north_rate) = self.relative_velocity_mps;
+
+        // Inside the horizontal limit while a t² + b t + c <= 0
+        let a = east_rate * east_rate + north_rate * north_rate;
+        let b = 2.0 * (east * east_rate + north * north_rate);
+        let c = east * east + north * north - horizontal_m * horizontal_m;
+        let (mut start, mut end) = if a <= f64::EPSILON {
+            if c > 0.0 {
+                return false;
+            }
+            (0.0, within_s)
+        } else {
+            let discriminant = b * b - 4.0 * a * c;
+            if discriminant < 0.0 {
+                return false;
+            }
+            let root = discriminant.sqrt();
+            ((-b - root) / (2.0 * a), (-b + root) / (2.0 * a))
+        };
+
+        // Without altitude we can't rule a conflict out
+        if let Some(altitude) = self.relative_altitude_ft {
+            let rate = self.relative_vertical_rate_fps;
+            if rate.abs() <= f64::EPSILON {
+                if altitude.abs() > vertical_ft {
+                    return false;
+                }
+            } else {
+                let first = (-vertical_ft - altitude) / rate;
+                let second = (vertical_ft - altitude) / rate;
+                start = start.max(first.min(second));
+                end = end.min(first.max(second));
+            }
+        }
+
+        start.max(0.0) <= end.min(within_s)
+    }
+}
+
+/// Separation limits for each level. A target reaches a level if it will be inside both the
+/// horizontal and vertical limits within the time limit.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct ConflictThresholds {
+    pub enabled: bool,
+    pub advisory_time_s: f32,
+    pub advisory_horizontal_nm: f32,
+    pub advisory_vertical_ft: f32,
+    pub alert_time_s: f32,
+    pub alert_horizontal_nm: f32,
+    pub alert_vertical_ft: f32,
+}
+
+impl Default for ConflictThresholds {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            advisory_time_s: 60.0,
+            advisory_horizontal_nm: 1.5,
+            advisory_vertical_ft: 1000.0,
+            alert_time_s: 30.0,
+            alert_horizontal_nm: 0.5,
+            alert_vertical_ft: 500.0,
+        }
+    }
+}
+
+impl ConflictThresholds {
+    pub fn assess(&self, cpa: &Cpa) -> ThreatLevel {
+        let within = |time_s: f32, horizontal_nm: f32, vertical_ft: f32| {
+            cpa.breaches(
+                f64::from(time_s),
+                f64::from(horizontal_nm) * geo::METRES_PER_NM,
+                f64::from(vertical_ft),
+            )
+        };
+
+        if within(
+            self.alert_time_s,
+            self.alert_horizontal_nm,
+            self.alert_vertical_ft,
+        ) {
+            ThreatLevel::Alert
+        } else if within(
+            self.advisory_time_s,
+            self.advisory_horizontal_nm,
+            self.advisory_vertical_ft,
+        ) {
+            ThreatLevel::Advisory
+        } else {
+            ThreatLevel::None
+        }
+    }
+}
+
+/// Closest point of approach of `target` relative to the ownship, looking at most `horizon_s`
+/// ahead. Aircraft without a velocity are treated as stationary.
+///
+/// Both positions are first moved on to `now`: the ownship fix is `ownship_age_s` old and the
+/// target's position may be a lot older. `relative_altitude_ft` is the target's height above the
+/// ownship at those positions.
+pub fn closest_approach(
+    ownship: &Fix,
+    ownship_age_s: f64,
+    ownship_vertical_rate_fpm: f64,
+    target: &Target,
+    relative_altitude_ft: Option<i32>,
+    now: f64,
+    horizon_s: f64,
+) -> Option<Cpa> {
+    let position = target.position.as_ref()?;
+    let target_age_s = (now - target.position_time).max(0.0);
+    let ownship_age_s = ownship_age_s.max(0.0);
+
+    let velocity = |groundspeed: Option<f32>, track: Option<f32>| match (groundspeed, track) {
+        (Some(groundspeed), Some(track)) => {
+            let speed = f64::from(groundspeed) * geo::MPS_PER_KT;
+            let track = f64::from(track).to_radians();
+            (speed * track.sin(), speed * track.cos())
+        }
+        _ => (0.0, 0.0),
+    };
+    let (own_east, own_north) = velocity(ownship.groundspeed_kt, ownship.track_deg);
+    let (target_east, target_north) = velocity(target.groundspeed_kt, target.track_deg);
+    let (relative_east, relative_north) = (target_east - own_east, target_north - own_north);
+
+    let (east, north) = geo::project(&ownship.position, position);
+    let east = east + target_east * target_age_s - own_east * ownship_age_s;
+    let north = north + target_north * target_age_s - own_north * ownship_age_s;
+
+    let closing = relative_east * relative_east + relative_north * relative_north;
+    let time_s = if closing > f64::EPSILON {
+        (-(east * relative_east + north * relative_north) / closing).clamp(0.0, horizon_s)
+    } else {
+        0.0
+    };
+
+    let horizontal_m = (east + relative_east * time_s).hypot(north + relative_north * time_s);
+
+    let target_rate_fps = f64::from(target.vertical_rate_fpm.unwrap_or(0)) / 60.0;
+    let own_rate_fps = ownship_vertical_rate_fpm / 60.0;
+    let relative_altitude_ft = relative_altitude_ft.map(|relative| {
+        f64::from(relative) + target_rate_fps * target_age_s - own_rate_fps * ownship_age_s
+    });
+    let relative_vertical_rate_fps = target_rate_fps - own_rate_fps;
+
+    Some(Cpa {
+        time_s,
+        horizontal_m,
+        vertical_ft: relative_altitude_ft
+            .map(|relative| relative + relative_vertical_rate_fps * time_s),
+        relative_position_m: (east, north),
+        relative_velocity_mps: (relative_east, relative_north),
+        relative_altitude_ft,
+        relative_vertical_rate_fps,
+    })
+}
+
+impl LivechartApp {
+    /// Recomputes the threat level of every target against the current ownship fix.
+    pub fn assess_traffic(&mut self) {
+        self.live.threats.clear();
+        let thresholds = &self.data.conflict_thresholds;
+        if !thresholds.enabled {
+            return;
+        }
+        let Some(ownship) = &self.live.ownship else {
+            return;
+        };
+
+        let horizon = f64::from(thresholds.advisory_time_s.max(thresholds.alert_time_s));
+        let vertical_rate = self.live.trail.vertical_rate_fpm();
+        let now = crate::utc::now();
+        let ownship_age = self
+            .live
+            .ownship_received
+            .map_or(0.0, |received| now - received);
+        let threats: HashMap<TargetId, (ThreatLevel, Cpa)> = self
+            .live
+            .traffic
+            .targets
+            .values()
+            .filter(|target| !target.on_ground)
+            .filter_map(|target| {
+                let relative_altitude = self.relative_altitude_ft(target);
+                let cpa = closest_approach(
+                    ownship,
+                    ownship_age,
+                    vertical_rate,
+                    target,
+                    relative_altitude,
+                    now,
+                    horizon,
+                )?;
+                // FLARM and GDL 90 devices raise their own alerts, which we never downgrade
+                let level = thresholds.assess(&cpa).max(target.alert);
+                (level != ThreatLevel::None).then_some((target.id, (level, cpa)))
+            })
+            .collect();
+        self.live.threats = threats;
+    }
+
+    pub fn threat_level(&self, id: TargetId) -> ThreatLevel {
+        self.live
+            .threats
+            .get(&id)
+            .map_or(ThreatLevel::None, |(level, _)| *level)
+    }
+
+    /// Banner for the most urgent target.
+    pub fn traffic_banner(&self, ctx: &egui::Context, ui: &egui::Ui) {
+        let Some((id, (level, cpa))) =
+            self.live
+                .threats
+                .iter()
+                .max_by(|(_, (a, a_cpa)), (_, (b, b_cpa))| {
+                    // Highest level first, then soonest
+                    a.cmp(b).then(b_cpa.time_s.total_cmp(&a_cpa.time_s))
+                })
+        else {
+            return;
+        };
+        let Some(target) = self.live.traffic.targets.get(id) else {
+            return;
+        };
+
+        let (title, fill) = match level {
+            ThreatLevel::Alert => ("TRAFFIC ALERT", egui::Color32::from_rgb(200, 0, 0)),
+            _ => ("TRAFFIC", egui::Color32::from_rgb(230, 160, 0)),
+        };
+        let mut text = format!(
+            "{title}  {}  {:.1} NM",
+            target.label(),
+            cpa.horizontal_m / geo::METRES_PER_NM
+        );
+        if let Some(vertical) = cpa.vertical_ft {
+            text += &format!(" {:+.0} ft", vertical);
+        }
+        if cpa.time_s > 0.0 {
+            text += &format!(" in {:.0} s", cpa.time_s);
+        }
+
+        egui::Area::new(egui::Id::new("traffic_banner"))
+            .anchor(
+                egui::Align2::CENTER_TOP,
+                egui::vec2(0.0, ui.max_rect().top() + 40.0),
+            )
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                egui::Frame::new()
+                    .fill(fill)
+                    .corner_radius(4.0)
+                    .inner_margin(8.0)
+                    .show(ui, |ui| {
+                        ui.label(
+                            egui::RichText::new(text)
+                                .strong()
+                                .color(egui::Color32::WHITE),
+                        );
+                    });
+            });
+    }
+
+    pub fn conflict_thresholds_menu(&mut self, ui: &mut egui::Ui) {
+        let thresholds = &mut self.data.conflict_thresholds;
+        ui.checkbox(&mut thresholds.enabled, "Traffic alerts");
+        ui.add_enabled_ui(thresholds.enabled, |ui| {
+            egui::Grid::new("conflict_thresholds").show(ui, |ui| {
+                ui.label("");
+                ui.label("Time");
+                ui.label("Horizontal");
+                ui.label("Vertical");
+                ui.end_row();
+
+                for (name, time, horizontal, vertical) in [
+                    (
+                        "Advisory",
+                        &mut thresholds.advisory_time_s,
+                        &mut thresholds.advisory_horizontal_nm,
+                        &mut thresholds.advisory_vertical_ft,
+                    ),
+                    (
+                        "Alert",
+                        &mut thresholds.alert_time_s,
+                        &mut thresholds.alert_horizontal_nm,
+                        &mut thresholds.alert_vertical_ft,
+                    ),
+                ] {
+                    ui.label(name);
+                    ui.add(egui::DragValue::new(time).range(0.0..=300.0).suffix(" s"));
+                    ui.add(
+                        egui::DragValue::new(horizontal)
+                            .range(0.1..=10.0)
+                            .speed(0.05)
+                            .suffix(" NM"),
+                    );
+                    ui.add(
+                        egui::DragValue::new(vertical)
+                            .range(100.0..=5000.0)
+                            .speed(10.0)
+                            .suffix(" ft"),
+                    );
+                    ui.end_row();
+                }
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::RealCoordinate;
+
+    const NOW: f64 = 1_714_573_805.0;
+    const NM: f64 = geo::METRES_PER_NM;
+
+    fn origin() -> RealCoordinate {
+        RealCoordinate {
+            lat: 40.0,
+            lon: -105.0,
+        }
+    }
+
+    fn ownship(groundspeed_kt: Option<f32>, track_deg: Option<f32>) -> Fix {
+        Fix {
+            time: NOW,
+            position: origin(),
+            altitude_ft: Some(6_500.0),
+            altitude_hae: false,
+            groundspeed_kt,
+            track_deg,
+            source: "test".to_owned(),
+        }
+    }
+
+    /// A target `north_nm` north of the ownship, last positioned `age_s` ago.
+    fn target(north_nm: f64, groundspeed_kt: f32, track_deg: f32, age_s: f64) -> Target {
+        Target {
+            id: 0xA1B2C3,
+            position: Some(geo::unproject(&origin(), 0.0, north_nm * NM)),
+            groundspeed_kt: Some(groundspeed_kt),
+            track_deg: Some(track_deg),
+            position_time: NOW - age_s,
+            last_seen: NOW,
+            ..Default::default()
+        }
+    }
+
+    fn cpa(own: &Fix, target: &Target, relative_altitude_ft: Option<i32>) -> Cpa {
+        closest_approach(own, 0.0, 0.0, target, relative_altitude_ft, NOW, 120.0).unwrap()
+    }
+
+    #[test]
+    fn head_on_traffic_meets_at_the_closing_time() {
+        // 240 kt closing speed covers 5 NM in 75 s
+        let cpa = cpa(
+            &ownship(Some(120.0), Some(0.0)),
+            &target(5.0, 120.0, 180.0, 0.0),
+            Some(0),
+        );
+        assert!((cpa.time_s - 75.0).abs() < 0.5);
+        assert!(cpa.horizontal_m < 10.0);
+        assert_eq!(cpa.vertical_ft, Some(0.0));
+
+        // Inside 1.5 NM after 52.5 s, but 0.5 NM only after 67.5 s
+        assert!(cpa.breaches(60.0, 1.5 * NM, 1000.0));
+        assert!(!cpa.breaches(30.0, 0.5 * NM, 500.0));
+        assert_eq!(
+            ConflictThresholds::default().assess(&cpa),
+            ThreatLevel::Advisory
+        );
+    }
+
+    #[test]
+    fn diverging_traffic_is_closest_now() {
+        let cpa = cpa(
+            &ownship(Some(120.0), Some(0.0)),
+            &target(-2.0, 120.0, 180.0, 0.0),
+            Some(0),
+        );
+        assert_eq!(cpa.time_s, 0.0);
+        assert!((cpa.horizontal_m - 2.0 * NM).abs() < 1.0);
+        assert!(!cpa.breaches(60.0, 1.5 * NM, 1000.0));
+        assert_eq!(
+            ConflictThresholds::default().assess(&cpa),
+            ThreatLevel::None
+        );
+    }
+
+    #[test]
+    fn overhead_traffic_is_separated_vertically_only() {
+        let own = ownship(None, None);
+        let mut overhead = target(0.0, 0.0, 0.0, 0.0);
+        overhead.groundspeed_kt = None;
+
+        let level = |relative_ft: i32| {
+            ConflictThresholds::default().assess(&cpa(&own, &overhead, Some(relative_ft)))
+        };
+        assert_eq!(level(1_500), ThreatLevel::None);
+        assert_eq!(level(800), ThreatLevel::Advisory);
+        assert_eq!(level(-300), ThreatLevel::Alert);
+        // Without an altitude there is nothing to rule the conflict out
+        assert_eq!(
+            ConflictThresholds::default().assess(&cpa(&own, &overhead, None)),
+            ThreatLevel::Alert
+        );
+
+        // Descending at 1200 fpm from 1500 ft above: within 1000 ft after 25 s, 500 ft after 50 s
+        overhead.vertical_rate_fpm = Some(-1200);
+        let descending = cpa(&own, &overhead, Some(1_500));
+        assert!(descending.breaches(30.0, 1.5 * NM, 1000.0));
+        assert!(!descending.breaches(45.0, 0.5 * NM, 500.0));
+        assert!(descending.breaches(55.0, 0.5 * NM, 500.0));
+    }
+
+    #[test]
+    fn breaches_past_the_time_limit_are_ignored() {
+        // Reaches 1.5 NM after 277.5 s, well past the 120 s horizon
+        let cpa = cpa(
+            &ownship(Some(120.0), Some(0.0)),
+            &target(20.0, 120.0, 180.0, 0.0),
+            Some(0),
+        );
+        assert_eq!(cpa.time_s, 120.0);
+        assert!(!cpa.breaches(60.0, 1.5 * NM, 1000.0));
+        assert!(!cpa.breaches(270.0, 1.5 * NM, 1000.0));
+        assert!(cpa.breaches(280.0, 1.5 * NM, 1000.0));
+        assert_eq!(
+            ConflictThresholds::default().assess(&cpa),
+            ThreatLevel::None
+        );
+    }
+
+    #[test]
+    fn stale_positions_are_moved_on_to_now() {
+        // 1 NM behind at 120 kt, last heard 10 s ago: a third of a mile closer by now
+        let own = ownship(None, None);
+        let stale = target(-1.0, 120.0, 0.0, 10.0);
+        let cpa = closest_approach(&own, 0.0, 0.0, &stale, Some(200), NOW, 120.0).unwrap();
+        let expected_north = -NM + 120.0 * geo::MPS_PER_KT * 10.0;
+        assert!((cpa.relative_position_m.1 - expected_north).abs() < 1.0);
+        assert!((cpa.time_s - 20.0).abs() < 0.1);
+
+        // The same for an old ownship fix, and for the vertical rates of both
+        let mut climbing = target(1.0, 0.0, 0.0, 10.0);
+        climbing.vertical_rate_fpm = Some(600);
+        let cpa = closest_approach(
+            &ownship(Some(120.0), Some(0.0)),
+            10.0,
+            -600.0,
+            &climbing,
+            Some(200),
+            NOW,
+            120.0,
+        )
+        .unwrap();
+        assert!((cpa.relative_position_m.1 - expected_north.abs()).abs() < 1.0);
+        assert_eq!(cpa.relative_altitude_ft, Some(400.0));
+    }
+}
@@ -0,0 +1,464 @@
+//! Course and glide path deviation on the final approach of the chart's procedure, shown as a
+//! CDI/VDI over the chart.
+//!
+//! The final approach course runs from the FAF to the runway threshold, the glide path rises from
+//! the threshold crossing height at the coded vertical angle. Both scales have two dots each way.
+
+use crate::app::LivechartApp;
+use crate::geo;
+use crate::navdb::{FixKind, NavDatabase};
+use crate::procedures::procedure_name;
+use crate::structs::{Fix, RealCoordinate};
+
+/// Full scale of the course needle, as on an RNP approach final.
+const LATERAL_FULL_SCALE_M: f64 = 0.3 * geo::METRES_PER_NM;
+/// Full scale of the glide path needle, as on an ILS.
+const VERTICAL_FULL_SCALE_DEG: f64 = 0.7;
+/// Further out than this the indicator is flagged.
+const MAX_DISTANCE_M: f64 = 30.0 * geo::METRES_PER_NM;
+const DEFAULT_GLIDEPATH_DEG: f64 = 3.0;
+const DEFAULT_CROSSING_HEIGHT_FT: f64 = 50.0;
+/// Dots from the centre to the end of a scale.
+const DOTS: f32 = 2.0;
+/// Magenta, as the needles of a GPS approach.
+const NEEDLE_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 0, 255);
+
+/// The straight-in final of a procedure.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FinalApproach {
+    pub runway: String,
+    pub threshold: RealCoordinate,
+    /// True course towards the threshold.
+    pub course_deg: f64,
+    pub glidepath_deg: f64,
+    pub crossing_height_ft: f64,
+    /// The airport elevation when the runway record has none, `None` without either.
+    pub threshold_elevation_ft: Option<f64>,
+}
+
+/// Where the ownship is relative to the final approach.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Deviation {
+    /// Along the course, to the threshold.
+    pub distance_m: f64,
+    /// Right of the course is positive.
+    pub cross_m: f64,
+    /// Above the glide path is positive, without an altitude there is none.
+    pub vertical_ft: Option<f64>,
+    pub vertical_deg: Option<f64>,
+}
+
+impl Deviation {
+    /// Needle position in dots, positive means the course is to the right.
+    pub fn lateral_dots(&self) -> f32 {
+        (-self.cross_m / LATERAL_FULL_SCALE_M) as f32 * DOTS
+    }
+
+    /// Needle position in dots, positive means the glide path is above.
+    pub fn vertical_dots(&self) -> Option<f32> {
+        self.vertical_deg
+            .map(|deg| (-deg / VERTICAL_FULL_SCALE_DEG) as f32 * DOTS)
+    }
+}
+
+/// The final approach of a procedure, ending at the runway threshold. `None` for circling
+/// approaches, which don't lead to a runway.
+pub fn final_approach(
+    database: &NavDatabase,
+    airport: &str,
+    procedure: &str,
+) -> Option<FinalApproach> {
+    let mut final_legs = Vec::new();
+    for leg in database
+        .legs(airport, procedure)
+        .iter()
+        .filter(|leg| leg.route_type != 'A')
+    {
+        final_legs.push(leg);
+        if leg.is_missed_approach_point() {
+            break;
+        }
+    }
+
+    let resolve = |leg: &&crate::navdb::ProcedureLeg| {
+        leg.fix
+            .as_ref()
+            .and_then(|fix| database.resolve(fix, airport))
+    };
+    let runway = final_legs
+        .iter()
+        .rev()
+        .filter_map(resolve)
+        .find(|fix| matches!(fix.kind, FixKind::Runway { .. }))?;
+    let faf = final_legs
+        .iter()
+        .filter(|leg| leg.is_final_approach_fix())
+        .find_map(resolve);
+    let glidepath_deg = final_legs
+        .iter()
+        .find_map(|leg| leg.vertical_angle_deg)
+        .map_or(DEFAULT_GLIDEPATH_DEG, |angle| f64::from(angle.abs()));
+
+    let FixKind::Runway {
+        bearing_deg,
+        bearing_true,
+        threshold_elevation_ft,
+        crossing_height_ft,
+        ..
+    } = &runway.kind
+    else {
+        return None;
+    };
+    let (variation, airport_elevation_ft) = match database.airport(airport).map(|fix| &fix.kind) {
+        Some(FixKind::Airport {
+            variation_deg,
+            elevation_ft,
+        }) => (f64::from(variation_deg.unwrap_or(0.0)), *elevation_ft),
+        _ => (0.0, None),
+    };
+    let course_deg = match faf {
+        Some(faf) => geo::bearing_deg(&faf.position, &runway.position),
+        None => {
+            let bearing = f64::from((*bearing_deg)?);
+            if *bearing_true {
+                bearing
+            } else {
+                bearing + variation
+            }
+        }
+    };
+
+    Some(FinalApproach {
+        runway: runway.ident.clone(),
+        threshold: runway.position.clone(),
+        course_deg,
+        glidepath_deg,
+        crossing_height_ft: crossing_height_ft.map_or(DEFAULT_CROSSING_HEIGHT_FT, f64::from),
+        threshold_elevation_ft: threshold_elevation_ft
+            .or(airport_elevation_ft)
+            .map(f64::from),
+    })
+}
+
+impl FinalApproach {
+    /// `None` past the threshold or too far out to be on the approach.
+    pub fn deviation(&self, fix: &Fix) -> Option<Deviation> {
+        let (east, north) = geo::project(&self.threshold, &fix.position);
+        let course = self.course_deg.to_radians();
+        let (along_east, along_north) = (course.sin(), course.cos());
+        let distance_m = -(east * along_east + north * along_north);
+        let cross_m = east * along_north - north * along_east;
+        if distance_m <= 0.0 || distance_m > MAX_DISTANCE_M {
+            return None;
+        }
+
+        let glidepath = self.glidepath_deg.to_radians();
+        // The glide path reaches the ground this far past the threshold
+        let intercept_m = self.crossing_height_ft * geo::METRES_PER_FT / glidepath.tan();
+        // Heights above the ellipsoid are off from MSL by the geoid, up to 100 m
+        let height_m = fix
+            .altitude_ft
+            .filter(|_| !fix.altitude_hae)
+            .zip(self.threshold_elevation_ft)
+            .map(|(altitude, threshold)| (f64::from(altitude) - threshold) * geo::METRES_PER_FT);
+        let on_path_m = (distance_m + intercept_m) * glidepath.tan();
+
+        Some(Deviation {
+            distance_m,
+            cross_m,
+            vertical_ft: height_m.map(|height| (height - on_path_m) / geo::METRES_PER_FT),
+            vertical_deg: height_m.map(|height| {
+                height.atan2(distance_m + intercept_m).to_degrees() - self.glidepath_deg
+            }),
+        })
+    }
+}
+
+impl LivechartApp {
+    /// CDI and VDI for the chart's procedure, in the corner of the chart view.
+    pub fn deviation_indicator(&self, ctx: &egui::Context) {
+        if !self.data.show_deviation {
+            return;
+        }
+        let Some(database) = &self.live.navdb else {
+            return;
+        };
+        let airport = self.data.chart_airport.trim().to_uppercase();
+        let Some(approach) = final_approach(database, &airport, &self.data.chart_procedure) else {
+            return;
+        };
+        let fix = self.displayed_ownship();
+        let deviation = fix.as_ref().and_then(|fix| approach.deviation(fix));
+        // Why the glide path is flagged while the course is not
+        let no_glidepath = match &fix {
+            Some(fix) if fix.altitude_ft.is_none() => Some("no altitude"),
+            Some(fix) if fix.altitude_hae => Some("altitude is above the ellipsoid, not MSL"),
+            Some(_) if approach.threshold_elevation_ft.is_none() => {
+                Some("threshold elevation unknown")
+            }
+            _ => None,
+        };
+
+        egui::Area::new(egui::Id::new("deviation_indicator"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10.0, -10.0))
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.strong(procedure_name(&self.data.chart_procedure));
+                        ui.weak(format!("{:.1}°", approach.glidepath_deg));
+                    });
+                    paint_scales(ui, deviation.as_ref());
+
+                    let Some(deviation) = deviation else {
+                        ui.weak("Not on the final approach");
+                        return;
+                    };
+                    let side = if deviation.cross_m >= 0.0 { "R" } else { "L" };
+                    let mut text = format!(
+                        "{:.1} NM to {}, {side} {:.2} NM",
+                        deviation.distance_m / geo::METRES_PER_NM,
+                        approach.runway,
+                        deviation.cross_m.abs() / geo::METRES_PER_NM
+                    );
+                    if let Some(vertical) = deviation.vertical_ft {
+                        let above = if vertical >= 0.0 { "high" } else { "low" };
+                        text += &format!(", {:.0} ft {above}", vertical.abs());
+                    }
+                    ui.label(text);
+                    if let Some(reason) = no_glidepath {
+                        ui.weak(format!("No glide path: {reason}"));
+                    }
+                });
+            });
+    }
+}
+
+/// The course needle over a horizontal scale and the glide path diamond beside a vertical one.
+fn paint_scales(ui: &mut egui::Ui, deviation: Option<&Deviation>) {
+    let dot_spacing = 22.0;
+    let (rect, _) = ui.allocate_exact_size(
+        egui::vec2(7.0 * dot_spacing, 5.0 * dot_spacing),
+        egui::Sense::hover(),
+    );
+    let painter = ui.painter_at(rect);
+    let color = ui.visuals().text_color();
+    let flagged = ui.visuals().weak_text_color();
+
+    // Course, in the middle of the left part
+    let cdi_center = egui::pos2(rect.left() + 2.75 * dot_spacing, rect.center().y);
+    for dot in -2..=2 {
+        let pos = cdi_center + egui::vec2(dot as f32 * dot_spacing, 0.0);
+        if dot == 0 {
+            painter.line_segment(
+                [pos - egui::vec2(0.0, 6.0), pos + egui::vec2(0.0, 6.0)],
+                (1.5, color),
+            );
+        } else {
+            painter.circle_stroke(pos, 3.5, (1.5, color));
+        }
+    }
+
+    // Glide path, on the right edge
+    let vdi_center = egui::pos2(rect.right() - 0.75 * dot_spacing, rect.center().y);
+    for dot in -2..=2 {
+        let pos = vdi_center + egui::vec2(0.0, dot as f32 * dot_spacing);
+        if dot == 0 {
+            painter.line_segment(
+                [pos - egui::vec2(8.0, 0.0), pos + egui::vec2(8.0, 0.0)],
+                (1.5, color),
+            );
+        } else {
+            painter.circle_stroke(pos, 3.5, (1.5, color));
+        }
+    }
+
+    let Some(deviation) = deviation else {
+        painter.text(
+            cdi_center - egui::vec2(0.0, 1.5 * dot_spacing),
+            egui::Align2::CENTER_CENTER,
+            "OFF",
+            egui::FontId::proportional(14.0),
+            flagged,
+        );
+        return;
+    };
+
+    let clamp = |dots: f32| dots.clamp(-DOTS - 0.4, DOTS + 0.4);
+    let x = cdi_center.x + clamp(deviation.lateral_dots()) * dot_spacing;
+    painter.line_segment(
+        [
+            egui::pos2(x, rect.top() + 4.0),
+            egui::pos2(x, rect.bottom() - 4.0),
+        ],
+        (3.0, NEEDLE_COLOR),
+    );
+
+    match deviation.vertical_dots() {
+        Some(dots) => {
+            let y = vdi_center.y - clamp(dots) * dot_spacing;
+            let size = 7.0;
+            painter.add(egui::Shape::convex_polygon(
+                vec![
+                    egui::pos2(vdi_center.x, y - size),
+                    egui::pos2(vdi_center.x + size, y),
+                    egui::pos2(vdi_center.x, y + size),
+                    egui::pos2(vdi_center.x - size, y),
+                ],
+                NEEDLE_COLOR,
+                egui::Stroke::NONE,
+            ));
+        }
+        None => {
+            painter.text(
+                vdi_center - egui::vec2(0.0, 1.5 * dot_spacing),
+                egui::Align2::CENTER_CENTER,
+                "GS",
+                egui::FontId::proportional(12.0),
+                flagged,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AIRPORT: &str =
+        "SUSAP KDENK2ADEN     0     160Y N39514200W104402300E008005434                                DENVER INTL                   123452510";
+    const RUNWAY: &str =
+        "SUSAP KDENK2GRW16R   0160001739 N39534370W104414770               05330000055200                                           123452510";
+    const FAF: &str =
+        "SUSAP KDENK2CKIPPR K20    C     N40023100W104370200                       E0080                   KIPPR                    123452510";
+    const FAF_LEG: &str =
+        "SUSAP KDENK2FR16RY R      030KIPPRK2PC0E  F    TF                     17390050    @ 08000             -300                 123452510";
+    const MAP_LEG: &str =
+        "SUSAP KDENK2FR16RY R      040RW16RK2PG0GY M    TF                                                                          123452510";
+
+    fn approach(records: &[&str]) -> FinalApproach {
+        let database = NavDatabase::parse(&records.join("\n"));
+        assert_eq!(database.skipped, 0);
+        final_approach(&database, "KDEN", "R16RY").unwrap()
+    }
+
+    fn kden() -> FinalApproach {
+        approach(&[AIRPORT, RUNWAY, FAF, FAF_LEG, MAP_LEG])
+    }
+
+    /// On the extended centreline `distance_nm` out, `right_m` to the right of the course and
+    /// `high_ft` above the glide path.
+    fn fix(approach: &FinalApproach, distance_nm: f64, right_m: f64, high_ft: f64) -> Fix {
+        let distance_m = distance_nm * geo::METRES_PER_NM;
+        let course = approach.course_deg.to_radians();
+        let east = -distance_m * course.sin() + right_m * course.cos();
+        let north = -distance_m * course.cos() - right_m * course.sin();
+        let on_path_ft = approach.threshold_elevation_ft.unwrap()
+            + approach.crossing_height_ft
+            + distance_m * approach.glidepath_deg.to_radians().tan() / geo::METRES_PER_FT;
+        Fix {
+            time: 0.0,
+            position: geo::unproject(&approach.threshold, east, north),
+            altitude_ft: Some((on_path_ft + high_ft) as f32),
+            altitude_hae: false,
+            groundspeed_kt: None,
+            track_deg: None,
+            source: "test".to_owned(),
+        }
+    }
+
+    #[test]
+    fn final_approach_runs_from_the_faf_to_the_threshold() {
+        let approach = kden();
+        assert_eq!(approach.runway, "RW16R");
+        assert_eq!(approach.glidepath_deg, 3.0);
+        assert_eq!(approach.crossing_height_ft, 55.0);
+        assert_eq!(approach.threshold_elevation_ft, Some(5330.0));
+        let kippr = NavDatabase::parse(FAF).fixes[0].position.clone();
+        assert_eq!(
+            approach.course_deg,
+            geo::bearing_deg(&kippr, &approach.threshold)
+        );
+    }
+
+    #[test]
+    fn threshold_elevation_falls_back_to_the_airport() {
+        let runway = RUNWAY.replace("05330", "     ");
+        let approach = approach(&[AIRPORT, &runway, FAF, FAF_LEG, MAP_LEG]);
+        assert_eq!(approach.threshold_elevation_ft, Some(5434.0));
+
+        // Without either there is no glide path, the course still works
+        let approach = FinalApproach {
+            threshold_elevation_ft: None,
+            ..kden()
+        };
+        let deviation = approach.deviation(&fix(&kden(), 5.0, 0.0, 0.0)).unwrap();
+        assert!(deviation.cross_m.abs() < 1.0);
+        assert_eq!(deviation.vertical_ft, None);
+        assert_eq!(deviation.vertical_dots(), None);
+    }
+
+    #[test]
+    fn on_course_and_on_the_glide_path() {
+        let approach = kden();
+        let deviation = approach.deviation(&fix(&approach, 5.0, 0.0, 0.0)).unwrap();
+        assert!((deviation.distance_m / geo::METRES_PER_NM - 5.0).abs() < 0.01);
+        assert!(deviation.cross_m.abs() < 1.0);
+        assert!(deviation.vertical_ft.unwrap().abs() < 1.0);
+        assert!(deviation.lateral_dots().abs() < 0.01);
+        assert!(deviation.vertical_dots().unwrap().abs() < 0.01);
+    }
+
+    #[test]
+    fn needles_point_back_to_the_course_and_glide_path() {
+        let approach = kden();
+
+        // Right of course: the course is to the left
+        let right = approach
+            .deviation(&fix(&approach, 5.0, 278.0, 0.0))
+            .unwrap();
+        assert!((right.cross_m - 278.0).abs() < 1.0);
+        assert!((right.lateral_dots() + 1.0).abs() < 0.01);
+        let left = approach
+            .deviation(&fix(&approach, 5.0, -278.0, 0.0))
+            .unwrap();
+        assert!((left.lateral_dots() - 1.0).abs() < 0.01);
+
+        // High: the glide path is below
+        let high = approach
+            .deviation(&fix(&approach, 5.0, 0.0, 300.0))
+            .unwrap();
+        assert!((high.vertical_ft.unwrap() - 300.0).abs() < 1.0);
+        assert!(high.vertical_deg.unwrap() > 0.0);
+        assert!(high.vertical_dots().unwrap() < 0.0);
+        let low = approach
+            .deviation(&fix(&approach, 5.0, 0.0, -300.0))
+            .unwrap();
+        assert!((low.vertical_ft.unwrap() + 300.0).abs() < 1.0);
+        assert!(low.vertical_dots().unwrap() > 0.0);
+    }
+
+    #[test]
+    fn flags_the_indicator_away_from_the_final() {
+        let approach = kden();
+        // Past the threshold, over the runway
+        assert_eq!(approach.deviation(&fix(&approach, -0.5, 0.0, 0.0)), None);
+        assert_eq!(approach.deviation(&fix(&approach, 31.0, 0.0, 0.0)), None);
+        assert!(approach
+            .deviation(&fix(&approach, 29.0, 0.0, 0.0))
+            .is_some());
+    }
+
+    #[test]
+    fn heights_above_the_ellipsoid_get_no_glide_path() {
+        let approach = kden();
+        let fix = Fix {
+            altitude_hae: true,
+            ..fix(&approach, 5.0, 0.0, 0.0)
+        };
+        let deviation = approach.deviation(&fix).unwrap();
+        assert!(deviation.cross_m.abs() < 1.0);
+        assert_eq!(deviation.vertical_ft, None);
+        assert_eq!(deviation.vertical_deg, None);
+    }
+}
@@ -0,0 +1,271 @@
+//! Per-feed statistics and the raw messages behind them, for finding out why a feed misbehaves.
+
+use std::collections::VecDeque;
+
+use crate::app::LivechartApp;
+use crate::feeds::FeedKind;
+use crate::utc;
+
+/// Message rates are averaged over this window.
+const RATE_WINDOW_S: f64 = 10.0;
+/// Raw messages kept per feed, in bytes. dump1090 documents can be large, so this is a byte
+/// budget rather than a message count.
+const RAW_BUDGET_BYTES: usize = 1 << 20;
+/// Weight of a new sample in the smoothed latency.
+const LATENCY_SMOOTHING: f64 = 0.1;
+
+#[derive(Debug, Clone)]
+pub enum RawData {
+    Text(String),
+    /// GDL 90 datagrams, still framed.
+    Binary(Vec<u8>),
+}
+
+impl RawData {
+    fn len(&self) -> usize {
+        match self {
+            Self::Text(text) => text.len(),
+            Self::Binary(bytes) => bytes.len(),
+        }
+    }
+
+    /// One line for the raw view.
+    pub fn display(&self) -> String {
+        match self {
+            Self::Text(text) => text.trim_end().to_owned(),
+            Self::Binary(bytes) => bytes
+                .iter()
+                .map(|b| format!("{b:02X}"))
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RawMessage {
+    /// Unix time of arrival.
+    pub time: f64,
+    pub data: RawData,
+}
+
+#[derive(Debug, Default)]
+pub struct FeedStats {
+    pub messages: u64,
+    pub parse_errors: u64,
+    pub last_error: Option<String>,
+    /// Unix time the last ownship fix arrived.
+    pub last_fix: Option<f64>,
+    /// Smoothed difference between arrival and the device's own timestamp. Includes any clock
+    /// offset between the device and this computer.
+    pub latency_s: Option<f64>,
+    arrivals: VecDeque<f64>,
+}
+
+impl FeedStats {
+    pub fn message(&mut self, time: f64) {
+        self.messages += 1;
+        self.arrivals.push_back(time);
+        while self
+            .arrivals
+            .front()
+            .is_some_and(|&arrival| time - arrival > RATE_WINDOW_S)
+        {
+            self.arrivals.pop_front();
+        }
+    }
+
+    pub fn parse_error(&mut self, error: String) {
+        self.parse_errors += 1;
+        self.last_error = Some(error);
+    }
+
+    pub fn latency(&mut self, sample: f64) {
+        self.latency_s = Some(match self.latency_s {
+            Some(latency) => latency + (sample - latency) * LATENCY_SMOOTHING,
+            None => sample,
+        });
+    }
+
+    /// Messages per second over the last few seconds.
+    pub fn rate(&self, now: f64) -> f64 {
+        let recent = self
+            .arrivals
+            .iter()
+            .filter(|&&arrival| now - arrival <= RATE_WINDOW_S)
+            .count();
+        recent as f64 / RATE_WINDOW_S
+    }
+}
+
+/// The most recent raw messages of a feed.
+#[derive(Debug, Default)]
+pub struct RawLog {
+    pub messages: VecDeque<RawMessage>,
+    bytes: usize,
+}
+
+impl RawLog {
+    pub fn push(&mut self, message: RawMessage) {
+        self.bytes += message.data.len();
+        self.messages.push_back(message);
+        while self.bytes > RAW_BUDGET_BYTES {
+            let Some(oldest) = self.messages.pop_front() else {
+                break;
+            };
+            self.bytes -= oldest.data.len();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.messages.clear();
+        self.bytes = 0;
+    }
+
+    /// The messages as a file in the feed's own format. NMEA and GDL 90 captures can be loaded as
+    /// a replay, traffic-only formats are plain text.
+    pub fn capture(&self) -> Vec<u8> {
+        let mut capture = Vec::new();
+        for message in &self.messages {
+            match &message.data {
+                RawData::Text(text) => {
+                    capture.extend(text.trim_end().as_bytes());
+                    capture.extend(b"\r\n");
+                }
+                RawData::Binary(bytes) => capture.extend(bytes),
+            }
+        }
+        capture
+    }
+}
+
+/// File extension the replay recognizes the capture by.
+fn capture_extension(kind: &FeedKind) -> &'static str {
+    match kind {
+        FeedKind::Gdl90Udp { .. } => "gdl90",
+        FeedKind::NmeaUdp { .. } | FeedKind::NmeaSerial { .. } | FeedKind::Simulator(_) => "nmea",
+        FeedKind::Sbs1Tcp { .. } => "sbs",
+        FeedKind::OgnAprs { .. } => "aprs",
+        FeedKind::Dump1090 { .. } | FeedKind::Mqtt { .. } => "txt",
+        // Packets without framing, for looking at rather than replaying
+        FeedKind::XPlaneUdp { .. } => "xplane",
+    }
+}
+
+impl LivechartApp {
+    /// Writes the raw view of a feed to the captures directory and returns the file's path.
+    fn export_capture(&self, index: usize) -> Result<std::path::PathBuf, String> {
+        let feed = &self.live.feeds[index];
+        let directory = crate::recording::sessions_dir()
+            .and_then(|sessions| Some(sessions.parent()?.join("captures")))
+            .ok_or("Captures are not supported here")?;
+        std::fs::create_dir_all(&directory)
+            .map_err(|e| format!("Could not create {}: {e}", directory.display()))?;
+
+        let now = utc::now();
+        let (year, month, day) = utc::civil_from_days((now / 86_400.0).floor() as i64);
+        let clock = utc::format_clock(now).replace(':', "");
+        let path = directory.join(format!(
+            "{}-{year:04}{month:02}{day:02}-{clock}.{}",
+            feed.config.kind.label().replace(' ', "-").to_lowercase(),
+            capture_extension(&feed.config.kind)
+        ));
+        std::fs::write(&path, feed.raw.capture())
+            .map_err(|e| format!("Could not write {}: {e}", path.display()))?;
+        Ok(path)
+    }
+
+    pub fn diagnostics_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.live.diagnostics_window_open;
+        let now = utc::now();
+
+        egui::Window::new("Diagnostics")
+            .open(&mut open)
+            .default_width(560.0)
+            .show(ctx, |ui| {
+                if self.live.feeds.is_empty() {
+                    ui.label("No feeds running.");
+                    return;
+                }
+
+                egui::Grid::new("diagnostics").striped(true).show(ui, |ui| {
+                    for heading in ["", "Source", "Rate", "Errors", "Last fix", "Latency"] {
+                        ui.strong(heading);
+                    }
+                    ui.end_row();
+
+                    for (index, feed) in self.live.feeds.iter().enumerate() {
+                        let stats = &feed.stats;
+                        ui.radio_value(&mut self.live.diagnostics_feed, index, "");
+                        ui.label(feed.config.kind.source_name())
+                            .on_hover_text(&feed.status);
+                        ui.label(format!("{:.1}/s", stats.rate(now)));
+                        let errors = ui.label(stats.parse_errors.to_string());
+                        if let Some(error) = &stats.last_error {
+                            errors.on_hover_text(error);
+                        }
+                        ui.label(
+                            stats
+                                .last_fix
+                                .map_or("–".to_owned(), |t| format!("{:.1} s", now - t)),
+                        );
+                        ui.label(
+                            stats
+                                .latency_s
+                                .map_or("–".to_owned(), |l| format!("{:.0} ms", l * 1000.0)),
+                        );
+                        ui.end_row();
+                    }
+                });
+                ui.separator();
+
+                let index = self.live.diagnostics_feed.min(self.live.feeds.len() - 1);
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.live.diagnostics_paused, "Pause scrolling");
+                    if ui.button("Clear").clicked() {
+                        self.live.feeds[index].raw.clear();
+                    }
+                    if ui.button("Export capture").clicked() {
+                        match self.export_capture(index) {
+                            Ok(path) => {
+                                self.live.diagnostics_status =
+                                    Some(format!("Saved {}", path.display()));
+                                self.live.diagnostics_capture = Some(path);
+                            }
+                            Err(e) => self.live.diagnostics_status = Some(e),
+                        }
+                    }
+                    if let Some(path) = &self.live.diagnostics_capture {
+                        if ui.button("Open in replay").clicked() {
+                            self.data.replay_path = path.display().to_string();
+                            self.live.replay_window_open = true;
+                        }
+                    }
+                });
+                if let Some(status) = &self.live.diagnostics_status {
+                    ui.weak(status);
+                }
+
+                let raw = &self.live.feeds[index].raw;
+                let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+                egui::ScrollArea::both()
+                    .max_height(300.0)
+                    .auto_shrink([false, false])
+                    .stick_to_bottom(!self.live.diagnostics_paused)
+                    .show_rows(ui, row_height, raw.messages.len(), |ui, rows| {
+                        for message in raw.messages.range(rows) {
+                            ui.monospace(format!(
+                                "{} {}",
+                                utc::format_clock(message.time),
+                                message.data.display()
+                            ));
+                        }
+                    });
+            });
+        self.live.diagnostics_window_open = open;
+        if open {
+            // Rates and ages change without new data
+            ctx.request_repaint_after(std::time::Duration::from_secs(1));
+        }
+    }
+}
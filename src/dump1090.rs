@@ -0,0 +1,136 @@
+//! dump1090's `aircraft.json`, covering both the old (`altitude`, `speed`, `vert_rate`) and the
+//! readsb/dump1090-fa (`alt_baro`, `gs`, `baro_rate`) field names.
+
+use crate::structs::RealCoordinate;
+use crate::traffic::{TrafficUpdate, NON_ICAO_FLAG};
+
+pub const DEFAULT_URL: &str = "http://127.0.0.1:8080/data/aircraft.json";
+
+/// Seconds without a position after which dump1090 still lists an aircraft, but we don't want it.
+const MAX_POSITION_AGE_S: f64 = 60.0;
+
+pub fn parse(json: &str, source: &str) -> Result<Vec<TrafficUpdate>, String> {
+    let document: serde_json::Value =
+        serde_json::from_str(json).map_err(|e| format!("Invalid aircraft.json: {e}"))?;
+    let aircraft = document
+        .get("aircraft")
+        .and_then(|a| a.as_array())
+        .ok_or("aircraft.json has no aircraft list")?;
+
+    Ok(aircraft
+        .iter()
+        .filter_map(|entry| parse_aircraft(entry, source))
+        .collect())
+}
+
+fn parse_aircraft(entry: &serde_json::Value, source: &str) -> Option<TrafficUpdate> {
+    let number = |keys: &[&str]| keys.iter().find_map(|key| entry.get(*key)?.as_f64());
+
+    // Non-ICAO addresses are prefixed with "~"
+    let hex = entry.get("hex")?.as_str()?;
+    let id = match hex.strip_prefix('~') {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()? | NON_ICAO_FLAG,
+        None => u32::from_str_radix(hex, 16).ok()?,
+    };
+
+    let position_age_s = number(&["seen_pos"]).unwrap_or(0.0);
+    let position = match (number(&["lat"]), number(&["lon"])) {
+        (Some(lat), Some(lon)) if position_age_s <= MAX_POSITION_AGE_S => {
+            Some(RealCoordinate { lat, lon })
+        }
+        _ => None,
+    };
+
+    // "alt_baro" is the string "ground" for aircraft on the ground
+    let altitude = entry.get("alt_baro").or_else(|| entry.get("altitude"));
+    let on_ground = altitude.and_then(|a| a.as_str()).map(|a| a == "ground");
+
+    Some(TrafficUpdate {
+        id,
+        callsign: entry
+            .get("flight")
+            .and_then(|f| f.as_str())
+            .map(|f| f.trim().to_owned())
+            .filter(|f| !f.is_empty()),
+        position,
+        position_age_s,
+        altitude_ft: altitude.and_then(|a| a.as_f64()).map(|a| a as i32),
+        altitude_geometric: false,
+        groundspeed_kt: number(&["gs", "speed"]).map(|s| s as f32),
+        track_deg: number(&["track"]).map(|t| t as f32),
+        vertical_rate_fpm: number(&["baro_rate", "geom_rate", "vert_rate"]).map(|r| r as i32),
+        on_ground: on_ground.or(Some(false)),
+        alert: None,
+        source: source.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Trimmed from a readsb aircraft.json, plus an old dump1090 entry
+    const AIRCRAFT_JSON: &str = r#"{
+        "now": 1714573805.2,
+        "messages": 1843211,
+        "aircraft": [
+            {"hex": "a1b2c3", "flight": "UAL1234 ", "alt_baro": 12025, "gs": 312.4,
+             "track": 163.2, "baro_rate": -1216, "lat": 39.8512, "lon": -104.6623,
+             "seen_pos": 2.7, "seen": 0.4, "rssi": -21.3},
+            {"hex": "~2a01f3", "alt_baro": "ground", "gs": 12.0, "lat": 39.8601,
+             "lon": -104.6734, "seen_pos": 0.8},
+            {"hex": "a7e3d1", "alt_baro": 35000, "lat": 40.1, "lon": -105.2, "seen_pos": 75.0},
+            {"hex": "4ca2b1", "flight": "", "altitude": 8000, "speed": 180, "vert_rate": 640},
+            {"hex": "zzzzzz", "alt_baro": 1000}
+        ]
+    }"#;
+
+    #[test]
+    fn reads_readsb_and_dump1090_field_names() {
+        let updates = parse(AIRCRAFT_JSON, "dump1090").unwrap();
+        assert_eq!(updates.len(), 4);
+
+        let airliner = &updates[0];
+        assert_eq!(airliner.id, 0xA1B2C3);
+        assert_eq!(airliner.callsign.as_deref(), Some("UAL1234"));
+        assert_eq!(airliner.altitude_ft, Some(12025));
+        assert!(!airliner.altitude_geometric);
+        assert_eq!(airliner.groundspeed_kt, Some(312.4));
+        assert_eq!(airliner.track_deg, Some(163.2));
+        assert_eq!(airliner.vertical_rate_fpm, Some(-1216));
+        assert_eq!(airliner.on_ground, Some(false));
+        assert_eq!(airliner.position_age_s, 2.7);
+        assert!(airliner.position.is_some());
+
+        let old = &updates[3];
+        assert_eq!(old.id, 0x4CA2B1);
+        assert_eq!(old.callsign, None);
+        assert_eq!(old.altitude_ft, Some(8000));
+        assert_eq!(old.groundspeed_kt, Some(180.0));
+        assert_eq!(old.vertical_rate_fpm, Some(640));
+        assert_eq!(old.position, None);
+    }
+
+    #[test]
+    fn flags_non_icao_addresses_and_ground_targets() {
+        let updates = parse(AIRCRAFT_JSON, "dump1090").unwrap();
+        let vehicle = &updates[1];
+        assert_eq!(vehicle.id, 0x2A01F3 | NON_ICAO_FLAG);
+        assert_eq!(vehicle.on_ground, Some(true));
+        assert_eq!(vehicle.altitude_ft, None);
+    }
+
+    #[test]
+    fn drops_positions_older_than_a_minute() {
+        let updates = parse(AIRCRAFT_JSON, "dump1090").unwrap();
+        assert_eq!(updates[2].id, 0xA7E3D1);
+        assert_eq!(updates[2].position, None);
+        assert_eq!(updates[2].altitude_ft, Some(35000));
+    }
+
+    #[test]
+    fn rejects_documents_without_an_aircraft_list() {
+        assert!(parse("{\"now\": 1}", "dump1090").is_err());
+        assert!(parse("<html>", "dump1090").is_err());
+    }
+}
@@ -0,0 +1,130 @@
+//! The flight and the outputs behind the `feed-emulator` binary, which plays a track log or a
+//! synthetic path as if it came from a receiver or a flight simulator.
+
+use std::path::Path;
+
+use crate::outputs::native::Transport;
+use crate::replay::TrackLog;
+use crate::simulator::{Sample, Simulator, SimulatorConfig};
+use crate::structs::Fix;
+use crate::{gdl90, nmea, xplane};
+
+const SOURCE: &str = "feed-emulator";
+
+/// What the emulator flies.
+pub enum Flight {
+    Recorded { log: TrackLog, repeat: bool },
+    Synthetic(Simulator),
+}
+
+impl Flight {
+    /// Any track log the replay can read (GPX, IGC, NMEA, GDL 90, sessions).
+    pub fn recorded(path: &Path, repeat: bool) -> Result<Self, String> {
+        let log = TrackLog::load(path)?;
+        if log.fixes.len() < 2 || log.duration() <= 0.0 {
+            return Err(format!("{} has no track to fly", path.display()));
+        }
+        Ok(Self::Recorded { log, repeat })
+    }
+
+    pub fn synthetic(config: &SimulatorConfig, seed: u64) -> Result<Self, String> {
+        Simulator::new(config, SOURCE, seed).map(Self::Synthetic)
+    }
+
+    /// The fix `elapsed_s` after the start, stamped with `time` so receivers see a live feed.
+    pub fn sample(&mut self, elapsed_s: f64, time: f64) -> Sample {
+        match self {
+            Self::Recorded { log, repeat } => {
+                let duration = log.duration();
+                let elapsed = if *repeat {
+                    elapsed_s % duration
+                } else if elapsed_s > duration {
+                    return Sample::Finished;
+                } else {
+                    elapsed_s
+                };
+
+                match log.interpolated(log.start() + elapsed) {
+                    Some(mut fix) => {
+                        fix.time = time;
+                        Sample::Fix(fix)
+                    }
+                    None => Sample::Finished,
+                }
+            }
+            Self::Synthetic(simulator) => simulator.sample(elapsed_s, time),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    /// RMC and GGA.
+    Nmea,
+    /// Heartbeat, ownship report and geometric altitude.
+    Gdl90,
+    /// `DATA` rows 3, 17 and 20.
+    XPlane,
+}
+
+/// One fix as a single datagram, or one chunk of a stream.
+pub fn encode(protocol: Protocol, fix: &Fix) -> Vec<u8> {
+    match protocol {
+        Protocol::Nmea => (nmea::format_rmc(fix) + &nmea::format_gga(fix, None)).into_bytes(),
+        Protocol::Gdl90 => {
+            let report = gdl90::Report::ownship(fix, None, "EMULATOR");
+            let mut messages = vec![
+                gdl90::encode_heartbeat(&gdl90::Heartbeat {
+                    gps_valid: true,
+                    seconds_of_day: fix.time.rem_euclid(86_400.0) as u32,
+                }),
+                gdl90::encode_report(10, &report),
+            ];
+            if let Some(altitude) = fix.altitude_ft {
+                messages.push(gdl90::encode_geometric_altitude(altitude.round() as i32));
+            }
+            messages.iter().flat_map(|m| gdl90::frame(m)).collect()
+        }
+        Protocol::XPlane => xplane::encode(fix),
+    }
+}
+
+/// Where the emulator sends to, from a command line spec like `nmea-udp:127.0.0.1:10110`.
+pub struct Output {
+    pub protocol: Protocol,
+    /// For the user, e.g. "NMEA, sending to 127.0.0.1:10110".
+    pub description: String,
+    transport: Transport,
+}
+
+impl Output {
+    /// `nmea-udp:HOST:PORT`, `nmea-tcp:PORT`, `gdl90-udp:HOST:PORT` or `xplane-udp:HOST:PORT`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (kind, address) = spec
+            .split_once(':')
+            .ok_or_else(|| format!("Expected KIND:ADDRESS, got {spec}"))?;
+        let (protocol, name, opened) = match kind {
+            "nmea-udp" => (Protocol::Nmea, "NMEA", Transport::udp(address)),
+            "gdl90-udp" => (Protocol::Gdl90, "GDL 90", Transport::udp(address)),
+            "xplane-udp" => (Protocol::XPlane, "X-Plane", Transport::udp(address)),
+            "nmea-tcp" => {
+                let port = address
+                    .parse()
+                    .map_err(|_| format!("Invalid TCP port {address}"))?;
+                (Protocol::Nmea, "NMEA", Transport::tcp(port))
+            }
+            _ => return Err(format!("Unknown output {kind}")),
+        };
+
+        let (transport, status) = opened?;
+        Ok(Self {
+            protocol,
+            description: format!("{name}, {}", status.to_lowercase()),
+            transport,
+        })
+    }
+
+    pub fn send(&mut self, fix: &Fix) -> Result<(), String> {
+        self.transport.send(&encode(self.protocol, fix))
+    }
+}
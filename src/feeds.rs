@@ -0,0 +1,1041 @@
+//! Live network feeds. Every feed runs on its own thread and hands decoded events to the UI
+//! thread through a channel.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::app::LivechartApp;
+use crate::diagnostics::{FeedStats, RawData, RawLog, RawMessage};
+use crate::fleet::FleetReport;
+use crate::geo::Georeference;
+use crate::gnss::GnssQuality;
+use crate::simulator::{self, SimulatorConfig, Waypoint};
+use crate::structs::{CoordinatePair, Fix, PixelCoordinate};
+use crate::traffic::TrafficUpdate;
+use crate::utc;
+
+/// How often blocking reads give up to check whether the feed was stopped.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const DUMP1090_INTERVAL: Duration = Duration::from_secs(1);
+const SIMULATOR_INTERVAL: Duration = Duration::from_secs(1);
+/// FLARM's data port default.
+const DEFAULT_BAUD_RATE: u32 = 19_200;
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+pub struct FeedConfig {
+    pub id: u64,
+    pub enabled: bool,
+    pub kind: FeedKind,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+pub enum FeedKind {
+    /// GDL 90 broadcast, ownship and traffic.
+    Gdl90Udp { port: u16 },
+    /// SBS-1 BaseStation text feed, traffic only.
+    Sbs1Tcp { address: String },
+    /// dump1090 `aircraft.json`, from a file or a `http://` URL.
+    Dump1090 { location: String },
+    /// NMEA 0183 broadcast, ownship from RMC/GGA and FLARM traffic.
+    NmeaUdp { port: u16 },
+    /// NMEA 0183 from a serial port, e.g. a FLARM data port.
+    NmeaSerial { path: String, baud_rate: u32 },
+    /// OGN beacons from an APRS-IS server, traffic only.
+    OgnAprs { address: String, filter: String },
+    /// Fleet positions from an MQTT broker. Topics are separated by spaces or commas and may use
+    /// wildcards.
+    Mqtt { address: String, topics: String },
+    /// X-Plane's UDP data output, ownship only.
+    XPlaneUdp { port: u16 },
+    /// Synthetic ownship flying a scripted path.
+    Simulator(SimulatorConfig),
+}
+
+impl FeedKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Gdl90Udp { .. } => "GDL 90",
+            Self::Sbs1Tcp { .. } => "SBS-1",
+            Self::Dump1090 { .. } => "dump1090",
+            Self::NmeaUdp { .. } => "NMEA UDP",
+            Self::NmeaSerial { .. } => "NMEA serial",
+            Self::OgnAprs { .. } => "OGN",
+            Self::Mqtt { .. } => "MQTT",
+            Self::XPlaneUdp { .. } => "X-Plane",
+            Self::Simulator(_) => "Simulator",
+        }
+    }
+
+    /// Short name used as the source of fixes and traffic.
+    pub fn source_name(&self) -> String {
+        match self {
+            Self::Gdl90Udp { port } => format!("GDL 90 :{port}"),
+            Self::Sbs1Tcp { address } => format!("SBS-1 {address}"),
+            Self::Dump1090 { .. } => "dump1090".to_owned(),
+            Self::NmeaUdp { port } => format!("NMEA :{port}"),
+            Self::NmeaSerial { path, .. } => format!("NMEA {path}"),
+            Self::OgnAprs { address, .. } => format!("OGN {address}"),
+            Self::Mqtt { address, .. } => format!("MQTT {address}"),
+            Self::XPlaneUdp { port } => format!("X-Plane :{port}"),
+            Self::Simulator(_) => "Simulator".to_owned(),
+        }
+    }
+}
+
+pub enum FeedEvent {
+    Ownship(Fix),
+    Traffic(TrafficUpdate),
+    Fleet(FleetReport),
+    /// Human readable connection state.
+    Status(String),
+    /// A line, datagram or document as it came in.
+    Raw(RawMessage),
+    ParseError(String),
+    /// Seconds between the device's timestamp and arrival.
+    Latency(f64),
+    Gnss(GnssQuality),
+    /// Geometric minus pressure altitude of the ownship in feet, from GDL 90 messages 10 and 11.
+    PressureOffset(f32),
+}
+
+/// A running feed, owned by the UI thread. Dropping it stops the thread.
+pub struct Feed {
+    pub config: FeedConfig,
+    pub status: String,
+    pub stats: FeedStats,
+    pub raw: RawLog,
+    receiver: Receiver<FeedEvent>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Feed {
+    pub fn start(config: FeedConfig, ctx: &egui::Context) -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let link = Link {
+                sender,
+                ctx: ctx.clone(),
+                stop: stop.clone(),
+                source: config.kind.source_name(),
+            };
+            let kind = config.kind.clone();
+            let id = config.id;
+            let spawned = std::thread::Builder::new()
+                .name(format!("feed {id}"))
+                .spawn(move || match kind {
+                    FeedKind::Gdl90Udp { port } => native::gdl90_udp(&link, port),
+                    FeedKind::Sbs1Tcp { address } => native::sbs1_tcp(&link, &address),
+                    FeedKind::Dump1090 { location } => native::dump1090(&link, &location),
+                    FeedKind::NmeaUdp { port } => native::nmea_udp(&link, port),
+                    FeedKind::NmeaSerial { path, baud_rate } => {
+                        native::nmea_serial(&link, &path, baud_rate)
+                    }
+                    FeedKind::OgnAprs { address, filter } => {
+                        native::ogn_aprs(&link, &address, &filter)
+                    }
+                    FeedKind::Mqtt { address, topics } => {
+                        native::mqtt(&link, &address, &topics, id)
+                    }
+                    FeedKind::XPlaneUdp { port } => native::xplane_udp(&link, port),
+                    FeedKind::Simulator(config) => native::simulator(&link, &config, id),
+                });
+            if let Err(e) = spawned {
+                log::error!("Could not start feed thread: {e}");
+            }
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = (sender, ctx);
+        }
+
+        Self {
+            config,
+            status: if cfg!(target_arch = "wasm32") {
+                "Network feeds are not available on the web".to_owned()
+            } else {
+                "Starting".to_owned()
+            },
+            stats: FeedStats::default(),
+            raw: RawLog::default(),
+            receiver,
+            stop,
+        }
+    }
+
+    /// Everything the feed produced since the last call. Status and diagnostics are consumed
+    /// here.
+    pub fn drain(&mut self) -> Vec<FeedEvent> {
+        let mut events = Vec::new();
+        for event in self.receiver.try_iter() {
+            match event {
+                FeedEvent::Status(status) => self.status = status,
+                FeedEvent::Raw(message) => {
+                    self.stats.message(message.time);
+                    self.raw.push(message);
+                }
+                FeedEvent::ParseError(error) => self.stats.parse_error(error),
+                FeedEvent::Latency(latency) => self.stats.latency(latency),
+                event => {
+                    if let FeedEvent::Ownship(_) = event {
+                        self.stats.last_fix = Some(utc::now());
+                    }
+                    events.push(event);
+                }
+            }
+        }
+        events
+    }
+}
+
+impl Drop for Feed {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The feed thread's end of the channel.
+struct Link {
+    sender: Sender<FeedEvent>,
+    ctx: egui::Context,
+    stop: Arc<AtomicBool>,
+    source: String,
+}
+
+impl Link {
+    fn send(&self, event: FeedEvent) {
+        // The receiver only goes away together with the stop flag
+        let _ = self.sender.send(event);
+        self.ctx.request_repaint();
+    }
+
+    fn status(&self, status: impl Into<String>) {
+        self.send(FeedEvent::Status(status.into()));
+    }
+
+    fn raw(&self, data: RawData) {
+        self.send(FeedEvent::Raw(RawMessage {
+            time: utc::now(),
+            data,
+        }));
+    }
+
+    fn parse_error(&self, error: impl Into<String>) {
+        self.send(FeedEvent::ParseError(error.into()));
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    /// Sleeps for `duration`, waking up early if the feed is stopped.
+    fn sleep(&self, duration: Duration) {
+        let deadline = web_time::Instant::now() + duration;
+        while !self.stopped() && web_time::Instant::now() < deadline {
+            std::thread::sleep(POLL_INTERVAL.min(duration));
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use std::io::{BufRead, BufReader, Read as _, Write as _};
+    use std::net::{TcpStream, ToSocketAddrs as _, UdpSocket};
+    use std::time::Duration;
+
+    use super::{
+        FeedEvent, Link, DUMP1090_INTERVAL, POLL_INTERVAL, RECONNECT_DELAY, SIMULATOR_INTERVAL,
+    };
+    use crate::diagnostics::RawData;
+    use crate::gnss::GnssQuality;
+    use crate::nmea::{self, Decoded};
+    use crate::simulator::{Sample, Simulator, SimulatorConfig};
+    use crate::structs::Fix;
+    use crate::traffic::TrafficUpdate;
+    use crate::{dump1090, fleet, gdl90, ogn, outputs, sbs1, utc, xplane};
+
+    fn is_timeout(error: &std::io::Error) -> bool {
+        matches!(
+            error.kind(),
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+        )
+    }
+
+    /// Binds the port, retrying while another program holds it.
+    fn bind_udp(link: &Link, port: u16) -> Option<UdpSocket> {
+        while !link.stopped() {
+            let bound = UdpSocket::bind(("0.0.0.0", port)).and_then(|socket| {
+                socket.set_read_timeout(Some(POLL_INTERVAL))?;
+                Ok(socket)
+            });
+            match bound {
+                Ok(socket) => {
+                    link.status(format!("Listening on UDP {port}"));
+                    return Some(socket);
+                }
+                Err(e) => {
+                    link.status(format!("Could not listen on UDP {port}: {e}"));
+                    link.sleep(RECONNECT_DELAY);
+                }
+            }
+        }
+        None
+    }
+
+    pub fn gdl90_udp(link: &Link, port: u16) {
+        let Some(socket) = bind_udp(link, port) else {
+            return;
+        };
+
+        let mut deframer = gdl90::Deframer::default();
+        let mut geometric_altitude = None;
+        // Height above the ellipsoid unless the device says otherwise
+        let mut geometric_msl = false;
+        let mut buffer = [0u8; 4096];
+
+        while !link.stopped() {
+            let length = match socket.recv_from(&mut buffer) {
+                // Our own outputs broadcasting to this port
+                Ok((_, source)) if outputs::native::is_own_datagram(&source) => continue,
+                Ok((length, _)) => length,
+                Err(e) if is_timeout(&e) => continue,
+                Err(e) => {
+                    link.status(format!("Receive failed: {e}"));
+                    link.sleep(RECONNECT_DELAY);
+                    continue;
+                }
+            };
+
+            link.raw(RawData::Binary(buffer[..length].to_vec()));
+            let errors = deframer.errors;
+            let messages = deframer.push(&buffer[..length]);
+            for _ in errors..deframer.errors {
+                link.parse_error("GDL 90 frame with a bad CRC");
+            }
+
+            for message in messages {
+                match gdl90::decode(&message) {
+                    Some(gdl90::Message::Heartbeat(heartbeat)) => {
+                        let now = utc::now();
+                        let midnight = (now / 86_400.0).floor() * 86_400.0;
+                        // The heartbeat only has the time of day, take the one closest to now
+                        let device_time = midnight + f64::from(heartbeat.seconds_of_day);
+                        let offset = (now - device_time + 43_200.0).rem_euclid(86_400.0) - 43_200.0;
+                        link.send(FeedEvent::Latency(offset));
+                    }
+                    Some(gdl90::Message::DeviceId { geometric_msl: msl }) => {
+                        geometric_msl = msl;
+                    }
+                    Some(gdl90::Message::OwnshipGeometricAltitude(altitude)) => {
+                        geometric_altitude = Some(altitude as f32);
+                    }
+                    Some(gdl90::Message::Ownship(report)) => {
+                        link.send(FeedEvent::Gnss(GnssQuality::from_gdl90(
+                            report.nic,
+                            report.nacp,
+                        )));
+                        if let (Some(geometric), Some(pressure)) =
+                            (geometric_altitude, report.altitude_ft)
+                        {
+                            link.send(FeedEvent::PressureOffset(geometric - pressure as f32));
+                        }
+                        let Some(position) = report.position else {
+                            continue;
+                        };
+                        link.send(FeedEvent::Ownship(Fix {
+                            time: utc::now(),
+                            position,
+                            altitude_ft: geometric_altitude
+                                .or(report.altitude_ft.map(|a| a as f32)),
+                            altitude_hae: geometric_altitude.is_some() && !geometric_msl,
+                            groundspeed_kt: report.groundspeed_kt.map(f32::from),
+                            track_deg: report.track_deg,
+                            source: link.source.clone(),
+                        }));
+                    }
+                    Some(gdl90::Message::Traffic(report)) => {
+                        link.send(FeedEvent::Traffic(TrafficUpdate::from_gdl90(
+                            &report,
+                            &link.source,
+                        )));
+                    }
+                    Some(_) => {}
+                    None => link.parse_error(format!(
+                        "Malformed GDL 90 message {} ({} bytes)",
+                        message.first().copied().unwrap_or_default(),
+                        message.len()
+                    )),
+                }
+            }
+        }
+    }
+
+    pub fn sbs1_tcp(link: &Link, address: &str) {
+        while !link.stopped() {
+            let stream = match connect(address) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    link.status(format!("Could not connect to {address}: {e}"));
+                    link.sleep(RECONNECT_DELAY);
+                    continue;
+                }
+            };
+            link.status(format!("Connected to {address}"));
+
+            read_lines(link, BufReader::new(stream), address, |line| {
+                match sbs1::parse(line, &link.source) {
+                    Some(update) => link.send(FeedEvent::Traffic(update)),
+                    // Other line types (STA, AIR, ...) are expected and ignored
+                    None if line.starts_with("MSG") => {
+                        link.parse_error(format!("Invalid SBS-1 message: {}", line.trim()));
+                    }
+                    None => {}
+                }
+            });
+            link.sleep(RECONNECT_DELAY);
+        }
+    }
+
+    pub fn ogn_aprs(link: &Link, address: &str, filter: &str) {
+        while !link.stopped() {
+            let mut stream = match connect(address) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    link.status(format!("Could not connect to {address}: {e}"));
+                    link.sleep(RECONNECT_DELAY);
+                    continue;
+                }
+            };
+            if let Err(e) = stream.write_all(ogn::login(filter).as_bytes()) {
+                link.status(format!("Could not log in to {address}: {e}"));
+                link.sleep(RECONNECT_DELAY);
+                continue;
+            }
+            link.status(format!("Connected to {address}"));
+
+            read_lines(link, BufReader::new(stream), address, |line| {
+                match ogn::parse(line, &link.source) {
+                    Some(update) => link.send(FeedEvent::Traffic(update)),
+                    // Receiver beacons and no-track aircraft are skipped on purpose
+                    None if !line.starts_with('#') && !line.contains('>') => {
+                        link.parse_error(format!("Invalid APRS packet: {}", line.trim()));
+                    }
+                    None => {}
+                }
+            });
+            link.sleep(RECONNECT_DELAY);
+        }
+    }
+
+    pub fn nmea_udp(link: &Link, port: u16) {
+        let Some(socket) = bind_udp(link, port) else {
+            return;
+        };
+
+        let mut decoder = nmea::Decoder::new(link.source.clone());
+        let mut buffer = [0u8; 4096];
+        while !link.stopped() {
+            let length = match socket.recv_from(&mut buffer) {
+                // Our own outputs broadcasting to this port
+                Ok((_, source)) if outputs::native::is_own_datagram(&source) => continue,
+                Ok((length, _)) => length,
+                Err(e) if is_timeout(&e) => continue,
+                Err(e) => {
+                    link.status(format!("Receive failed: {e}"));
+                    link.sleep(RECONNECT_DELAY);
+                    continue;
+                }
+            };
+
+            // A datagram usually carries a handful of complete sentences
+            for line in String::from_utf8_lossy(&buffer[..length]).lines() {
+                link.raw(RawData::Text(line.to_owned()));
+                nmea_line(link, &mut decoder, line);
+            }
+        }
+    }
+
+    pub fn xplane_udp(link: &Link, port: u16) {
+        let Some(socket) = bind_udp(link, port) else {
+            return;
+        };
+
+        let mut buffer = [0u8; 4096];
+        while !link.stopped() {
+            let length = match socket.recv_from(&mut buffer) {
+                // Our own outputs broadcasting to this port
+                Ok((_, source)) if outputs::native::is_own_datagram(&source) => continue,
+                Ok((length, _)) => length,
+                Err(e) if is_timeout(&e) => continue,
+                Err(e) => {
+                    link.status(format!("Receive failed: {e}"));
+                    link.sleep(RECONNECT_DELAY);
+                    continue;
+                }
+            };
+
+            link.raw(RawData::Binary(buffer[..length].to_vec()));
+            let Some(data) = xplane::decode(&buffer[..length]) else {
+                link.parse_error(format!("Not an X-Plane DATA packet ({length} bytes)"));
+                continue;
+            };
+            // Rows without a position (speeds only) are fine, there's just nothing to show
+            if let Some(fix) = data.to_fix(utc::now(), &link.source) {
+                link.send(FeedEvent::Ownship(fix));
+            }
+        }
+    }
+
+    pub fn nmea_serial(link: &Link, path: &str, baud_rate: u32) {
+        while !link.stopped() {
+            let port = match serialport::new(path, baud_rate)
+                .timeout(POLL_INTERVAL)
+                .open()
+            {
+                Ok(port) => port,
+                Err(e) => {
+                    link.status(format!("Could not open {path}: {e}"));
+                    link.sleep(RECONNECT_DELAY);
+                    continue;
+                }
+            };
+            link.status(format!("Reading {path} at {baud_rate} baud"));
+
+            let mut decoder = nmea::Decoder::new(link.source.clone());
+            read_lines(link, BufReader::new(port), path, |line| {
+                nmea_line(link, &mut decoder, line);
+            });
+            link.sleep(RECONNECT_DELAY);
+        }
+    }
+
+    pub fn mqtt(link: &Link, address: &str, topics: &str, feed_id: u64) {
+        use rumqttc::{Client, Event, MqttOptions, Packet, QoS, RecvTimeoutError};
+
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => match port.parse() {
+                Ok(port) => (host, port),
+                Err(_) => return link.status(format!("Invalid port in {address}")),
+            },
+            None => (address, 1883),
+        };
+        let topics: Vec<&str> = topics
+            .split([',', ' '])
+            .filter(|topic| !topic.is_empty())
+            .collect();
+
+        // Brokers drop the older of two connections with the same client id
+        let client_id = format!("livechart-{}-{feed_id}", std::process::id());
+        let mut options = MqttOptions::new(client_id, host, port);
+        options.set_keep_alive(Duration::from_secs(30));
+        let (client, mut connection) = Client::new(options, 64);
+
+        while !link.stopped() {
+            match connection.recv_timeout(POLL_INTERVAL) {
+                Ok(Ok(Event::Incoming(Packet::ConnAck(_)))) => {
+                    // Subscriptions don't survive a reconnect with a clean session
+                    for topic in &topics {
+                        if let Err(e) = client.try_subscribe(*topic, QoS::AtMostOnce) {
+                            link.status(format!("Could not subscribe to {topic}: {e}"));
+                        }
+                    }
+                    link.status(format!("Connected to {address}"));
+                }
+                Ok(Ok(Event::Incoming(Packet::Publish(publish)))) => {
+                    link.raw(RawData::Text(format!(
+                        "{} {}",
+                        publish.topic,
+                        String::from_utf8_lossy(&publish.payload)
+                    )));
+                    match fleet::parse(&publish.topic, &publish.payload, &link.source) {
+                        Ok(report) => link.send(FeedEvent::Fleet(report)),
+                        Err(e) => link.parse_error(e),
+                    }
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => {
+                    link.status(format!("Could not connect to {address}: {e}"));
+                    link.sleep(RECONNECT_DELAY);
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        let _ = client.try_disconnect();
+    }
+
+    fn nmea_line(link: &Link, decoder: &mut nmea::Decoder, line: &str) {
+        if line.trim().is_empty() {
+            return;
+        }
+        let Some(sentence) = nmea::parse(line) else {
+            return link.parse_error(format!("Invalid NMEA sentence: {}", line.trim()));
+        };
+
+        match decoder.push_sentence(sentence) {
+            Some(Decoded::Fix(fix)) => {
+                // GGA-only feeds have no date, so their times aren't comparable
+                if fix.time > 86_400.0 {
+                    link.send(FeedEvent::Latency(utc::now() - fix.time));
+                }
+                link.send(FeedEvent::Ownship(fix));
+            }
+            Some(Decoded::Traffic(update)) => link.send(FeedEvent::Traffic(update)),
+            Some(Decoded::Status(status)) => link.status(status),
+            None => {}
+        }
+        if let Some(quality) = decoder.take_quality() {
+            link.send(FeedEvent::Gnss(quality));
+        }
+    }
+
+    /// Hands complete lines to `handle` until the feed is stopped or the connection ends.
+    fn read_lines(link: &Link, mut reader: impl BufRead, name: &str, mut handle: impl FnMut(&str)) {
+        let mut line = String::new();
+        while !link.stopped() {
+            match reader.read_line(&mut line) {
+                Ok(0) => {
+                    link.status(format!("{name} closed the connection"));
+                    return;
+                }
+                Ok(_) => {
+                    link.raw(RawData::Text(line.clone()));
+                    handle(&line);
+                    line.clear();
+                }
+                // Partial lines stay in the buffer until the rest arrives
+                Err(e) if is_timeout(&e) => continue,
+                Err(e) => {
+                    link.status(format!("Connection to {name} lost: {e}"));
+                    return;
+                }
+            }
+        }
+    }
+
+    pub fn dump1090(link: &Link, location: &str) {
+        while !link.stopped() {
+            let json = if location.starts_with("http://") {
+                http_get(location)
+            } else {
+                std::fs::read_to_string(location).map_err(|e| e.to_string())
+            };
+
+            let json = match json {
+                Ok(json) => json,
+                Err(e) => {
+                    link.status(format!("Could not read {location}: {e}"));
+                    link.sleep(DUMP1090_INTERVAL);
+                    continue;
+                }
+            };
+
+            link.raw(RawData::Text(json.clone()));
+            match dump1090::parse(&json, &link.source) {
+                Ok(updates) => {
+                    link.status(format!("Polling {location}, {} aircraft", updates.len()));
+                    for update in updates {
+                        link.send(FeedEvent::Traffic(update));
+                    }
+                }
+                Err(e) => {
+                    link.status(format!("Could not read {location}: {e}"));
+                    link.parse_error(e);
+                }
+            }
+
+            link.sleep(DUMP1090_INTERVAL);
+        }
+    }
+
+    pub fn simulator(link: &Link, config: &SimulatorConfig, seed: u64) {
+        let mut simulator = match Simulator::new(config, &link.source, seed) {
+            Ok(simulator) => simulator,
+            Err(e) => return link.status(e),
+        };
+        link.status(format!(
+            "Flying {:.1} NM at {} kt",
+            simulator.length_m() / crate::geo::METRES_PER_NM,
+            config.speed_kt
+        ));
+
+        let start = utc::now();
+        while !link.stopped() {
+            let now = utc::now();
+            match simulator.sample(now - start, now) {
+                Sample::Fix(fix) => {
+                    // What a receiver would have sent, so captures replay like real ones
+                    link.raw(RawData::Text(nmea::format_rmc(&fix)));
+                    link.raw(RawData::Text(nmea::format_gga(&fix, None)));
+                    link.send(FeedEvent::Ownship(fix));
+                }
+                Sample::Dropout => {}
+                Sample::Finished => return link.status("Reached the last waypoint"),
+            }
+            link.sleep(SIMULATOR_INTERVAL);
+        }
+    }
+
+    fn connect(address: &str) -> std::io::Result<TcpStream> {
+        let socket_address = address.to_socket_addrs()?.next().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "address did not resolve")
+        })?;
+        let stream = TcpStream::connect_timeout(&socket_address, RECONNECT_DELAY)?;
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        Ok(stream)
+    }
+
+    /// Minimal HTTP/1.0 GET, enough for a local dump1090 web server.
+    pub fn http_get(url: &str) -> Result<String, String> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or("Only http:// URLs are supported")?;
+        let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+        let address = if host.contains(':') {
+            host.to_owned()
+        } else {
+            format!("{host}:80")
+        };
+
+        let mut stream = connect(&address).map_err(|e| e.to_string())?;
+        stream
+            .set_read_timeout(Some(RECONNECT_DELAY))
+            .map_err(|e| e.to_string())?;
+        write!(
+            stream,
+            "GET /{path} HTTP/1.0\r\nHost: {host}\r\nConnection: close\r\n\r\n"
+        )
+        .map_err(|e| e.to_string())?;
+
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .map_err(|e| e.to_string())?;
+
+        let (head, body) = response
+            .split_once("\r\n\r\n")
+            .ok_or("Malformed HTTP response")?;
+        let status = head.lines().next().unwrap_or_default();
+        if !status.contains(" 200 ") {
+            return Err(format!("HTTP error: {status}"));
+        }
+        Ok(body.to_owned())
+    }
+}
+
+impl LivechartApp {
+    /// Starts enabled feeds that aren't running and stops the ones that were disabled, removed or
+    /// reconfigured.
+    pub fn sync_feeds(&mut self, ctx: &egui::Context) {
+        let configs = &self.data.feeds;
+        self.live
+            .feeds
+            .retain(|feed| configs.iter().any(|c| c.enabled && *c == feed.config));
+
+        for config in configs.iter().filter(|c| c.enabled) {
+            if !self
+                .live
+                .feeds
+                .iter()
+                .any(|feed| feed.config.id == config.id)
+            {
+                self.live.feeds.push(Feed::start(config.clone(), ctx));
+            }
+        }
+    }
+
+    /// Hands everything the feeds produced to the rest of the app.
+    pub fn poll_feeds(&mut self, ctx: &egui::Context) {
+        let events: Vec<FeedEvent> = self
+            .live
+            .feeds
+            .iter_mut()
+            .flat_map(|feed| feed.drain())
+            .collect();
+
+        let now = utc::now();
+        for event in events {
+            match event {
+                FeedEvent::Ownship(fix) => {
+                    // The replay owns the ownship while it's loaded
+                    if self.live.replay.is_none() {
+                        self.ingest_fix(fix);
+                    }
+                }
+                FeedEvent::Traffic(update) => self.live.traffic.update(update, now),
+                FeedEvent::Fleet(report) => self.ingest_fleet_report(report),
+                FeedEvent::Gnss(quality) => {
+                    if self.live.replay.is_none() {
+                        self.ingest_gnss(quality);
+                    }
+                }
+                FeedEvent::PressureOffset(offset) => self.live.pressure_offset_ft = Some(offset),
+                // Consumed by `Feed::drain`
+                FeedEvent::Status(_)
+                | FeedEvent::Raw(_)
+                | FeedEvent::ParseError(_)
+                | FeedEvent::Latency(_) => {}
+            }
+        }
+
+        let display = &self.data.traffic_display;
+        self.live
+            .traffic
+            .expire(now, f64::from(display.remove_after_s));
+        self.expire_fleet(now);
+        if !self.live.traffic.targets.is_empty() || !self.live.fleet.aircraft.is_empty() {
+            // Keep ageing targets out even when no new data arrives
+            ctx.request_repaint_after(Duration::from_secs(1));
+        }
+    }
+
+    /// A circuit around the middle of the chart on screen, for new simulators. Empty if the
+    /// chart isn't georeferenced.
+    fn demo_waypoints(&mut self) -> String {
+        let (Ok((width, height)), Some(georeference)) = (
+            self.active_chart_size(),
+            Georeference::fit(&self.data.points),
+        ) else {
+            return String::new();
+        };
+
+        let corner = |x: f32, y: f32| {
+            let real = georeference.pixel_to_real(&PixelCoordinate {
+                x: x * width as f32,
+                y: y * height as f32,
+            });
+            Waypoint {
+                lat: real.lat,
+                lon: real.lon,
+                altitude_ft: Some(3000.0),
+            }
+        };
+        simulator::format_waypoints(&[
+            corner(0.25, 0.25),
+            corner(0.75, 0.25),
+            corner(0.75, 0.75),
+            corner(0.25, 0.75),
+            corner(0.25, 0.25),
+        ])
+    }
+
+    pub fn feeds_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.live.feeds_window_open;
+        let demo_waypoints = self.demo_waypoints();
+        egui::Window::new("Feeds")
+            .open(&mut open)
+            .default_width(380.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Add:");
+                    let next_id = self.data.feeds.iter().map(|c| c.id + 1).max().unwrap_or(0);
+                    let mut add = |kind| {
+                        self.data.feeds.push(FeedConfig {
+                            id: next_id,
+                            enabled: false,
+                            kind,
+                        });
+                    };
+                    if ui.button("GDL 90").clicked() {
+                        add(FeedKind::Gdl90Udp { port: 4000 });
+                    }
+                    if ui.button("SBS-1").clicked() {
+                        add(FeedKind::Sbs1Tcp {
+                            address: format!("127.0.0.1:{}", crate::sbs1::DEFAULT_PORT),
+                        });
+                    }
+                    if ui.button("dump1090").clicked() {
+                        add(FeedKind::Dump1090 {
+                            location: crate::dump1090::DEFAULT_URL.to_owned(),
+                        });
+                    }
+                    if ui.button("NMEA UDP").clicked() {
+                        add(FeedKind::NmeaUdp { port: 10110 });
+                    }
+                    if ui.button("NMEA serial").clicked() {
+                        add(FeedKind::NmeaSerial {
+                            path: String::new(),
+                            baud_rate: DEFAULT_BAUD_RATE,
+                        });
+                    }
+                    if ui.button("OGN").clicked() {
+                        add(FeedKind::OgnAprs {
+                            address: crate::ogn::DEFAULT_ADDRESS.to_owned(),
+                            filter: String::new(),
+                        });
+                    }
+                    if ui.button("MQTT").clicked() {
+                        add(FeedKind::Mqtt {
+                            address: "127.0.0.1:1883".to_owned(),
+                            topics: crate::fleet::DEFAULT_TOPICS.to_owned(),
+                        });
+                    }
+                    if ui.button("X-Plane").clicked() {
+                        add(FeedKind::XPlaneUdp {
+                            port: crate::xplane::DEFAULT_PORT,
+                        });
+                    }
+                    if ui.button("Simulator").clicked() {
+                        add(FeedKind::Simulator(SimulatorConfig {
+                            waypoints: demo_waypoints,
+                            ..Default::default()
+                        }));
+                    }
+                });
+                ui.separator();
+
+                if self.data.feeds.is_empty() {
+                    ui.label("No feeds configured.");
+                }
+
+                let points = &self.data.points;
+                let mut remove = None;
+                for (index, config) in self.data.feeds.iter_mut().enumerate() {
+                    let running = self.live.feeds.iter().find(|f| f.config.id == config.id);
+
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut config.enabled, config.kind.label());
+                        match &mut config.kind {
+                            FeedKind::Gdl90Udp { port } => {
+                                ui.label("UDP port");
+                                ui.add(egui::DragValue::new(port));
+                            }
+                            FeedKind::Sbs1Tcp { address } => {
+                                ui.text_edit_singleline(address);
+                            }
+                            FeedKind::Dump1090 { location } => {
+                                ui.text_edit_singleline(location)
+                                    .on_hover_text("File path or http:// URL of aircraft.json");
+                            }
+                            FeedKind::NmeaUdp { port } => {
+                                ui.label("UDP port");
+                                ui.add(egui::DragValue::new(port));
+                            }
+                            FeedKind::NmeaSerial { path, baud_rate } => {
+                                ui.add(
+                                    egui::TextEdit::singleline(path)
+                                        .hint_text("/dev/ttyUSB0, COM3, ...")
+                                        .desired_width(120.0),
+                                );
+                                egui::ComboBox::from_id_salt(("baud_rate", config.id))
+                                    .selected_text(baud_rate.to_string())
+                                    .show_ui(ui, |ui| {
+                                        for rate in [4800, 9600, 19_200, 38_400, 57_600, 115_200] {
+                                            ui.selectable_value(baud_rate, rate, rate.to_string());
+                                        }
+                                    });
+                            }
+                            FeedKind::OgnAprs { address, filter } => {
+                                ui.add(egui::TextEdit::singleline(address).desired_width(120.0));
+                                ui.add(
+                                    egui::TextEdit::singleline(filter)
+                                        .hint_text("r/lat/lon/km")
+                                        .desired_width(100.0),
+                                )
+                                .on_hover_text("APRS-IS server filter, e.g. r/47.26/11.35/50");
+                            }
+                            FeedKind::Mqtt { address, topics } => {
+                                ui.add(egui::TextEdit::singleline(address).desired_width(120.0));
+                                ui.add(egui::TextEdit::singleline(topics).desired_width(140.0))
+                                    .on_hover_text("Topics to subscribe to, separated by spaces");
+                            }
+                            FeedKind::XPlaneUdp { port } => {
+                                ui.label("UDP port");
+                                ui.add(egui::DragValue::new(port));
+                            }
+                            FeedKind::Simulator(simulator) => {
+                                ui.add(
+                                    egui::DragValue::new(&mut simulator.speed_kt)
+                                        .range(1.0..=600.0)
+                                        .suffix(" kt"),
+                                );
+                                ui.add(
+                                    egui::DragValue::new(&mut simulator.noise_m)
+                                        .range(0.0..=500.0)
+                                        .prefix("noise ")
+                                        .suffix(" m"),
+                                );
+                                ui.add(
+                                    egui::DragValue::new(&mut simulator.dropout_percent)
+                                        .range(0.0..=100.0)
+                                        .prefix("dropouts ")
+                                        .suffix(" %"),
+                                );
+                                ui.checkbox(&mut simulator.repeat, "Repeat");
+                            }
+                        }
+                        if ui.small_button("🗑").clicked() {
+                            remove = Some(index);
+                        }
+                    });
+
+                    if let FeedKind::Simulator(simulator) = &mut config.kind {
+                        simulator_editor(ui, simulator, points);
+                    }
+
+                    if let Some(feed) = running {
+                        ui.weak(format!(
+                            "{} · {} messages",
+                            feed.status, feed.stats.messages
+                        ));
+                    }
+                    ui.separator();
+                }
+
+                if let Some(index) = remove {
+                    self.data.feeds.remove(index);
+                }
+            });
+        self.live.feeds_window_open = open;
+    }
+}
+
+/// Waypoints of a simulator, with a shortcut to fly the final approach between two control
+/// points of the chart on screen.
+fn simulator_editor(ui: &mut egui::Ui, simulator: &mut SimulatorConfig, points: &[CoordinatePair]) {
+    ui.add(
+        egui::TextEdit::multiline(&mut simulator.waypoints)
+            .hint_text("lat, lon, altitude in feet; one waypoint per line")
+            .desired_rows(3)
+            .code_editor(),
+    );
+    if let Err(e) = simulator::parse_waypoints(&simulator.waypoints) {
+        ui.colored_label(ui.visuals().error_fg_color, e);
+    }
+
+    let georeferenced: Vec<_> = points
+        .iter()
+        .filter_map(|point| Some((&point.pixels, point.real.as_ref()?)))
+        .collect();
+    let label = |pixels: &PixelCoordinate| format!("({}, {})", pixels.x.round(), pixels.y.round());
+    ui.add_enabled_ui(georeferenced.len() >= 2, |ui| {
+        ui.menu_button("Fly final approach", |ui| {
+            for (from_pixels, from) in &georeferenced {
+                ui.menu_button(format!("From {}", label(from_pixels)), |ui| {
+                    for (to_pixels, to) in &georeferenced {
+                        if to_pixels == from_pixels {
+                            continue;
+                        }
+                        if ui.button(format!("to {}", label(to_pixels))).clicked() {
+                            simulator.waypoints = "# Altitudes assume a threshold at sea level\n"
+                                .to_owned()
+                                + &simulator::format_waypoints(&simulator::final_approach(
+                                    from, to, 0.0,
+                                ));
+                            simulator.repeat = false;
+                            ui.close_menu();
+                        }
+                    }
+                });
+            }
+        })
+        .response
+        .on_hover_text("Through one control point to another, e.g. the FAF to the threshold");
+    });
+}
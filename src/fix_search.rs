@@ -0,0 +1,236 @@
+//! Finding a fix on a dense chart: a search field in the menu bar that centres the view on the
+//! chosen fix, flashes it and shows what it is.
+
+use egui::Vec2;
+
+use crate::app::LivechartApp;
+use crate::geo::{self, Georeference};
+use crate::navdb::{FixKind, NavFix};
+use crate::structs::ViewState;
+
+/// How long the found fix flashes.
+const HIGHLIGHT_S: f64 = 3.0;
+/// Zoom the view goes to at least when jumping to a fix.
+const JUMP_ZOOM: f32 = 3.0;
+const MAX_RESULTS: usize = 10;
+const HIGHLIGHT_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 200, 0);
+
+/// The fix picked from the search.
+#[derive(Debug, Clone)]
+pub struct FoundFix {
+    pub fix: NavFix,
+    /// Unix time it was picked, for the highlight.
+    pub found_at: f64,
+    /// The view still has to be moved onto it.
+    pub jump: bool,
+}
+
+/// What we know about a fix, one line each.
+pub fn fix_details(fix: &NavFix) -> Vec<String> {
+    let mut details = vec![fix.kind_label().to_owned()];
+    if !fix.name.is_empty() {
+        details.push(fix.name.clone());
+    }
+    if let Some(airport) = &fix.airport {
+        details.push(format!("Airport {airport}"));
+    }
+    match &fix.kind {
+        FixKind::Vhf {
+            frequency_mhz,
+            class,
+        } => {
+            if let Some(frequency) = frequency_mhz {
+                details.push(format!("{frequency:.2} MHz"));
+            }
+            if !class.is_empty() {
+                details.push(format!("Class {class}"));
+            }
+        }
+        FixKind::Ndb {
+            frequency_khz: Some(frequency),
+        } => details.push(format!("{frequency:.1} kHz")),
+        FixKind::Airport {
+            elevation_ft: Some(elevation),
+            ..
+        } => details.push(format!("Elevation {elevation} ft")),
+        FixKind::Runway {
+            length_ft,
+            bearing_deg,
+            bearing_true,
+            threshold_elevation_ft,
+            ..
+        } => {
+            if let Some(length) = length_ft {
+                details.push(format!("{length} ft long"));
+            }
+            if let Some(bearing) = bearing_deg {
+                let reference = if *bearing_true { "T" } else { "M" };
+                details.push(format!("Heading {bearing:03.0}°{reference}"));
+            }
+            if let Some(elevation) = threshold_elevation_ft {
+                details.push(format!("Threshold {elevation} ft"));
+            }
+        }
+        _ => {}
+    }
+    details.push(format!("{:.6}, {:.6}", fix.position.lat, fix.position.lon));
+    details
+}
+
+impl LivechartApp {
+    /// Search field in the menu bar, listing matching fixes nearest the chart first.
+    pub fn fix_search_menu(&mut self, ui: &mut egui::Ui) {
+        let Some(database) = self.live.navdb.clone() else {
+            return;
+        };
+        let georeference = Georeference::fit(&self.data.points);
+        let size = self.active_chart_size().ok();
+        let near = self.chart_reference_position();
+
+        let menu = ui.menu_button("🔍 Find", |ui| {
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.live.fix_search)
+                    .hint_text("Fix, navaid or airport")
+                    .desired_width(160.0),
+            );
+            // Only when the menu opens, every frame would steal focus from the rest of the menu
+            if std::mem::take(&mut self.live.fix_search_focus) {
+                response.request_focus();
+            }
+
+            let found = database.search(&self.live.fix_search, near.as_ref());
+            let enter = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            let mut chosen = None;
+            for (index, (fix, distance)) in found.iter().take(MAX_RESULTS).enumerate() {
+                // Fixes off the chart can't be shown, but their details still help
+                let on_chart = match (&georeference, size) {
+                    (Some(georeference), Some(size)) => {
+                        let pixel = georeference.real_to_pixel(&fix.position);
+                        (0.0..size.0 as f32).contains(&pixel.x)
+                            && (0.0..size.1 as f32).contains(&pixel.y)
+                    }
+                    _ => false,
+                };
+                let mut text = format!("{} {}", fix.ident, fix.kind_label());
+                if let Some(distance) = distance {
+                    text += &format!(", {:.0} NM", distance / geo::METRES_PER_NM);
+                }
+                if !on_chart {
+                    text += " (off the chart)";
+                }
+                if ui.selectable_label(false, text).clicked() || (enter && index == 0) {
+                    chosen = Some(((*fix).clone(), on_chart));
+                }
+            }
+            if found.is_empty() && !self.live.fix_search.trim().is_empty() {
+                ui.weak("Nothing found");
+            }
+
+            if let Some((fix, on_chart)) = chosen {
+                if on_chart {
+                    let view_state = self.data.view_state.get_or_insert(ViewState::default());
+                    view_state.scale = view_state.scale.max(JUMP_ZOOM);
+                    // Following would pull the view straight back to the ownship
+                    view_state.follow_ownship = false;
+                }
+                self.live.found_fix = Some(FoundFix {
+                    fix,
+                    found_at: crate::utc::now(),
+                    jump: on_chart,
+                });
+                ui.close_menu();
+            }
+        });
+        if menu.response.clicked() {
+            self.live.fix_search_focus = true;
+        }
+    }
+
+    /// Moves the image so the found fix is in the middle of the view, once. Returns the adjusted
+    /// image rectangle.
+    pub fn jump_to_found_fix(
+        &mut self,
+        ui: &egui::Ui,
+        image_rect: egui::Rect,
+        image_size: (u32, u32),
+        georeference: Option<&Georeference>,
+    ) -> egui::Rect {
+        let (Some(found), Some(georeference)) = (&mut self.live.found_fix, georeference) else {
+            return image_rect;
+        };
+        if !found.jump {
+            return image_rect;
+        }
+        found.jump = false;
+
+        let size = Vec2::new(image_size.0 as f32, image_size.1 as f32);
+        let pixel = georeference.real_to_pixel(&found.fix.position);
+        let normalized = Vec2::new(pixel.x, pixel.y) / size;
+        let view_state = self.data.view_state.get_or_insert(ViewState::default());
+
+        // Where the fix is drawn, the image is rotated around the pivot
+        let pivot = image_rect.min + view_state.pivot * image_rect.size();
+        let rotation = egui::emath::Rot2::from_angle(view_state.rotation);
+        let unrotated = image_rect.min + normalized * image_rect.size();
+        let on_screen = pivot + rotation * (unrotated - pivot);
+
+        let delta = ui.max_rect().center() - on_screen;
+        view_state.offset += delta;
+        image_rect.translate(delta)
+    }
+
+    /// Rings around the found fix that fade out.
+    pub fn draw_found_fix(
+        &self,
+        ui: &egui::Ui,
+        image_response: &egui::Response,
+        image_size: (u32, u32),
+        georeference: &Georeference,
+    ) {
+        let Some(found) = &self.live.found_fix else {
+            return;
+        };
+        let age = crate::utc::now() - found.found_at;
+        if age > HIGHLIGHT_S {
+            return;
+        }
+
+        let pos = self.real_to_screen(
+            &found.fix.position,
+            georeference,
+            image_response,
+            image_size,
+        );
+        let fade = 1.0 - (age / HIGHLIGHT_S) as f32;
+        let pulse = (age * 3.0).fract() as f32;
+        let painter = ui.painter_at(image_response.rect);
+        let color = HIGHLIGHT_COLOR.gamma_multiply(fade);
+        painter.circle_stroke(pos, 10.0, egui::Stroke::new(3.0, color));
+        painter.circle_stroke(
+            pos,
+            10.0 + 20.0 * pulse,
+            egui::Stroke::new(2.0, color.gamma_multiply(1.0 - pulse)),
+        );
+        ui.ctx().request_repaint();
+    }
+
+    /// Details of the found fix until it is closed.
+    pub fn found_fix_window(&mut self, ctx: &egui::Context) {
+        let Some(found) = &self.live.found_fix else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new(format!("Fix {}", found.fix.ident))
+            .id(egui::Id::new("found_fix"))
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                for line in fix_details(&found.fix) {
+                    ui.label(line);
+                }
+            });
+        if !open {
+            self.live.found_fix = None;
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! FLARM's proprietary NMEA sentences (data port specification, `PFLAU` and `PFLAA`).
+//!
+//! FLARM reports traffic relative to its own GPS position, so turning it into absolute positions
+//! needs the ownship fix from the RMC/GGA sentences on the same port.
+
+use crate::conflict::ThreatLevel;
+use crate::geo;
+use crate::structs::Fix;
+use crate::traffic::{Target, TargetId, TrafficUpdate, NON_ICAO_FLAG};
+
+/// FLARM aircraft types that are never airborne. 0xD is a UAV, which does fly.
+const GROUND_VEHICLE_TYPE: u8 = 0xE;
+const STATIC_OBJECT_TYPE: u8 = 0xF;
+
+/// Operating status and the most relevant alarm.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pflau {
+    /// Number of devices received.
+    pub received: u8,
+    pub transmitting: bool,
+    /// 0 no fix, 1 on the ground, 2 airborne.
+    pub gps: u8,
+    pub alarm_level: u8,
+}
+
+/// One traffic target relative to the ownship.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pflaa {
+    pub alarm_level: u8,
+    pub relative_north_m: f64,
+    pub relative_east_m: f64,
+    pub relative_vertical_m: Option<f64>,
+    pub id: Option<TargetId>,
+    pub track_deg: Option<f32>,
+    pub groundspeed_ms: Option<f32>,
+    pub climb_rate_ms: Option<f32>,
+    pub aircraft_type: Option<u8>,
+}
+
+/// `fields` includes the address, e.g. `["PFLAU", "3", "1", ...]`.
+pub fn parse_pflau(fields: &[&str]) -> Option<Pflau> {
+    let field = |i: usize| fields.get(i).copied().unwrap_or("");
+    Some(Pflau {
+        received: field(1).parse().ok()?,
+        transmitting: field(2) == "1",
+        gps: field(3).parse().ok()?,
+        alarm_level: field(5).parse().unwrap_or(0),
+    })
+}
+
+pub fn parse_pflaa(fields: &[&str]) -> Option<Pflaa> {
+    let field = |i: usize| fields.get(i).copied().unwrap_or("");
+    Some(Pflaa {
+        alarm_level: field(1).parse().unwrap_or(0),
+        relative_north_m: field(2).parse().ok()?,
+        relative_east_m: field(3).parse().ok()?,
+        relative_vertical_m: field(4).parse().ok(),
+        id: target_id(field(5).parse().ok(), field(6)),
+        track_deg: field(7).parse().ok(),
+        groundspeed_ms: field(9).parse().ok(),
+        climb_rate_ms: field(10).parse().ok(),
+        aircraft_type: u8::from_str_radix(field(11), 16).ok(),
+    })
+}
+
+/// FLARM and OGN address types: 0 random, 1 ICAO, 2 FLARM, 3 OGN. Only ICAO addresses can be
+/// matched against ADS-B traffic.
+pub fn target_id(address_type: Option<u8>, address: &str) -> Option<TargetId> {
+    let address = u32::from_str_radix(address, 16).ok()?;
+    Some(match address_type {
+        Some(1) => address,
+        _ => address | NON_ICAO_FLAG,
+    })
+}
+
+/// FLARM alarm levels count down the time to impact: 1 is 13-18 s, 2 is 9-12 s, 3 is 0-8 s.
+pub fn threat_level(alarm_level: u8) -> ThreatLevel {
+    match alarm_level {
+        0 => ThreatLevel::None,
+        1 => ThreatLevel::Advisory,
+        _ => ThreatLevel::Alert,
+    }
+}
+
+/// FLARM's inverse of [`threat_level`].
+pub fn alarm_level(level: ThreatLevel) -> u8 {
+    match level {
+        ThreatLevel::None => 0,
+        ThreatLevel::Advisory => 1,
+        ThreatLevel::Alert => 2,
+    }
+}
+
+impl Pflau {
+    pub fn status(&self) -> String {
+        let gps = match self.gps {
+            0 => "no GPS",
+            1 => "on ground",
+            _ => "airborne",
+        };
+        format!("FLARM {gps}, {} aircraft received", self.received)
+    }
+
+    pub fn format(&self) -> String {
+        // Bearing, alarm type, vertical and distance of the alarm target are left out
+        crate::nmea::sentence(&format!(
+            "PFLAU,{},{},{},1,{},,0,,",
+            self.received,
+            u8::from(self.transmitting),
+            self.gps,
+            self.alarm_level
+        ))
+    }
+}
+
+impl Pflaa {
+    /// Relative position of `target` as seen from the ownship. `None` for targets without a
+    /// position. `relative_altitude_ft` is the target's height above the ownship, with both
+    /// altitudes on the same datum.
+    pub fn relative_to(
+        ownship: &Fix,
+        target: &Target,
+        relative_altitude_ft: Option<i32>,
+        alarm_level: u8,
+    ) -> Option<Self> {
+        let (east, north) = geo::project(&ownship.position, target.position.as_ref()?);
+        Some(Self {
+            alarm_level,
+            relative_north_m: north,
+            relative_east_m: east,
+            relative_vertical_m: relative_altitude_ft
+                .map(|relative| f64::from(relative) * geo::METRES_PER_FT),
+            id: Some(target.id),
+            track_deg: target.track_deg,
+            groundspeed_ms: target
+                .groundspeed_kt
+                .map(|speed| (f64::from(speed) * geo::MPS_PER_KT) as f32),
+            climb_rate_ms: target
+                .vertical_rate_fpm
+                .map(|rate| (f64::from(rate) * geo::METRES_PER_FT / 60.0) as f32),
+            // There is no type for an aircraft on the ground, receivers don't alarm on objects
+            aircraft_type: Some(if target.on_ground {
+                STATIC_OBJECT_TYPE
+            } else {
+                0
+            }),
+        })
+    }
+
+    pub fn format(&self) -> String {
+        let optional = |value: Option<f64>, decimals: usize| {
+            value.map_or(String::new(), |v| format!("{v:.decimals$}"))
+        };
+        let (id_type, id) = match self.id {
+            Some(id) if id & NON_ICAO_FLAG != 0 => (2, id & (NON_ICAO_FLAG - 1)),
+            Some(id) => (1, id),
+            None => (0, 0),
+        };
+        crate::nmea::sentence(&format!(
+            "PFLAA,{},{:.0},{:.0},{},{id_type},{id:06X},{},,{},{},{:X}",
+            self.alarm_level,
+            self.relative_north_m,
+            self.relative_east_m,
+            optional(self.relative_vertical_m, 0),
+            optional(self.track_deg.map(f64::from), 0),
+            optional(self.groundspeed_ms.map(f64::from), 0),
+            optional(self.climb_rate_ms.map(f64::from), 1),
+            self.aircraft_type.unwrap_or(0),
+        ))
+    }
+
+    /// Absolute position and altitude, using the ownship fix FLARM measured against.
+    pub fn to_update(&self, ownship: &Fix, source: &str) -> Option<TrafficUpdate> {
+        // Targets without an id (privacy or no-track mode) can't be followed between sentences
+        let id = self.id?;
+        let position = geo::unproject(
+            &ownship.position,
+            self.relative_east_m,
+            self.relative_north_m,
+        );
+
+        Some(TrafficUpdate {
+            id,
+            callsign: None,
+            position: Some(position),
+            position_age_s: 0.0,
+            altitude_ft: ownship
+                .altitude_ft
+                .zip(self.relative_vertical_m)
+                .map(|(own, relative)| (f64::from(own) + relative / geo::METRES_PER_FT) as i32),
+            altitude_geometric: true,
+            groundspeed_kt: self
+                .groundspeed_ms
+                .map(|speed| (f64::from(speed) / geo::MPS_PER_KT) as f32),
+            track_deg: self.track_deg,
+            vertical_rate_fpm: self
+                .climb_rate_ms
+                .map(|rate| (f64::from(rate) / geo::METRES_PER_FT * 60.0) as i32),
+            // Ground vehicles and obstacles are on the ground, everything else is assumed to fly
+            on_ground: Some(matches!(
+                self.aircraft_type,
+                Some(GROUND_VEHICLE_TYPE | STATIC_OBJECT_TYPE)
+            )),
+            alert: Some(threat_level(self.alarm_level)),
+            source: source.to_owned(),
+        })
+    }
+}
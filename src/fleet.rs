@@ -0,0 +1,388 @@
+//! Our own aircraft reporting their positions over MQTT, each with its own colour, label and
+//! trail.
+//!
+//! Every message is one JSON object for one aircraft, e.g.
+//! `{"id": "D-EABC", "callsign": "Echo 1", "lat": 47.26, "lon": 11.35, "alt": 3500, "gs": 95,
+//! "track": 260}`. Altitude is in feet, speed in knots. Without an `id` the last topic level
+//! names the aircraft.
+
+use std::collections::BTreeMap;
+
+use crate::app::LivechartApp;
+use crate::geo::Georeference;
+use crate::ownship::Trail;
+use crate::structs::{Fix, RealCoordinate};
+use crate::traffic::draw_target_symbol;
+use crate::utc;
+
+pub const DEFAULT_TOPICS: &str = "livechart/fleet/#";
+
+/// Aircraft without a report for this long are drawn faded.
+const STALE_AFTER_S: f64 = 30.0;
+/// Aircraft without a report for this long are removed.
+const REMOVE_AFTER_S: f64 = 600.0;
+/// Colours remembered for aircraft that may come back. Past this, the colours of aircraft not
+/// currently reporting are forgotten.
+const MAX_COLORS: usize = 64;
+
+/// Handed out in order to aircraft without a colour of their own.
+const PALETTE: [egui::Color32; 8] = [
+    egui::Color32::from_rgb(230, 25, 75),
+    egui::Color32::from_rgb(60, 180, 75),
+    egui::Color32::from_rgb(0, 130, 200),
+    egui::Color32::from_rgb(245, 130, 48),
+    egui::Color32::from_rgb(145, 30, 180),
+    egui::Color32::from_rgb(70, 240, 240),
+    egui::Color32::from_rgb(240, 50, 230),
+    egui::Color32::from_rgb(128, 128, 0),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FleetReport {
+    pub name: String,
+    pub label: Option<String>,
+    pub fix: Fix,
+}
+
+pub fn parse(topic: &str, payload: &[u8], source: &str) -> Result<FleetReport, String> {
+    let object: serde_json::Value =
+        serde_json::from_slice(payload).map_err(|e| format!("Invalid JSON on {topic}: {e}"))?;
+    let number = |keys: &[&str]| keys.iter().find_map(|key| object.get(*key)?.as_f64());
+    let text = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|key| object.get(*key)?.as_str())
+            .map(str::to_owned)
+    };
+
+    let name = text(&["id", "name", "registration"])
+        .or_else(|| topic.rsplit('/').next().map(str::to_owned))
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| format!("No aircraft id on {topic}"))?;
+
+    let (Some(lat), Some(lon)) = (
+        number(&["lat", "latitude"]),
+        number(&["lon", "lng", "longitude"]),
+    ) else {
+        return Err(format!("No position for {name}"));
+    };
+
+    // Unix seconds or an ISO 8601 string, the time of arrival otherwise
+    let time = number(&["time", "timestamp"])
+        .or_else(|| text(&["time", "timestamp"]).and_then(|t| utc::parse_iso8601(&t)))
+        .unwrap_or_else(utc::now);
+
+    Ok(FleetReport {
+        label: text(&["callsign", "label"]),
+        fix: Fix {
+            time,
+            position: RealCoordinate { lat, lon },
+            altitude_ft: number(&["alt", "altitude"]).map(|a| a as f32),
+            altitude_hae: false,
+            groundspeed_kt: number(&["gs", "speed", "groundspeed"]).map(|s| s as f32),
+            track_deg: number(&["track", "course", "heading"]).map(|t| t as f32),
+            source: source.to_owned(),
+        },
+        name,
+    })
+}
+
+#[derive(Debug)]
+pub struct FleetAircraft {
+    pub label: Option<String>,
+    pub fix: Fix,
+    pub trail: Trail,
+    /// Unix time the last report arrived.
+    pub last_seen: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct Fleet {
+    /// By name, so the sidebar list keeps its order.
+    pub aircraft: BTreeMap<String, FleetAircraft>,
+}
+
+/// How the fleet is shown, and the colour picked for each aircraft.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct FleetDisplay {
+    pub show: bool,
+    pub show_trails: bool,
+    pub colors: BTreeMap<String, egui::Color32>,
+}
+
+impl Default for FleetDisplay {
+    fn default() -> Self {
+        Self {
+            show: true,
+            show_trails: true,
+            colors: BTreeMap::new(),
+        }
+    }
+}
+
+impl LivechartApp {
+    pub fn ingest_fleet_report(&mut self, report: FleetReport) {
+        let now = utc::now();
+        let max_age = f64::from(self.data.ownship_display.trail_minutes) * 60.0;
+
+        let colors = &mut self.data.fleet_display.colors;
+        if !colors.contains_key(&report.name) {
+            if colors.len() >= MAX_COLORS {
+                let fleet = &self.live.fleet.aircraft;
+                colors.retain(|name, _| fleet.contains_key(name));
+            }
+            colors.insert(report.name.clone(), PALETTE[colors.len() % PALETTE.len()]);
+        }
+
+        let aircraft = self
+            .live
+            .fleet
+            .aircraft
+            .entry(report.name)
+            .or_insert_with(|| FleetAircraft {
+                label: None,
+                fix: report.fix.clone(),
+                trail: Trail::default(),
+                last_seen: now,
+            });
+        if report.label.is_some() {
+            aircraft.label = report.label;
+        }
+        aircraft.trail.push(report.fix.clone(), max_age);
+        aircraft.fix = report.fix;
+        aircraft.last_seen = now;
+    }
+
+    pub fn expire_fleet(&mut self, now: f64) {
+        self.live
+            .fleet
+            .aircraft
+            .retain(|_, aircraft| now - aircraft.last_seen <= REMOVE_AFTER_S);
+        let fleet = &self.live.fleet;
+        if self
+            .live
+            .fleet_focus
+            .as_ref()
+            .is_some_and(|name| !fleet.aircraft.contains_key(name))
+        {
+            self.live.fleet_focus = None;
+        }
+    }
+
+    fn fleet_color(&self, name: &str) -> egui::Color32 {
+        self.data
+            .fleet_display
+            .colors
+            .get(name)
+            .copied()
+            .unwrap_or(PALETTE[0])
+    }
+
+    pub fn draw_fleet(
+        &self,
+        ui: &egui::Ui,
+        image_response: &egui::Response,
+        image_size: (u32, u32),
+        georeference: &Georeference,
+    ) {
+        let now = utc::now();
+        let painter = ui.painter_at(image_response.rect);
+
+        for (name, aircraft) in &self.live.fleet.aircraft {
+            let mut color = self.fleet_color(name);
+            if now - aircraft.last_seen > STALE_AFTER_S {
+                color = color.gamma_multiply(0.4);
+            }
+
+            if self.data.fleet_display.show_trails {
+                self.paint_trail(
+                    &painter,
+                    &aircraft.trail,
+                    color,
+                    image_response,
+                    image_size,
+                    georeference,
+                );
+            }
+
+            let fix = &aircraft.fix;
+            let pos = self.real_to_screen(&fix.position, georeference, image_response, image_size);
+            let direction = fix.track_deg.map(|track| {
+                let ahead = crate::geo::destination(&fix.position, f64::from(track), 100.0);
+                let ahead = self.real_to_screen(&ahead, georeference, image_response, image_size);
+                (ahead - pos).normalized()
+            });
+            draw_target_symbol(&painter, pos, direction, color);
+            if self.live.fleet_focus.as_ref() == Some(name) {
+                painter.circle_stroke(pos, 13.0, egui::Stroke::new(2.0, color));
+            }
+
+            painter.text(
+                pos + egui::vec2(0.0, -12.0),
+                egui::Align2::CENTER_BOTTOM,
+                aircraft.label.as_deref().unwrap_or(name),
+                egui::FontId::proportional(12.0),
+                color,
+            );
+            if let Some(altitude) = fix.altitude_ft {
+                painter.text(
+                    pos + egui::vec2(0.0, 12.0),
+                    egui::Align2::CENTER_TOP,
+                    format!("{altitude:.0} ft"),
+                    egui::FontId::monospace(11.0),
+                    color,
+                );
+            }
+        }
+    }
+
+    /// Sidebar list of the fleet. Clicking an aircraft follows it instead of the ownship.
+    pub fn fleet_list(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Fleet");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.data.fleet_display.show, "Show");
+            ui.checkbox(&mut self.data.fleet_display.show_trails, "Trails");
+        });
+
+        let now = utc::now();
+        let mut focus = None;
+        if ui
+            .selectable_label(self.live.fleet_focus.is_none(), "Ownship")
+            .clicked()
+        {
+            focus = Some(None);
+        }
+
+        for (name, aircraft) in &self.live.fleet.aircraft {
+            ui.horizontal(|ui| {
+                if let Some(color) = self.data.fleet_display.colors.get_mut(name) {
+                    ui.color_edit_button_srgba(color);
+                }
+
+                let focused = self.live.fleet_focus.as_ref() == Some(name);
+                let label = match &aircraft.label {
+                    Some(label) => format!("{label} ({name})"),
+                    None => name.clone(),
+                };
+                if ui.selectable_label(focused, label).clicked() {
+                    focus = Some(Some(name.clone()));
+                }
+
+                let fix = &aircraft.fix;
+                let mut details = Vec::new();
+                if let Some(altitude) = fix.altitude_ft {
+                    details.push(format!("{altitude:.0} ft"));
+                }
+                if let Some(speed) = fix.groundspeed_kt {
+                    details.push(format!("{speed:.0} kt"));
+                }
+                details.push(format!("{:.0} s ago", now - aircraft.last_seen));
+                ui.weak(details.join(" · "));
+            });
+        }
+
+        if let Some(focus) = focus {
+            if focus.is_some() {
+                if let Some(view_state) = &mut self.data.view_state {
+                    view_state.follow_ownship = true;
+                }
+            }
+            self.live.fleet_focus = focus;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_every_key_alias() {
+        let report = parse(
+            "livechart/fleet/ignored",
+            br#"{"registration": "D-EABC", "label": "Echo 1", "latitude": 47.26,
+                "lng": 11.35, "altitude": 3500, "groundspeed": 95, "heading": 260,
+                "timestamp": 1714573805}"#,
+            "mqtt",
+        )
+        .unwrap();
+        assert_eq!(report.name, "D-EABC");
+        assert_eq!(report.label.as_deref(), Some("Echo 1"));
+        assert_eq!(
+            report.fix.position,
+            RealCoordinate {
+                lat: 47.26,
+                lon: 11.35
+            }
+        );
+        assert_eq!(report.fix.altitude_ft, Some(3500.0));
+        assert_eq!(report.fix.groundspeed_kt, Some(95.0));
+        assert_eq!(report.fix.track_deg, Some(260.0));
+        assert_eq!(report.fix.time, 1_714_573_805.0);
+        assert_eq!(report.fix.source, "mqtt");
+    }
+
+    #[test]
+    fn names_the_aircraft_after_the_topic_without_an_id() {
+        let report = parse(
+            "livechart/fleet/D-EXYZ",
+            br#"{"lat": 47.0, "lon": 11.0}"#,
+            "mqtt",
+        )
+        .unwrap();
+        assert_eq!(report.name, "D-EXYZ");
+        assert_eq!(report.label, None);
+        assert_eq!(report.fix.altitude_ft, None);
+
+        assert!(parse("livechart/fleet/", br#"{"lat": 47.0, "lon": 11.0}"#, "mqtt").is_err());
+    }
+
+    #[test]
+    fn reads_iso_and_unix_times() {
+        let at = |time: &str| {
+            let payload =
+                format!(r#"{{"id": "D-EABC", "lat": 47.0, "lon": 11.0, "time": {time}}}"#);
+            parse("fleet", payload.as_bytes(), "mqtt").unwrap().fix.time
+        };
+        assert_eq!(at("1714573805.5"), 1_714_573_805.5);
+        assert_eq!(at(r#""2024-05-01T14:30:05Z""#), 1_714_573_805.0);
+
+        // Unreadable times fall back to the time of arrival
+        let before = utc::now();
+        assert!(at(r#""yesterday""#) >= before);
+    }
+
+    #[test]
+    fn rejects_reports_without_a_position() {
+        assert!(parse("fleet/D-EABC", br#"{"lat": 47.0}"#, "mqtt").is_err());
+        assert!(parse("fleet/D-EABC", b"not json", "mqtt").is_err());
+    }
+
+    #[test]
+    fn forgets_colours_of_absent_aircraft_past_the_limit() {
+        let mut app = LivechartApp::default();
+        let report = |name: String| FleetReport {
+            name,
+            label: None,
+            fix: parse("fleet", br#"{"id": "x", "lat": 47.0, "lon": 11.0}"#, "mqtt")
+                .unwrap()
+                .fix,
+        };
+
+        for index in 0..MAX_COLORS {
+            app.ingest_fleet_report(report(format!("D-{index:04}")));
+        }
+        // Only the last two are still reporting
+        app.expire_fleet(utc::now() + REMOVE_AFTER_S + 1.0);
+        app.ingest_fleet_report(report("D-0062".to_owned()));
+        app.ingest_fleet_report(report("D-0063".to_owned()));
+        assert_eq!(app.data.fleet_display.colors.len(), MAX_COLORS);
+
+        app.ingest_fleet_report(report("D-NEW".to_owned()));
+        let colors = &app.data.fleet_display.colors;
+        assert_eq!(colors.len(), 3);
+        assert!(["D-0062", "D-0063", "D-NEW"]
+            .iter()
+            .all(|name| colors.contains_key(*name)));
+    }
+}
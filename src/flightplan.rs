@@ -0,0 +1,583 @@
+//! Planned routes from Garmin FlightPlan (`.fpl`), X-Plane (`.fms`) and GPX `<rte>` files, drawn
+//! over the chart with the active leg highlighted.
+
+use crate::app::LivechartApp;
+use crate::geo::{self, Georeference};
+use crate::structs::RealCoordinate;
+
+/// Legs that aren't active.
+const ROUTE_COLOR: egui::Color32 = egui::Color32::from_rgb(90, 90, 220);
+/// Magenta, like the active leg on any GPS.
+const ACTIVE_LEG_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 0, 255);
+/// Further off the active leg than this, the nearest leg becomes active instead.
+const MAX_OFF_LEG_M: f64 = 3.0 * geo::METRES_PER_NM;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteWaypoint {
+    pub ident: String,
+    pub position: RealCoordinate,
+    pub altitude_ft: Option<f32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FlightPlan {
+    pub name: String,
+    pub waypoints: Vec<RouteWaypoint>,
+}
+
+/// Where the ownship is relative to a leg, in metres.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LegPosition {
+    /// Distance flown along the leg from its first waypoint.
+    pub along_m: f64,
+    /// Right of the leg is positive.
+    pub cross_m: f64,
+    pub length_m: f64,
+}
+
+impl FlightPlan {
+    /// Loads a plan, picking the format from the file extension.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &std::path::Path) -> Result<Self, String> {
+        let name = path
+            .file_stem()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let bytes = std::fs::read(path).map_err(|e| format!("Could not read {name}: {e}"))?;
+        let text = String::from_utf8_lossy(&bytes);
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        let mut plan = match extension.as_str() {
+            "fpl" => Self::from_fpl(&text)?,
+            "fms" => Self::from_fms(&text),
+            "gpx" => Self::from_gpx_route(&text)?,
+            _ => return Err(format!("Unknown flight plan format .{extension}")),
+        };
+        if plan.waypoints.len() < 2 {
+            return Err(format!("{name} has no route"));
+        }
+        if plan.name.is_empty() {
+            plan.name = name;
+        }
+        Ok(plan)
+    }
+
+    /// Garmin FlightPlan v1: a waypoint table with the coordinates, and a route referring to it
+    /// by identifier and type.
+    pub fn from_fpl(text: &str) -> Result<Self, String> {
+        let document =
+            roxmltree::Document::parse(text).map_err(|e| format!("Invalid flight plan: {e}"))?;
+        let child_text = |node: roxmltree::Node<'_, '_>, name: &str| {
+            node.children()
+                .find(|child| child.tag_name().name() == name)
+                .and_then(|child| child.text())
+                .map(str::trim)
+                .unwrap_or("")
+                .to_owned()
+        };
+
+        let table: Vec<(String, String, RouteWaypoint)> = document
+            .descendants()
+            .filter(|node| node.tag_name().name() == "waypoint")
+            .filter_map(|waypoint| {
+                let ident = child_text(waypoint, "identifier");
+                let position = RealCoordinate {
+                    lat: child_text(waypoint, "lat").parse().ok()?,
+                    lon: child_text(waypoint, "lon").parse().ok()?,
+                };
+                Some((
+                    ident.clone(),
+                    child_text(waypoint, "type"),
+                    RouteWaypoint {
+                        ident,
+                        position,
+                        altitude_ft: None,
+                    },
+                ))
+            })
+            .collect();
+
+        let route = document
+            .descendants()
+            .find(|node| node.tag_name().name() == "route");
+        let waypoints = match route {
+            Some(route) => route
+                .children()
+                .filter(|node| node.tag_name().name() == "route-point")
+                .filter_map(|point| {
+                    let ident = child_text(point, "waypoint-identifier");
+                    let kind = child_text(point, "waypoint-type");
+                    table
+                        .iter()
+                        .find(|(i, k, _)| *i == ident && (kind.is_empty() || *k == kind))
+                        .map(|(_, _, waypoint)| waypoint.clone())
+                })
+                .collect(),
+            // Without a route the table is in flying order
+            None => table.into_iter().map(|(_, _, waypoint)| waypoint).collect(),
+        };
+
+        Ok(Self {
+            name: route
+                .map(|route| child_text(route, "route-name"))
+                .unwrap_or_default(),
+            waypoints,
+        })
+    }
+
+    /// X-Plane 11/12 (`1100 Version`) and older (`3 version`) flight plans. Each waypoint line is
+    /// `type ident [via] altitude lat lon`.
+    pub fn from_fms(text: &str) -> Self {
+        let mut plan = Self::default();
+        for line in text.lines() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.len() < 5 || words[0].parse::<u32>().is_err() {
+                continue;
+            }
+            let number = |index: usize| words[index].parse::<f64>().ok();
+            let (Some(lat), Some(lon)) = (number(words.len() - 2), number(words.len() - 1)) else {
+                continue;
+            };
+            plan.waypoints.push(RouteWaypoint {
+                ident: words[1].to_owned(),
+                position: RealCoordinate { lat, lon },
+                altitude_ft: number(words.len() - 3)
+                    .filter(|&altitude| altitude > 0.0)
+                    .map(|altitude| altitude as f32),
+            });
+        }
+        plan
+    }
+
+    /// The first `<rte>` of a GPX file.
+    pub fn from_gpx_route(text: &str) -> Result<Self, String> {
+        let document = roxmltree::Document::parse(text).map_err(|e| format!("Invalid GPX: {e}"))?;
+        let Some(route) = document
+            .descendants()
+            .find(|node| node.tag_name().name() == "rte")
+        else {
+            return Err("The GPX file has no route".to_owned());
+        };
+        let child_text = |node: roxmltree::Node<'_, '_>, name: &str| {
+            node.children()
+                .find(|child| child.tag_name().name() == name)
+                .and_then(|child| child.text())
+                .map(|text| text.trim().to_owned())
+        };
+
+        let waypoints = route
+            .children()
+            .filter(|node| node.tag_name().name() == "rtept")
+            .enumerate()
+            .filter_map(|(index, point)| {
+                Some(RouteWaypoint {
+                    ident: child_text(point, "name").unwrap_or_else(|| format!("WP{}", index + 1)),
+                    position: RealCoordinate {
+                        lat: point.attribute("lat")?.parse().ok()?,
+                        lon: point.attribute("lon")?.parse().ok()?,
+                    },
+                    altitude_ft: child_text(point, "ele")
+                        .and_then(|ele| ele.parse::<f64>().ok())
+                        .map(|m| (m / geo::METRES_PER_FT) as f32),
+                })
+            })
+            .collect();
+
+        Ok(Self {
+            name: child_text(route, "name").unwrap_or_default(),
+            waypoints,
+        })
+    }
+
+    /// Position relative to leg `leg`, from waypoint `leg` to `leg + 1`.
+    pub fn leg_position(&self, leg: usize, position: &RealCoordinate) -> Option<LegPosition> {
+        let from = &self.waypoints.get(leg)?.position;
+        let to = &self.waypoints.get(leg + 1)?.position;
+        let (to_east, to_north) = geo::project(from, to);
+        let (east, north) = geo::project(from, position);
+        let length_m = to_east.hypot(to_north);
+        if length_m <= 0.0 {
+            return None;
+        }
+        let (unit_east, unit_north) = (to_east / length_m, to_north / length_m);
+        Some(LegPosition {
+            along_m: east * unit_east + north * unit_north,
+            cross_m: east * unit_north - north * unit_east,
+            length_m,
+        })
+    }
+
+    /// The leg being flown. Once on a leg we stay on it until its end is passed, otherwise the
+    /// leg closest to the position is picked. So is it when the position is far from the leg,
+    /// after a direct-to or a diversion.
+    pub fn active_leg(&self, position: &RealCoordinate, current: Option<usize>) -> Option<usize> {
+        let legs = self.waypoints.len().checked_sub(1)?;
+        if let Some(mut leg) = current.filter(|&leg| leg < legs) {
+            while leg + 1 < legs {
+                match self.leg_position(leg, position) {
+                    Some(on_leg) if on_leg.along_m < on_leg.length_m => break,
+                    _ => leg += 1,
+                }
+            }
+            let near_leg = self.leg_position(leg, position).is_some_and(|on_leg| {
+                on_leg.cross_m.abs() <= MAX_OFF_LEG_M
+                    && (-MAX_OFF_LEG_M..=on_leg.length_m + MAX_OFF_LEG_M).contains(&on_leg.along_m)
+            });
+            if near_leg {
+                return Some(leg);
+            }
+        }
+
+        (0..legs)
+            .filter_map(|leg| {
+                let on_leg = self.leg_position(leg, position)?;
+                let beyond = (-on_leg.along_m)
+                    .max(on_leg.along_m - on_leg.length_m)
+                    .max(0.0);
+                Some((leg, on_leg.cross_m.hypot(beyond)))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(leg, _)| leg)
+    }
+}
+
+impl LivechartApp {
+    /// Follows the ownship along the route.
+    pub fn update_active_leg(&mut self) {
+        let (Some(plan), Some(fix)) = (&self.live.flight_plan, &self.live.ownship) else {
+            self.live.active_leg = None;
+            return;
+        };
+        self.live.active_leg = plan.active_leg(&fix.position, self.live.active_leg);
+    }
+
+    pub fn draw_route(
+        &self,
+        ui: &egui::Ui,
+        image_response: &egui::Response,
+        image_size: (u32, u32),
+        georeference: &Georeference,
+    ) {
+        let Some(plan) = &self.live.flight_plan else {
+            return;
+        };
+        let painter = ui.painter_at(image_response.rect);
+        let screen: Vec<egui::Pos2> = plan
+            .waypoints
+            .iter()
+            .map(|waypoint| {
+                self.real_to_screen(&waypoint.position, georeference, image_response, image_size)
+            })
+            .collect();
+
+        for (leg, pair) in screen.windows(2).enumerate() {
+            let stroke = if Some(leg) == self.live.active_leg {
+                egui::Stroke::new(4.0, ACTIVE_LEG_COLOR)
+            } else {
+                egui::Stroke::new(2.5, ROUTE_COLOR)
+            };
+            painter.line_segment([pair[0], pair[1]], stroke);
+        }
+
+        for (waypoint, pos) in plan.waypoints.iter().zip(&screen) {
+            let size = 5.0;
+            painter.add(egui::Shape::convex_polygon(
+                vec![
+                    *pos + egui::vec2(0.0, -size),
+                    *pos + egui::vec2(size, 0.0),
+                    *pos + egui::vec2(0.0, size),
+                    *pos + egui::vec2(-size, 0.0),
+                ],
+                egui::Color32::WHITE,
+                egui::Stroke::new(1.5, ROUTE_COLOR),
+            ));
+            painter.text(
+                *pos + egui::vec2(8.0, 0.0),
+                egui::Align2::LEFT_CENTER,
+                &waypoint.ident,
+                egui::FontId::proportional(12.0),
+                ROUTE_COLOR,
+            );
+        }
+    }
+
+    pub fn route_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.live.route_window_open;
+        egui::Window::new("Route")
+            .open(&mut open)
+            .default_width(300.0)
+            .show(ctx, |ui| {
+                if self.live.flight_plan_new_path.is_empty() {
+                    self.live.flight_plan_new_path = self.data.flight_plan_path.clone();
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Flight plan:");
+                    ui.text_edit_singleline(&mut self.live.flight_plan_new_path)
+                        .on_hover_text("Garmin .fpl, X-Plane .fms or GPX with a route");
+                    if ui.button("Load").clicked() {
+                        self.data.flight_plan_path =
+                            self.live.flight_plan_new_path.trim().to_owned();
+                        // Loading the same path again re-reads it
+                        self.live.flight_plan_source = None;
+                    }
+                });
+                if let Some(error) = &self.live.flight_plan_error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+
+                let Some(plan) = &self.live.flight_plan else {
+                    return;
+                };
+                ui.separator();
+                ui.strong(&plan.name);
+                egui::Grid::new("route_waypoints")
+                    .num_columns(2)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (index, waypoint) in plan.waypoints.iter().enumerate() {
+                            let active = self.live.active_leg.map(|leg| leg + 1) == Some(index);
+                            if active {
+                                ui.colored_label(ACTIVE_LEG_COLOR, format!("→ {}", waypoint.ident));
+                            } else {
+                                ui.label(&waypoint.ident);
+                            }
+                            ui.label(
+                                waypoint
+                                    .altitude_ft
+                                    .map(|altitude| format!("{altitude:.0} ft"))
+                                    .unwrap_or_default(),
+                            );
+                            ui.end_row();
+                        }
+                    });
+
+                if ui.button("Unload").clicked() {
+                    // Also forgotten for the next start
+                    self.data.flight_plan_path.clear();
+                    self.live.flight_plan_new_path.clear();
+                    self.live.flight_plan_source = None;
+                    self.live.flight_plan = None;
+                    self.live.active_leg = None;
+                }
+            });
+        self.live.route_window_open = open;
+    }
+
+    /// Loads the configured flight plan whenever the path changes, the saved one on startup.
+    pub fn sync_flight_plan(&mut self) {
+        let path = self.data.flight_plan_path.clone();
+        if path.is_empty() || self.live.flight_plan_source.as_ref() == Some(&path) {
+            return;
+        }
+        self.live.flight_plan_source = Some(path.clone());
+
+        #[cfg(not(target_arch = "wasm32"))]
+        match FlightPlan::load(std::path::Path::new(&path)) {
+            Ok(plan) => {
+                self.live.flight_plan = Some(plan);
+                self.live.active_leg = None;
+                self.live.flight_plan_error = None;
+            }
+            Err(error) => self.live.flight_plan_error = Some(error),
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.live.flight_plan_error =
+                Some("Loading files is not supported on the web".to_owned());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FMS_11: &str = "I
+1100 Version
+CYCLE 2310
+ADEP KSEA
+DEPRWY RW16L
+ADES KPDX
+DESRWY RW10R
+NUMENR 4
+1 KSEA ADEP 433.000000 47.449000 -122.309306
+3 SEA DRCT 0.000000 47.435372 -122.309617
+3 OLM V165 6500.000000 46.971233 -122.902183
+1 KPDX ADES 31.000000 45.588722 -122.597500
+";
+
+    const FMS_3: &str = "I
+3 version
+1
+3
+1 KSEA 433.000000 47.449000 -122.309306
+28 +47.000_-122.500 4500.000000 47.000000 -122.500000
+1 KPDX 31.000000 45.588722 -122.597500
+";
+
+    const FPL: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<flight-plan xmlns="http://www8.garmin.com/xmlschemas/FlightPlan/v1">
+  <created>20231015T12:00:00Z</created>
+  <waypoint-table>
+    <waypoint>
+      <identifier>KPDX</identifier>
+      <type>AIRPORT</type>
+      <country-code>K1</country-code>
+      <lat>45.588722</lat>
+      <lon>-122.5975</lon>
+      <comment />
+    </waypoint>
+    <waypoint>
+      <identifier>OLM</identifier>
+      <type>VOR</type>
+      <country-code>K1</country-code>
+      <lat>46.971233</lat>
+      <lon>-122.902183</lon>
+      <comment />
+    </waypoint>
+    <waypoint>
+      <identifier>KSEA</identifier>
+      <type>AIRPORT</type>
+      <country-code>K1</country-code>
+      <lat>47.449</lat>
+      <lon>-122.309306</lon>
+      <comment />
+    </waypoint>
+  </waypoint-table>
+  <route>
+    <route-name>KSEA TO KPDX</route-name>
+    <flight-plan-index>1</flight-plan-index>
+    <route-point>
+      <waypoint-identifier>KSEA</waypoint-identifier>
+      <waypoint-type>AIRPORT</waypoint-type>
+      <waypoint-country-code>K1</waypoint-country-code>
+    </route-point>
+    <route-point>
+      <waypoint-identifier>OLM</waypoint-identifier>
+      <waypoint-type>VOR</waypoint-type>
+      <waypoint-country-code>K1</waypoint-country-code>
+    </route-point>
+    <route-point>
+      <waypoint-identifier>KPDX</waypoint-identifier>
+      <waypoint-type>AIRPORT</waypoint-type>
+      <waypoint-country-code>K1</waypoint-country-code>
+    </route-point>
+  </route>
+</flight-plan>
+"#;
+
+    fn idents(plan: &FlightPlan) -> Vec<&str> {
+        plan.waypoints.iter().map(|w| w.ident.as_str()).collect()
+    }
+
+    #[test]
+    fn reads_xplane_11_and_12_plans() {
+        let plan = FlightPlan::from_fms(FMS_11);
+        assert_eq!(idents(&plan), ["KSEA", "SEA", "OLM", "KPDX"]);
+        assert_eq!(
+            plan.waypoints[2],
+            RouteWaypoint {
+                ident: "OLM".to_owned(),
+                position: RealCoordinate {
+                    lat: 46.971233,
+                    lon: -122.902183,
+                },
+                altitude_ft: Some(6500.0),
+            }
+        );
+        // 0 means no altitude
+        assert_eq!(plan.waypoints[1].altitude_ft, None);
+    }
+
+    #[test]
+    fn reads_older_xplane_plans() {
+        let plan = FlightPlan::from_fms(FMS_3);
+        assert_eq!(idents(&plan), ["KSEA", "+47.000_-122.500", "KPDX"]);
+        assert_eq!(plan.waypoints[1].altitude_ft, Some(4500.0));
+        assert_eq!(plan.waypoints[2].position.lat, 45.588722);
+    }
+
+    #[test]
+    fn reads_garmin_plans_in_route_order() {
+        let plan = FlightPlan::from_fpl(FPL).unwrap();
+        assert_eq!(plan.name, "KSEA TO KPDX");
+        assert_eq!(idents(&plan), ["KSEA", "OLM", "KPDX"]);
+        assert_eq!(plan.waypoints[0].position.lon, -122.309306);
+        assert!(FlightPlan::from_fpl("<flight-plan>").is_err());
+    }
+
+    #[test]
+    fn reads_gpx_routes() {
+        let plan = FlightPlan::from_gpx_route(
+            r#"<gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+  <rte>
+    <name>Lake tour</name>
+    <rtept lat="47.449" lon="-122.309306"><name>KSEA</name><ele>131.1</ele></rtept>
+    <rtept lat="47.0" lon="-122.5"></rtept>
+  </rte>
+</gpx>"#,
+        )
+        .unwrap();
+        assert_eq!(plan.name, "Lake tour");
+        assert_eq!(idents(&plan), ["KSEA", "WP2"]);
+        assert!((plan.waypoints[0].altitude_ft.unwrap() - 430.1).abs() < 0.1);
+        assert!(FlightPlan::from_gpx_route("<gpx></gpx>").is_err());
+    }
+
+    /// North along 8° E, then east along 47.2° N.
+    fn dogleg() -> FlightPlan {
+        let waypoint = |ident: &str, lat, lon| RouteWaypoint {
+            ident: ident.to_owned(),
+            position: RealCoordinate { lat, lon },
+            altitude_ft: None,
+        };
+        FlightPlan {
+            name: String::new(),
+            waypoints: vec![
+                waypoint("A", 47.0, 8.0),
+                waypoint("B", 47.2, 8.0),
+                waypoint("C", 47.2, 8.4),
+            ],
+        }
+    }
+
+    #[test]
+    fn sequences_legs_as_their_ends_are_passed() {
+        let plan = dogleg();
+        let on_first = RealCoordinate {
+            lat: 47.1,
+            lon: 8.0,
+        };
+        assert_eq!(plan.active_leg(&on_first, None), Some(0));
+        assert_eq!(plan.active_leg(&on_first, Some(0)), Some(0));
+
+        // Past B, cutting the corner
+        let past_b = RealCoordinate {
+            lat: 47.201,
+            lon: 8.01,
+        };
+        assert_eq!(plan.active_leg(&past_b, Some(0)), Some(1));
+    }
+
+    #[test]
+    fn picks_the_nearest_leg_far_off_the_active_one() {
+        let plan = dogleg();
+        // Direct to C: still abeam the first leg, but far off it
+        let near_c = RealCoordinate {
+            lat: 47.19,
+            lon: 8.38,
+        };
+        assert_eq!(plan.active_leg(&near_c, Some(0)), Some(1));
+
+        // Flying the first leg again after a go-around from the second
+        let back = RealCoordinate {
+            lat: 47.05,
+            lon: 8.0,
+        };
+        assert_eq!(plan.active_leg(&back, Some(1)), Some(0));
+    }
+}
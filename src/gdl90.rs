@@ -0,0 +1,425 @@
+//! GDL 90 data interface (as used by Stratux, SkyEcho, ForeFlight Sentry, ...).
+//!
+//! Reference: GDL 90 Data Interface Specification, 560-1058-00 Rev A.
+
+use crate::structs::{Fix, RealCoordinate};
+
+const FLAG: u8 = 0x7E;
+const ESCAPE: u8 = 0x7D;
+
+const CRC_TABLE: [u16; 256] = {
+    let mut table = [0u16; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+pub fn crc(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, &byte| {
+        CRC_TABLE[(crc >> 8) as usize] ^ (crc << 8) ^ u16::from(byte)
+    })
+}
+
+/// Splits a byte stream into messages, undoing byte stuffing and dropping frames with a bad CRC.
+#[derive(Debug, Default)]
+pub struct Deframer {
+    /// Frames dropped for a bad CRC so far.
+    pub errors: u64,
+    buffer: Vec<u8>,
+    in_frame: bool,
+    escaped: bool,
+}
+
+impl Deframer {
+    /// Feeds raw bytes in, returns every complete message (without flags and CRC).
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Vec<u8>> {
+        let mut messages = Vec::new();
+
+        for &byte in bytes {
+            if byte == FLAG {
+                if self.in_frame && self.buffer.len() > 2 {
+                    let (message, checksum) = self.buffer.split_at(self.buffer.len() - 2);
+                    if crc(message) == u16::from_le_bytes([checksum[0], checksum[1]]) {
+                        messages.push(message.to_vec());
+                    } else {
+                        self.errors += 1;
+                    }
+                }
+                self.buffer.clear();
+                self.in_frame = true;
+                self.escaped = false;
+            } else if !self.in_frame {
+                continue;
+            } else if byte == ESCAPE {
+                self.escaped = true;
+            } else if self.escaped {
+                self.buffer.push(byte ^ 0x20);
+                self.escaped = false;
+            } else {
+                self.buffer.push(byte);
+            }
+        }
+
+        messages
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Heartbeat(Heartbeat),
+    Ownship(Report),
+    /// Geometric (GNSS) altitude of the ownship in feet.
+    OwnshipGeometricAltitude(i32),
+    Traffic(Report),
+    /// ForeFlight device identification, with whether geometric altitudes are above MSL rather
+    /// than the WGS 84 ellipsoid.
+    DeviceId {
+        geometric_msl: bool,
+    },
+    /// Valid message we don't decode, with its id.
+    Other(u8),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heartbeat {
+    pub gps_valid: bool,
+    /// Seconds since 0000Z.
+    pub seconds_of_day: u32,
+}
+
+/// Ownship and traffic reports share this layout.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub alert: bool,
+    pub address_type: u8,
+    pub address: u32,
+    pub position: Option<RealCoordinate>,
+    /// Pressure altitude in feet.
+    pub altitude_ft: Option<i32>,
+    pub airborne: bool,
+    pub nic: u8,
+    pub nacp: u8,
+    pub groundspeed_kt: Option<u16>,
+    pub vertical_rate_fpm: Option<i32>,
+    pub track_deg: Option<f32>,
+    pub emitter_category: u8,
+    pub callsign: String,
+}
+
+impl Report {
+    /// Ownship report for a GNSS fix. Pressure altitude, NIC and NACp are unknown, callers that
+    /// know better fill them in. The GNSS altitude goes into message 11.
+    pub fn ownship(fix: &Fix, vertical_rate_fpm: Option<i32>, callsign: &str) -> Self {
+        Self {
+            alert: false,
+            address_type: 0,
+            address: 0,
+            position: Some(fix.position.clone()),
+            altitude_ft: None,
+            airborne: fix.groundspeed_kt.map_or(true, |s| s > 30.0),
+            nic: 0,
+            nacp: 0,
+            groundspeed_kt: fix.groundspeed_kt.map(|s| s.round() as u16),
+            vertical_rate_fpm,
+            track_deg: fix.track_deg,
+            emitter_category: 1,
+            callsign: callsign.to_owned(),
+        }
+    }
+}
+
+/// Decodes a single message as returned by [`Deframer::push`].
+pub fn decode(message: &[u8]) -> Option<Message> {
+    let id = *message.first()?;
+    Some(match id {
+        0 if message.len() >= 7 => Message::Heartbeat(Heartbeat {
+            gps_valid: message[1] & 0x80 != 0,
+            seconds_of_day: (u32::from(message[2] & 0x80) << 9)
+                | u32::from(u16::from_le_bytes([message[3], message[4]])),
+        }),
+        10 => Message::Ownship(decode_report(message)?),
+        20 => Message::Traffic(decode_report(message)?),
+        11 if message.len() >= 5 => {
+            let altitude = i16::from_be_bytes([message[1], message[2]]);
+            Message::OwnshipGeometricAltitude(i32::from(altitude) * 5)
+        }
+        // The capabilities are the last four bytes, big endian
+        0x65 if message.len() >= 39 && message[1] == 0 => Message::DeviceId {
+            geometric_msl: message[38] & 0x01 != 0,
+        },
+        _ => Message::Other(id),
+    })
+}
+
+fn decode_report(message: &[u8]) -> Option<Report> {
+    if message.len() < 28 {
+        return None;
+    }
+
+    let lat = semicircles(&message[5..8]);
+    let lon = semicircles(&message[8..11]);
+    let nic = message[13] >> 4;
+    // All zero with NIC 0 means "no position"
+    let position = if lat == 0.0 && lon == 0.0 && nic == 0 {
+        None
+    } else {
+        Some(RealCoordinate { lat, lon })
+    };
+
+    let altitude = (u16::from(message[11]) << 4) | u16::from(message[12] >> 4);
+    let misc = message[12] & 0x0F;
+
+    let horizontal = (u16::from(message[14]) << 4) | u16::from(message[15] >> 4);
+    let vertical = (u16::from(message[15] & 0x0F) << 8) | u16::from(message[16]);
+    // 12 bit two's complement
+    let vertical = (i32::from(vertical) << 20) >> 20;
+
+    let callsign = message[19..27]
+        .iter()
+        .map(|&b| b as char)
+        .collect::<String>()
+        .trim()
+        .to_owned();
+
+    Some(Report {
+        alert: message[1] >> 4 != 0,
+        address_type: message[1] & 0x0F,
+        address: u32::from_be_bytes([0, message[2], message[3], message[4]]),
+        position,
+        altitude_ft: (altitude != 0xFFF).then(|| i32::from(altitude) * 25 - 1000),
+        airborne: misc & 0x08 != 0,
+        nic,
+        nacp: message[13] & 0x0F,
+        groundspeed_kt: (horizontal != 0xFFF).then_some(horizontal),
+        vertical_rate_fpm: (vertical != -2048).then_some(vertical * 64),
+        track_deg: (misc & 0x03 != 0).then(|| f32::from(message[17]) * 360.0 / 256.0),
+        emitter_category: message[18],
+        callsign,
+    })
+}
+
+/// 24 bit signed, big endian, 180 / 2^23 degrees per LSB.
+fn semicircles(bytes: &[u8]) -> f64 {
+    let raw = i32::from_be_bytes([bytes[0], bytes[1], bytes[2], 0]) >> 8;
+    f64::from(raw) * 180.0 / f64::from(1 << 23)
+}
+
+fn to_semicircles(degrees: f64) -> [u8; 3] {
+    let raw = (degrees * f64::from(1 << 23) / 180.0).round() as i32;
+    let bytes = raw.to_be_bytes();
+    [bytes[1], bytes[2], bytes[3]]
+}
+
+/// Adds the CRC, escapes flag and escape bytes and wraps the message in flags.
+pub fn frame(message: &[u8]) -> Vec<u8> {
+    let checksum = crc(message).to_le_bytes();
+    let mut framed = vec![FLAG];
+    for &byte in message.iter().chain(&checksum) {
+        if byte == FLAG || byte == ESCAPE {
+            framed.extend([ESCAPE, byte ^ 0x20]);
+        } else {
+            framed.push(byte);
+        }
+    }
+    framed.push(FLAG);
+    framed
+}
+
+pub fn encode_heartbeat(heartbeat: &Heartbeat) -> Vec<u8> {
+    let timestamp = heartbeat.seconds_of_day % 86_400;
+    let [low, high, ..] = timestamp.to_le_bytes();
+    vec![
+        0,
+        // UAT initialized, position valid
+        0x01 | if heartbeat.gps_valid { 0x80 } else { 0 },
+        // Timestamp bit 16, UTC OK
+        ((timestamp >> 16) as u8) << 7 | 0x01,
+        low,
+        high,
+        0,
+        0,
+    ]
+}
+
+/// Message 10 for the ownship, 20 for traffic.
+pub fn encode_report(id: u8, report: &Report) -> Vec<u8> {
+    let mut message = vec![id; 28];
+    message[1] = u8::from(report.alert) << 4 | (report.address_type & 0x0F);
+    message[2..5].copy_from_slice(&report.address.to_be_bytes()[1..]);
+
+    let (lat, lon) = report.position.as_ref().map_or(([0; 3], [0; 3]), |p| {
+        (to_semicircles(p.lat), to_semicircles(p.lon))
+    });
+    message[5..8].copy_from_slice(&lat);
+    message[8..11].copy_from_slice(&lon);
+
+    let altitude = report
+        .altitude_ft
+        .map_or(0xFFF, |a| ((a + 1000) / 25).clamp(0, 0xFFE) as u16);
+    let track_valid = report.track_deg.is_some();
+    let misc = u8::from(report.airborne) << 3 | u8::from(track_valid);
+    message[11] = (altitude >> 4) as u8;
+    message[12] = ((altitude & 0x0F) as u8) << 4 | misc;
+    message[13] = report.nic << 4 | (report.nacp & 0x0F);
+
+    let horizontal = report.groundspeed_kt.map_or(0xFFF, |s| s.min(0xFFE));
+    let vertical = report
+        .vertical_rate_fpm
+        .map_or(0x800, |v| (v / 64).clamp(-510, 510) as u16 & 0xFFF);
+    message[14] = (horizontal >> 4) as u8;
+    message[15] = ((horizontal & 0x0F) as u8) << 4 | (vertical >> 8) as u8;
+    message[16] = vertical as u8;
+    message[17] = report
+        .track_deg
+        .map_or(0, |t| (t.rem_euclid(360.0) * 256.0 / 360.0) as u8);
+    message[18] = report.emitter_category;
+
+    let mut callsign = [b' '; 8];
+    for (slot, byte) in callsign.iter_mut().zip(report.callsign.bytes()) {
+        *slot = byte;
+    }
+    message[19..27].copy_from_slice(&callsign);
+    message[27] = 0;
+    message
+}
+
+pub fn encode_geometric_altitude(altitude_ft: i32) -> Vec<u8> {
+    let [high, low] = ((altitude_ft / 5).clamp(-32768, 32767) as i16).to_be_bytes();
+    // Vertical figure of merit not available
+    vec![11, high, low, 0x7F, 0xFF]
+}
+
+/// ForeFlight's device identification (id 0x65), which makes it list us as a GDL 90 source. The
+/// capability flag says geometric altitudes are above MSL.
+pub fn encode_foreflight_id(name: &str, long_name: &str) -> Vec<u8> {
+    let mut message = vec![0x65, 0, 1];
+    message.extend([0xFF; 8]);
+    for (text, length) in [(name, 8), (long_name, 16)] {
+        let mut field = vec![b' '; length];
+        for (slot, byte) in field.iter_mut().zip(text.bytes()) {
+            *slot = byte;
+        }
+        message.extend(field);
+    }
+    message.extend([0, 0, 0, 1]);
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The traffic report example from the GDL 90 specification, section 3.5.4.
+    const TRAFFIC_REPORT: [u8; 28] = [
+        0x14, 0x00, 0xAB, 0x45, 0x49, 0x1F, 0xEF, 0x15, 0xA8, 0x89, 0x78, 0x0F, 0x09, 0xA9, 0x07,
+        0xB0, 0x01, 0x20, 0x01, 0x4E, 0x38, 0x32, 0x35, 0x56, 0x20, 0x20, 0x20, 0x00,
+    ];
+
+    #[test]
+    fn decodes_the_specification_traffic_report() {
+        let Some(Message::Traffic(report)) = decode(&TRAFFIC_REPORT) else {
+            panic!("not a traffic report");
+        };
+        assert!(!report.alert);
+        assert_eq!(report.address_type, 0);
+        assert_eq!(report.address, 0xAB4549);
+        let position = report.position.clone().unwrap();
+        assert!((position.lat - 44.90708).abs() < 1e-4);
+        assert!((position.lon + 122.99488).abs() < 1e-4);
+        assert_eq!(report.altitude_ft, Some(5000));
+        assert!(report.airborne);
+        assert_eq!((report.nic, report.nacp), (10, 9));
+        assert_eq!(report.groundspeed_kt, Some(123));
+        assert_eq!(report.vertical_rate_fpm, Some(64));
+        assert_eq!(report.track_deg, Some(45.0));
+        assert_eq!(report.emitter_category, 1);
+        assert_eq!(report.callsign, "N825V");
+    }
+
+    #[test]
+    fn traffic_reports_survive_framing_and_encoding() {
+        let Some(Message::Traffic(report)) = decode(&TRAFFIC_REPORT) else {
+            panic!("not a traffic report");
+        };
+        assert_eq!(encode_report(20, &report), TRAFFIC_REPORT);
+
+        let mut deframer = Deframer::default();
+        let mut stream = frame(&TRAFFIC_REPORT);
+        // A corrupted copy is dropped, the good one still comes through
+        let mut corrupted = frame(&TRAFFIC_REPORT);
+        corrupted[10] ^= 0x01;
+        stream.extend(corrupted);
+        assert_eq!(deframer.push(&stream), vec![TRAFFIC_REPORT.to_vec()]);
+        assert_eq!(deframer.errors, 1);
+    }
+
+    #[test]
+    fn marks_unknown_fields_as_missing() {
+        let mut message = TRAFFIC_REPORT;
+        // No position, invalid altitude, speed and vertical rate, no track
+        message[5..11].fill(0);
+        message[11] = 0xFF;
+        message[12] = 0xF0;
+        message[13] = 0x00;
+        message[14] = 0xFF;
+        message[15] = 0xF8;
+        message[16] = 0x00;
+        let Some(Message::Traffic(report)) = decode(&message) else {
+            panic!("not a traffic report");
+        };
+        assert_eq!(report.position, None);
+        assert_eq!(report.altitude_ft, None);
+        assert!(!report.airborne);
+        assert_eq!(report.groundspeed_kt, None);
+        assert_eq!(report.vertical_rate_fpm, None);
+        assert_eq!(report.track_deg, None);
+        assert_eq!(decode(&message[..20]), None);
+    }
+
+    #[test]
+    fn reads_the_altitude_datum_from_the_device_id() {
+        let mut message = encode_foreflight_id("LiveChrt", "LiveChart");
+        assert_eq!(
+            decode(&message),
+            Some(Message::DeviceId {
+                geometric_msl: true
+            })
+        );
+        message[38] = 0;
+        assert_eq!(
+            decode(&message),
+            Some(Message::DeviceId {
+                geometric_msl: false
+            })
+        );
+    }
+
+    #[test]
+    fn traffic_updates_keep_icao_addresses_only() {
+        let Some(Message::Traffic(mut report)) = decode(&TRAFFIC_REPORT) else {
+            panic!("not a traffic report");
+        };
+        let update = crate::traffic::TrafficUpdate::from_gdl90(&report, "gdl90");
+        assert_eq!(update.id, 0xAB4549);
+        assert_eq!(update.callsign.as_deref(), Some("N825V"));
+        assert_eq!(update.on_ground, Some(false));
+
+        // Self-assigned addresses can collide with ICAO ones
+        report.address_type = 1;
+        let update = crate::traffic::TrafficUpdate::from_gdl90(&report, "gdl90");
+        assert_eq!(update.id, 0xAB4549 | crate::traffic::NON_ICAO_FLAG);
+    }
+}
@@ -0,0 +1,361 @@
+use crate::structs::{CoordinatePair, PixelCoordinate, RealCoordinate};
+
+/// Control points narrower than this share of their length don't determine an affine fit well:
+/// any error across the line is blown up into a shear.
+const MIN_SPREAD_RATIO: f64 = 0.1;
+
+/// Mean earth radius in metres.
+pub const EARTH_RADIUS_M: f64 = 6_371_008.8;
+pub const METRES_PER_NM: f64 = 1852.0;
+pub const METRES_PER_FT: f64 = 0.3048;
+pub const MPS_PER_KT: f64 = METRES_PER_NM / 3600.0;
+
+/// Great-circle distance in metres.
+pub fn distance_m(from: &RealCoordinate, to: &RealCoordinate) -> f64 {
+    let (lat1, lat2) = (from.lat.to_radians(), to.lat.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (to.lon - from.lon).to_radians();
+
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().atan2((1.0 - a).sqrt())
+}
+
+/// Initial true bearing in degrees (0..360) of the great circle from `from` to `to`.
+pub fn bearing_deg(from: &RealCoordinate, to: &RealCoordinate) -> f64 {
+    let (lat1, lat2) = (from.lat.to_radians(), to.lat.to_radians());
+    let dlon = (to.lon - from.lon).to_radians();
+
+    let y = dlon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * dlon.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+/// Point reached by travelling `distance_m` along the great circle with initial `bearing_deg`.
+pub fn destination(from: &RealCoordinate, bearing_deg: f64, distance_m: f64) -> RealCoordinate {
+    let lat1 = from.lat.to_radians();
+    let lon1 = from.lon.to_radians();
+    let bearing = bearing_deg.to_radians();
+    let delta = distance_m / EARTH_RADIUS_M;
+
+    let lat2 = (lat1.sin() * delta.cos() + lat1.cos() * delta.sin() * bearing.cos()).asin();
+    let lon2 = lon1
+        + (bearing.sin() * delta.sin() * lat1.cos()).atan2(delta.cos() - lat1.sin() * lat2.sin());
+
+    RealCoordinate {
+        lat: lat2.to_degrees(),
+        lon: (lon2.to_degrees() + 540.0).rem_euclid(360.0) - 180.0,
+    }
+}
+
+/// Affine fit between the chart image and the ground, computed from the control points that
+/// have a real coordinate assigned. With only two points, or points (nearly) in a line (the
+/// runway ends of an airport diagram, the fixes of a straight-in approach), it falls back to a
+/// similarity: scale, rotation and offset, like any chart drawn to scale.
+///
+/// Coordinates are first projected onto a local east/north plane (metres) around the centroid
+/// of the control points, which is accurate enough for the extent of a single plate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Georeference {
+    origin: RealCoordinate,
+    /// `[a, b, c, d, e, f]` with `px = a*east + b*north + c` and `py = d*east + e*north + f`.
+    forward: [f64; 6],
+    /// Fitted as a similarity rather than an affine transform.
+    pub similarity: bool,
+}
+
+/// Width over length of a point cloud: the ratio of the smaller to the larger singular value
+/// of the points about their centroid. 0 for points in a line.
+fn spread_ratio(points: &[(f64, f64)]) -> f64 {
+    let n = points.len() as f64;
+    let (mean_x, mean_y) = points
+        .iter()
+        .fold((0.0, 0.0), |(x, y), (px, py)| (x + px / n, y + py / n));
+    let (mut xx, mut yy, mut xy) = (0.0, 0.0, 0.0);
+    for (x, y) in points {
+        let (dx, dy) = (x - mean_x, y - mean_y);
+        xx += dx * dx;
+        yy += dy * dy;
+        xy += dx * dy;
+    }
+    // Eigenvalues of the scatter matrix, the squared singular values
+    let half_trace = (xx + yy) / 2.0;
+    let root = (half_trace * half_trace - (xx * yy - xy * xy))
+        .max(0.0)
+        .sqrt();
+    let (largest, smallest) = (half_trace + root, (half_trace - root).max(0.0));
+    if largest <= f64::EPSILON {
+        return 0.0;
+    }
+    (smallest / largest).sqrt()
+}
+
+impl Georeference {
+    /// Least-squares affine fit from three or more control points that spread out both ways,
+    /// otherwise a similarity from two or more.
+    pub fn fit(points: &[CoordinatePair]) -> Option<Self> {
+        let pairs: Vec<(&PixelCoordinate, &RealCoordinate)> = points
+            .iter()
+            .filter_map(|p| p.real.as_ref().map(|real| (&p.pixels, real)))
+            .collect();
+
+        if pairs.len() < 3 {
+            return Self::fit_similarity(&pairs);
+        }
+
+        let n = pairs.len() as f64;
+        let origin = RealCoordinate {
+            lat: pairs.iter().map(|(_, r)| r.lat).sum::<f64>() / n,
+            lon: pairs.iter().map(|(_, r)| r.lon).sum::<f64>() / n,
+        };
+
+        let ground: Vec<(f64, f64)> = pairs
+            .iter()
+            .map(|(_, real)| project(&origin, real))
+            .collect();
+        if spread_ratio(&ground) < MIN_SPREAD_RATIO {
+            return Self::fit_similarity(&pairs);
+        }
+
+        // Normal equations for [east, north, 1] -> px and -> py
+        let mut ata = [[0.0f64; 3]; 3];
+        let mut atx = [0.0f64; 3];
+        let mut aty = [0.0f64; 3];
+        for ((pixel, _), (east, north)) in pairs.iter().zip(&ground) {
+            let row = [*east, *north, 1.0];
+            for i in 0..3 {
+                for j in 0..3 {
+                    ata[i][j] += row[i] * row[j];
+                }
+                atx[i] += row[i] * f64::from(pixel.x);
+                aty[i] += row[i] * f64::from(pixel.y);
+            }
+        }
+
+        let (Some([a, b, c]), Some([d, e, f])) = (solve3(ata, atx), solve3(ata, aty)) else {
+            return Self::fit_similarity(&pairs);
+        };
+
+        Some(Self {
+            origin,
+            forward: [a, b, c, d, e, f],
+            similarity: false,
+        })
+    }
+
+    /// Least-squares similarity (uniform scale, rotation, offset, no mirroring). Needs two
+    /// distinct control points.
+    fn fit_similarity(pairs: &[(&PixelCoordinate, &RealCoordinate)]) -> Option<Self> {
+        if pairs.len() < 2 {
+            return None;
+        }
+
+        let n = pairs.len() as f64;
+        let origin = RealCoordinate {
+            lat: pairs.iter().map(|(_, r)| r.lat).sum::<f64>() / n,
+            lon: pairs.iter().map(|(_, r)| r.lon).sum::<f64>() / n,
+        };
+        // Image y grows downwards, flip it so both sides turn the same way
+        let ground: Vec<(f64, f64)> = pairs
+            .iter()
+            .map(|(_, real)| project(&origin, real))
+            .collect();
+        let image: Vec<(f64, f64)> = pairs
+            .iter()
+            .map(|(pixel, _)| (f64::from(pixel.x), -f64::from(pixel.y)))
+            .collect();
+        let mean = |values: &[(f64, f64)]| {
+            let (x, y) = values
+                .iter()
+                .fold((0.0, 0.0), |(x, y), (vx, vy)| (x + vx, y + vy));
+            (x / n, y / n)
+        };
+        let (ge, gn) = mean(&ground);
+        let (ix, iy) = mean(&image);
+
+        // As complex numbers, image = s * ground + t with s = p + iq
+        let (mut p, mut q, mut norm) = (0.0, 0.0, 0.0);
+        for ((e, n), (x, y)) in ground.iter().zip(&image) {
+            let (e, n, x, y) = (e - ge, n - gn, x - ix, y - iy);
+            p += x * e + y * n;
+            q += y * e - x * n;
+            norm += e * e + n * n;
+        }
+        if norm <= f64::EPSILON {
+            return None;
+        }
+        let (p, q) = (p / norm, q / norm);
+        let tx = ix - (p * ge - q * gn);
+        let ty = iy - (q * ge + p * gn);
+
+        Some(Self {
+            origin,
+            forward: [p, -q, tx, -q, -p, -ty],
+            similarity: true,
+        })
+    }
+
+    /// Maps a ground position onto the chart image.
+    pub fn real_to_pixel(&self, real: &RealCoordinate) -> PixelCoordinate {
+        let [a, b, c, d, e, f] = self.forward;
+        let (east, north) = project(&self.origin, real);
+        PixelCoordinate {
+            x: (a * east + b * north + c) as f32,
+            y: (d * east + e * north + f) as f32,
+        }
+    }
+
+    /// Maps a chart image position back onto the ground.
+    pub fn pixel_to_real(&self, pixel: &PixelCoordinate) -> RealCoordinate {
+        let [a, b, c, d, e, f] = self.forward;
+        let det = a * e - b * d;
+        let px = f64::from(pixel.x) - c;
+        let py = f64::from(pixel.y) - f;
+        let east = (e * px - b * py) / det;
+        let north = (a * py - d * px) / det;
+        unproject(&self.origin, east, north)
+    }
+
+    /// Average chart scale in image pixels per metre on the ground.
+    pub fn pixels_per_metre(&self) -> f64 {
+        let [a, b, _, d, e, _] = self.forward;
+        (a * e - b * d).abs().sqrt()
+    }
+
+    /// Distance in image pixels between each control point and where the fit puts it.
+    pub fn residuals_px(&self, points: &[CoordinatePair]) -> Vec<f32> {
+        points
+            .iter()
+            .filter_map(|p| {
+                let fitted = self.real_to_pixel(p.real.as_ref()?);
+                Some((fitted.x - p.pixels.x).hypot(fitted.y - p.pixels.y))
+            })
+            .collect()
+    }
+
+    /// Ratio of the largest to the smallest scale in any direction. Charts are drawn to the same
+    /// scale both ways, so anything much above 1 means a control point is off.
+    pub fn anisotropy(&self) -> f64 {
+        let [a, b, _, d, e, _] = self.forward;
+        // Singular values of the 2x2 linear part
+        let sum = a * a + b * b + d * d + e * e;
+        let det = (a * e - b * d).abs();
+        let root = (sum * sum - 4.0 * det * det).max(0.0).sqrt();
+        let largest = ((sum + root) / 2.0).sqrt();
+        let smallest = ((sum - root) / 2.0).max(0.0).sqrt();
+        largest / smallest
+    }
+
+    /// Whether east and north come out mirrored, as if the image had been flipped.
+    pub fn is_mirrored(&self) -> bool {
+        let [a, b, _, d, e, _] = self.forward;
+        // Image y grows downwards, so an upright chart has a negative determinant
+        a * e - b * d > 0.0
+    }
+}
+
+/// Equirectangular projection around `origin`, returns `(east, north)` in metres.
+pub fn project(origin: &RealCoordinate, real: &RealCoordinate) -> (f64, f64) {
+    let east = (real.lon - origin.lon).to_radians() * origin.lat.to_radians().cos();
+    let north = (real.lat - origin.lat).to_radians();
+    (east * EARTH_RADIUS_M, north * EARTH_RADIUS_M)
+}
+
+/// Inverse of [`project`].
+pub fn unproject(origin: &RealCoordinate, east: f64, north: f64) -> RealCoordinate {
+    RealCoordinate {
+        lat: origin.lat + (north / EARTH_RADIUS_M).to_degrees(),
+        lon: origin.lon + (east / (EARTH_RADIUS_M * origin.lat.to_radians().cos())).to_degrees(),
+    }
+}
+
+/// Solves a 3x3 linear system with Cramer's rule.
+fn solve3(m: [[f64; 3]; 3], v: [f64; 3]) -> Option<[f64; 3]> {
+    let det = |m: &[[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+
+    // Degenerate (e.g. collinear control points), relative to the magnitude of the matrix
+    let norm = m.iter().flatten().fold(0.0f64, |acc, x| acc.max(x.abs()));
+    let d = det(&m);
+    if norm == 0.0 || d.abs() <= 1e-10 * norm.powi(3) {
+        return None;
+    }
+
+    let mut result = [0.0; 3];
+    for (col, value) in result.iter_mut().enumerate() {
+        let mut replaced = m;
+        for row in 0..3 {
+            replaced[row][col] = v[row];
+        }
+        *value = det(&replaced) / d;
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A chart at 1 px per 10 m, north up, with its origin at `origin`.
+    fn control_point(origin: &RealCoordinate, east_m: f64, north_m: f64) -> CoordinatePair {
+        CoordinatePair {
+            pixels: PixelCoordinate {
+                x: (500.0 + east_m / 10.0) as f32,
+                y: (500.0 - north_m / 10.0) as f32,
+            },
+            real: Some(unproject(origin, east_m, north_m)),
+        }
+    }
+
+    fn origin() -> RealCoordinate {
+        RealCoordinate {
+            lat: 47.0,
+            lon: 8.0,
+        }
+    }
+
+    #[test]
+    fn spread_out_points_get_an_affine_fit() {
+        let origin = origin();
+        let points: Vec<_> = [(0.0, 0.0), (4000.0, 0.0), (0.0, 3000.0), (4000.0, 3000.0)]
+            .into_iter()
+            .map(|(east, north)| control_point(&origin, east, north))
+            .collect();
+        let georeference = Georeference::fit(&points).unwrap();
+        assert!(!georeference.similarity);
+        assert!(georeference
+            .residuals_px(&points)
+            .iter()
+            .all(|&residual| residual < 0.1));
+        assert!((georeference.pixels_per_metre() - 0.1).abs() < 1e-3);
+    }
+
+    #[test]
+    fn points_nearly_in_a_line_get_a_similarity() {
+        // The fixes of a straight-in final, one a little off the course
+        let origin = origin();
+        let points: Vec<_> = [
+            (0.0, 0.0),
+            (5000.0, 150.0),
+            (10_000.0, 0.0),
+            (15_000.0, 0.0),
+        ]
+        .into_iter()
+        .map(|(east, north)| control_point(&origin, east, north))
+        .collect();
+        let georeference = Georeference::fit(&points).unwrap();
+        assert!(georeference.similarity);
+        assert!((georeference.anisotropy() - 1.0).abs() < 1e-9);
+        assert!(!georeference.is_mirrored());
+    }
+
+    #[test]
+    fn spread_ratio_is_width_over_length() {
+        assert_eq!(spread_ratio(&[(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)]), 0.0);
+        let ratio = spread_ratio(&[(-10.0, -1.0), (10.0, -1.0), (-10.0, 1.0), (10.0, 1.0)]);
+        assert!((ratio - 0.1).abs() < 1e-9);
+        assert_eq!(spread_ratio(&[(3.0, 4.0), (3.0, 4.0)]), 0.0);
+    }
+}
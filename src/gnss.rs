@@ -0,0 +1,629 @@
+//! How good the ownship fix is: dilution of precision and satellites from NMEA `GSA`/`GSV`, or
+//! the NIC/NACp integrity and accuracy categories of GDL 90 ownship reports.
+//!
+//! Receivers without integrity monitoring only tell us the geometry, so their accuracy and
+//! protection level are estimates from HDOP and a typical range error, not certified values.
+
+use std::collections::BTreeMap;
+
+use crate::app::LivechartApp;
+use crate::geo::{self, Georeference};
+use crate::utc;
+
+/// One-sigma range error of an unaugmented receiver.
+const UERE_M: f64 = 5.0;
+/// A protection level bounds the error with a very small integrity risk, about six sigma.
+const PROTECTION_SIGMAS: f64 = 6.0;
+/// Quality older than this no longer describes the current fix.
+const STALE_AFTER_S: f64 = 5.0;
+
+/// Horizontal alert limits, tightest first. The fix supports an operation when its protection
+/// level is within the limit.
+const ALERT_LIMITS_M: [(&str, f64); 4] = [
+    ("LPV", 40.0),
+    ("LNAV", 556.0),
+    ("Terminal", 1852.0),
+    ("En route", 3704.0),
+];
+
+/// Active satellites and dilution of precision.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gsa {
+    /// Talker of the constellation the satellites belong to, e.g. `"GP"`.
+    pub talker: String,
+    /// 1 no fix, 2 2D, 3 3D.
+    pub fix_mode: u8,
+    pub prns: Vec<u16>,
+    pub pdop: Option<f32>,
+    pub hdop: Option<f32>,
+    pub vdop: Option<f32>,
+}
+
+/// One sentence of a satellites-in-view sequence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gsv {
+    pub talker: String,
+    pub total: u8,
+    pub number: u8,
+    pub satellites: Vec<Satellite>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Satellite {
+    pub talker: String,
+    pub prn: u16,
+    pub elevation_deg: Option<f32>,
+    pub azimuth_deg: Option<f32>,
+    /// Signal to noise ratio in dB-Hz, `None` when not tracked.
+    pub snr_db: Option<u8>,
+    pub used: bool,
+}
+
+/// `fields` includes the address, e.g. `["GNGSA", "A", "3", ...]`.
+pub fn parse_gsa(talker: &str, fields: &[&str]) -> Option<Gsa> {
+    let field = |i: usize| fields.get(i).copied().unwrap_or("");
+    // NMEA 4.10 combined (GN) sentences name the constellation in a trailing system id
+    let talker = match (talker, field(18)) {
+        ("GN", "1") => "GP",
+        ("GN", "2") => "GL",
+        ("GN", "3") => "GA",
+        ("GN", "4") => "GB",
+        ("GN", "5") => "GQ",
+        ("GN", "6") => "GI",
+        (talker, _) => talker,
+    };
+
+    Some(Gsa {
+        talker: talker.to_owned(),
+        fix_mode: field(2).parse().ok()?,
+        prns: (3..15).filter_map(|i| field(i).parse().ok()).collect(),
+        pdop: field(15).parse().ok(),
+        hdop: field(16).parse().ok(),
+        vdop: field(17).parse().ok(),
+    })
+}
+
+pub fn parse_gsv(talker: &str, fields: &[&str]) -> Option<Gsv> {
+    let field = |i: usize| fields.get(i).copied().unwrap_or("");
+    // Up to four satellites of four fields each, NMEA 4.10 adds a signal id after them
+    let satellites = fields
+        .get(4..)?
+        .chunks_exact(4)
+        .filter_map(|satellite| {
+            Some(Satellite {
+                talker: talker.to_owned(),
+                prn: satellite[0].parse().ok()?,
+                elevation_deg: satellite[1].parse().ok(),
+                azimuth_deg: satellite[2].parse().ok(),
+                snr_db: satellite[3].parse().ok(),
+                used: false,
+            })
+        })
+        .collect();
+
+    Some(Gsv {
+        talker: talker.to_owned(),
+        total: field(1).parse().ok()?,
+        number: field(2).parse().ok()?,
+        satellites,
+    })
+}
+
+/// GDL 90 NACp to the 95% estimated position uncertainty in metres (DO-282B table 2-18).
+pub fn nacp_epu_m(nacp: u8) -> Option<f64> {
+    Some(match nacp {
+        11 => 3.0,
+        10 => 10.0,
+        9 => 30.0,
+        8 => 0.05 * geo::METRES_PER_NM,
+        7 => 0.1 * geo::METRES_PER_NM,
+        6 => 0.3 * geo::METRES_PER_NM,
+        5 => 0.5 * geo::METRES_PER_NM,
+        4 => 1.0 * geo::METRES_PER_NM,
+        3 => 2.0 * geo::METRES_PER_NM,
+        2 => 4.0 * geo::METRES_PER_NM,
+        1 => 10.0 * geo::METRES_PER_NM,
+        _ => return None,
+    })
+}
+
+/// GDL 90 NIC to the horizontal containment radius in metres (DO-282B table 2-14).
+pub fn nic_containment_m(nic: u8) -> Option<f64> {
+    Some(match nic {
+        11 => 7.5,
+        10 => 25.0,
+        9 => 75.0,
+        8 => 0.1 * geo::METRES_PER_NM,
+        7 => 0.2 * geo::METRES_PER_NM,
+        6 => 0.6 * geo::METRES_PER_NM,
+        5 => 1.0 * geo::METRES_PER_NM,
+        4 => 2.0 * geo::METRES_PER_NM,
+        3 => 4.0 * geo::METRES_PER_NM,
+        2 => 8.0 * geo::METRES_PER_NM,
+        1 => 20.0 * geo::METRES_PER_NM,
+        _ => return None,
+    })
+}
+
+/// Everything known about the quality of the ownship fix.
+///
+/// NMEA receivers spread this over several sentences (and GSV over several lines per
+/// constellation), so this also collects them until a picture is complete.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GnssQuality {
+    /// 1 no fix, 2 2D, 3 3D.
+    pub fix_mode: Option<u8>,
+    pub pdop: Option<f32>,
+    pub hdop: Option<f32>,
+    pub vdop: Option<f32>,
+    pub satellites_used: Option<usize>,
+    /// Satellites in view of every constellation.
+    pub satellites: Vec<Satellite>,
+    pub nic: Option<u8>,
+    pub nacp: Option<u8>,
+    /// PRNs in use by talker, from the latest GSA of each constellation.
+    used: BTreeMap<String, Vec<u16>>,
+    /// GSV sequences still being received, by talker.
+    pending: BTreeMap<String, Vec<Satellite>>,
+}
+
+impl GnssQuality {
+    pub fn from_gdl90(nic: u8, nacp: u8) -> Self {
+        Self {
+            nic: (nic > 0).then_some(nic),
+            nacp: (nacp > 0).then_some(nacp),
+            ..Default::default()
+        }
+    }
+
+    pub fn push_gsa(&mut self, gsa: Gsa) {
+        self.fix_mode = Some(gsa.fix_mode);
+        self.pdop = gsa.pdop;
+        self.hdop = gsa.hdop;
+        self.vdop = gsa.vdop;
+        self.used.insert(gsa.talker, gsa.prns);
+        self.satellites_used = Some(self.used.values().map(Vec::len).sum());
+        self.mark_used();
+    }
+
+    /// Returns whether a sequence completed and the satellites in view changed.
+    pub fn push_gsv(&mut self, gsv: Gsv) -> bool {
+        if gsv.number <= 1 {
+            self.pending.remove(&gsv.talker);
+        }
+        let pending = self.pending.entry(gsv.talker.clone()).or_default();
+        pending.extend(gsv.satellites);
+        if gsv.number < gsv.total {
+            return false;
+        }
+
+        let complete = self.pending.remove(&gsv.talker).unwrap_or_default();
+        self.satellites
+            .retain(|satellite| satellite.talker != gsv.talker);
+        self.satellites.extend(complete);
+        self.mark_used();
+        true
+    }
+
+    /// GGA repeats HDOP and the satellite count, enough for receivers without GSA.
+    pub fn push_gga(&mut self, hdop: Option<f32>, satellites: Option<u8>) {
+        if self.used.is_empty() {
+            self.hdop = hdop.or(self.hdop);
+            self.satellites_used = satellites.map(usize::from).or(self.satellites_used);
+        }
+    }
+
+    fn mark_used(&mut self) {
+        for satellite in &mut self.satellites {
+            // Receivers that don't name the constellation report everything as GN
+            satellite.used = [satellite.talker.as_str(), "GN"].iter().any(|talker| {
+                self.used
+                    .get(*talker)
+                    .is_some_and(|prns| prns.contains(&satellite.prn))
+            });
+        }
+    }
+
+    /// Horizontal accuracy (95%) in metres, from NACp or estimated from HDOP.
+    pub fn accuracy_m(&self) -> Option<f64> {
+        self.nacp
+            .and_then(nacp_epu_m)
+            .or_else(|| Some(f64::from(self.hdop?) * UERE_M * 2.0))
+    }
+
+    /// Horizontal protection level in metres, from NIC or estimated from HDOP.
+    pub fn protection_level_m(&self) -> Option<f64> {
+        self.nic
+            .and_then(nic_containment_m)
+            .or_else(|| Some(f64::from(self.hdop?) * UERE_M * PROTECTION_SIGMAS))
+    }
+
+    /// NIC for a GDL 90 report, from the protection level. 0 is unknown.
+    pub fn gdl90_nic(&self) -> u8 {
+        category(self.protection_level_m(), nic_containment_m)
+    }
+
+    /// NACp for a GDL 90 report, from the accuracy. 0 is unknown.
+    pub fn gdl90_nacp(&self) -> u8 {
+        category(self.accuracy_m(), nacp_epu_m)
+    }
+
+    /// Whether the values come from the receiver's integrity monitoring rather than DOP.
+    pub fn is_monitored(&self) -> bool {
+        self.nic.is_some()
+    }
+
+    /// The most demanding operation the protection level allows, with its alert limit. `None`
+    /// without integrity monitoring, since a protection level guessed from HDOP proves nothing.
+    pub fn supported_operation(&self) -> Option<(&'static str, f64)> {
+        if self.fix_mode == Some(1) || !self.is_monitored() {
+            return None;
+        }
+        let protection_level = self.protection_level_m()?;
+        ALERT_LIMITS_M
+            .into_iter()
+            .find(|&(_, limit)| protection_level <= limit)
+    }
+}
+
+/// The best category whose bound is above `value_m`, 0 without one.
+fn category(value_m: Option<f64>, bound_m: fn(u8) -> Option<f64>) -> u8 {
+    let Some(value_m) = value_m else {
+        return 0;
+    };
+    (1..=11)
+        .rev()
+        .find(|&category| bound_m(category).is_some_and(|bound| value_m < bound))
+        .unwrap_or(0)
+}
+
+impl LivechartApp {
+    pub fn ingest_gnss(&mut self, quality: GnssQuality) {
+        self.live.gnss = Some(quality);
+        self.live.gnss_received = Some(utc::now());
+    }
+
+    /// Quality of the current fix, if a source reported one recently.
+    pub(crate) fn fresh_gnss(&self) -> Option<&GnssQuality> {
+        let received = self.live.gnss_received?;
+        (utc::now() - received <= STALE_AFTER_S)
+            .then_some(self.live.gnss.as_ref())
+            .flatten()
+    }
+
+    /// Circle of the 95% horizontal accuracy around the ownship.
+    pub fn draw_accuracy_circle(
+        &self,
+        ui: &egui::Ui,
+        image_response: &egui::Response,
+        image_size: (u32, u32),
+        georeference: &Georeference,
+    ) {
+        let (Some(fix), Some(accuracy)) = (
+            self.displayed_ownship(),
+            self.fresh_gnss().and_then(GnssQuality::accuracy_m),
+        ) else {
+            return;
+        };
+
+        let pos = self.real_to_screen(&fix.position, georeference, image_response, image_size);
+        let screen_per_pixel = image_response.rect.width() / image_size.0 as f32;
+        let radius = (accuracy * georeference.pixels_per_metre()) as f32 * screen_per_pixel;
+        if radius < 2.0 {
+            return;
+        }
+
+        let painter = ui.painter_at(image_response.rect);
+        let color = egui::Color32::from_rgb(0, 150, 255);
+        painter.circle(
+            pos,
+            radius,
+            color.gamma_multiply(0.15),
+            egui::Stroke::new(1.0, color),
+        );
+    }
+
+    pub fn gnss_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.live.gnss_window_open;
+
+        egui::Window::new("GNSS")
+            .open(&mut open)
+            .default_width(320.0)
+            .show(ctx, |ui| {
+                let Some(quality) = self.fresh_gnss() else {
+                    ui.label("No GNSS quality data. NMEA sources need GSA/GSV sentences.");
+                    return;
+                };
+
+                egui::Grid::new("gnss").num_columns(2).show(ui, |ui| {
+                    let optional = |value: Option<f32>| {
+                        value.map_or("–".to_owned(), |value| format!("{value:.1}"))
+                    };
+
+                    ui.label("Fix");
+                    ui.label(match quality.fix_mode {
+                        Some(1) => "None",
+                        Some(2) => "2D",
+                        Some(3) => "3D",
+                        _ => "–",
+                    });
+                    ui.end_row();
+
+                    ui.label("Satellites");
+                    ui.label(format!(
+                        "{} used, {} in view",
+                        quality
+                            .satellites_used
+                            .map_or("–".to_owned(), |used| used.to_string()),
+                        quality.satellites.len()
+                    ));
+                    ui.end_row();
+
+                    ui.label("PDOP / HDOP / VDOP");
+                    ui.label(format!(
+                        "{} / {} / {}",
+                        optional(quality.pdop),
+                        optional(quality.hdop),
+                        optional(quality.vdop)
+                    ));
+                    ui.end_row();
+
+                    if quality.nic.is_some() || quality.nacp.is_some() {
+                        ui.label("NIC / NACp");
+                        ui.label(format!(
+                            "{} / {}",
+                            quality.nic.map_or("–".to_owned(), |nic| nic.to_string()),
+                            quality.nacp.map_or("–".to_owned(), |nacp| nacp.to_string())
+                        ));
+                        ui.end_row();
+                    }
+
+                    let estimated = if quality.is_monitored() {
+                        ""
+                    } else {
+                        " (estimated)"
+                    };
+                    ui.label("Accuracy (95%)");
+                    ui.label(
+                        quality
+                            .accuracy_m()
+                            .map_or("–".to_owned(), |a| format!("{a:.0} m{estimated}")),
+                    );
+                    ui.end_row();
+
+                    ui.label("HPL");
+                    ui.label(
+                        quality
+                            .protection_level_m()
+                            .map_or("–".to_owned(), |p| format!("{p:.0} m{estimated}")),
+                    );
+                    ui.end_row();
+
+                    ui.label("Good for");
+                    match quality.supported_operation() {
+                        Some((operation, limit)) => {
+                            ui.label(format!("{operation} (HAL {limit:.0} m)"));
+                        }
+                        None if !quality.is_monitored() => {
+                            ui.weak("Not integrity-monitored");
+                        }
+                        None => {
+                            ui.colored_label(ui.visuals().error_fg_color, "Nothing");
+                        }
+                    }
+                    ui.end_row();
+                });
+
+                if !quality.satellites.is_empty() {
+                    ui.separator();
+                    sky_plot(ui, &quality.satellites);
+                }
+            });
+        self.live.gnss_window_open = open;
+    }
+}
+
+/// Satellite positions looking up, north at the top, coloured by signal strength. Filled
+/// satellites are used in the fix.
+fn sky_plot(ui: &mut egui::Ui, satellites: &[Satellite]) {
+    let size = ui.available_width().min(260.0);
+    let (response, painter) = ui.allocate_painter(egui::vec2(size, size), egui::Sense::hover());
+    let center = response.rect.center();
+    let radius = size / 2.0 - 14.0;
+    let grid = egui::Stroke::new(1.0, ui.visuals().weak_text_color());
+
+    // Horizon, 30° and 60° elevation
+    for elevation in [0.0, 30.0, 60.0] {
+        painter.circle_stroke(center, radius * (90.0 - elevation) / 90.0, grid);
+    }
+    for (label, azimuth) in [("N", 0.0f32), ("E", 90.0), ("S", 180.0), ("W", 270.0)] {
+        let direction = egui::Vec2::angled((azimuth - 90.0).to_radians());
+        painter.line_segment([center, center + direction * radius], grid);
+        painter.text(
+            center + direction * (radius + 8.0),
+            egui::Align2::CENTER_CENTER,
+            label,
+            egui::FontId::proportional(11.0),
+            ui.visuals().text_color(),
+        );
+    }
+
+    for satellite in satellites {
+        let (Some(elevation), Some(azimuth)) = (satellite.elevation_deg, satellite.azimuth_deg)
+        else {
+            continue;
+        };
+        let direction = egui::Vec2::angled((azimuth - 90.0).to_radians());
+        let pos = center + direction * radius * (90.0 - elevation.clamp(0.0, 90.0)) / 90.0;
+
+        let color = match satellite.snr_db {
+            Some(snr) if snr >= 40 => egui::Color32::from_rgb(0, 180, 0),
+            Some(snr) if snr >= 30 => egui::Color32::from_rgb(200, 180, 0),
+            Some(_) => egui::Color32::from_rgb(220, 60, 40),
+            None => egui::Color32::GRAY,
+        };
+        if satellite.used {
+            painter.circle_filled(pos, 6.0, color);
+        } else {
+            painter.circle_stroke(pos, 6.0, egui::Stroke::new(1.5, color));
+        }
+        painter.text(
+            pos + egui::vec2(0.0, 8.0),
+            egui::Align2::CENTER_TOP,
+            satellite.prn.to_string(),
+            egui::FontId::monospace(9.0),
+            ui.visuals().text_color(),
+        );
+
+        if response
+            .hover_pos()
+            .is_some_and(|pointer| pointer.distance(pos) < 8.0)
+        {
+            response.clone().on_hover_text(format!(
+                "{} {}: elevation {elevation:.0}°, azimuth {azimuth:.0}°, SNR {}",
+                satellite.talker,
+                satellite.prn,
+                satellite
+                    .snr_db
+                    .map_or("–".to_owned(), |snr| format!("{snr} dB-Hz"))
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(sentence: &str) -> Vec<&str> {
+        sentence.split(',').collect()
+    }
+
+    #[test]
+    fn reads_gsa_and_the_nmea_4_10_system_id() {
+        let gsa = parse_gsa(
+            "GN",
+            &fields("GNGSA,A,3,80,71,73,79,69,,,,,,,,1.83,1.09,1.47,2"),
+        )
+        .unwrap();
+        assert_eq!(gsa.talker, "GL");
+        assert_eq!(gsa.fix_mode, 3);
+        assert_eq!(gsa.prns, [80, 71, 73, 79, 69]);
+        assert_eq!(
+            (gsa.pdop, gsa.hdop, gsa.vdop),
+            (Some(1.83), Some(1.09), Some(1.47))
+        );
+
+        // Older receivers: no system id, empty DOPs without a fix
+        let gsa = parse_gsa("GP", &fields("GPGSA,A,1,,,,,,,,,,,,,,,")).unwrap();
+        assert_eq!(gsa.talker, "GP");
+        assert_eq!(gsa.fix_mode, 1);
+        assert!(gsa.prns.is_empty());
+        assert_eq!(gsa.hdop, None);
+        assert_eq!(parse_gsa("GP", &fields("GPGSA,A,,,")), None);
+    }
+
+    #[test]
+    fn collects_gsv_sequences() {
+        let first = parse_gsv(
+            "GP",
+            &fields("GPGSV,2,1,06,02,17,308,41,12,07,344,39,14,22,228,45,25,72,051,"),
+        )
+        .unwrap();
+        assert_eq!((first.total, first.number), (2, 1));
+        assert_eq!(first.satellites.len(), 4);
+        assert_eq!(
+            first.satellites[0],
+            Satellite {
+                talker: "GP".to_owned(),
+                prn: 2,
+                elevation_deg: Some(17.0),
+                azimuth_deg: Some(308.0),
+                snr_db: Some(41),
+                used: false,
+            }
+        );
+        // Not tracked
+        assert_eq!(first.satellites[3].snr_db, None);
+        // NMEA 4.10 signal id after the last satellite
+        let second = parse_gsv("GP", &fields("GPGSV,2,2,06,29,36,178,44,31,,,,1")).unwrap();
+        assert_eq!(second.satellites.len(), 2);
+        assert_eq!(second.satellites[1].elevation_deg, None);
+
+        let mut quality = GnssQuality::default();
+        quality
+            .push_gsa(parse_gsa("GP", &fields("GPGSA,A,3,02,14,29,,,,,,,,,,2.1,1.2,1.7")).unwrap());
+        assert!(!quality.push_gsv(first));
+        assert!(quality.satellites.is_empty());
+        assert!(quality.push_gsv(second));
+        assert_eq!(quality.satellites.len(), 6);
+        assert_eq!(quality.satellites_used, Some(3));
+        let used: Vec<u16> = quality
+            .satellites
+            .iter()
+            .filter(|satellite| satellite.used)
+            .map(|satellite| satellite.prn)
+            .collect();
+        assert_eq!(used, [2, 14, 29]);
+    }
+
+    #[test]
+    fn nic_and_nacp_follow_the_do_282b_tables() {
+        assert_eq!(nacp_epu_m(11), Some(3.0));
+        assert_eq!(nacp_epu_m(9), Some(30.0));
+        assert_eq!(nacp_epu_m(8), Some(0.05 * geo::METRES_PER_NM));
+        assert_eq!(nacp_epu_m(1), Some(18_520.0));
+        assert_eq!(nacp_epu_m(0), None);
+        assert_eq!(nacp_epu_m(12), None);
+
+        assert_eq!(nic_containment_m(11), Some(7.5));
+        assert_eq!(nic_containment_m(8), Some(0.1 * geo::METRES_PER_NM));
+        assert_eq!(nic_containment_m(1), Some(37_040.0));
+        assert_eq!(nic_containment_m(0), None);
+
+        // Every category bound is tighter than the one below it
+        for category in 2..=11 {
+            assert!(nacp_epu_m(category) < nacp_epu_m(category - 1));
+            assert!(nic_containment_m(category) < nic_containment_m(category - 1));
+        }
+    }
+
+    #[test]
+    fn category_is_the_best_bound_above_the_value() {
+        assert_eq!(category(Some(2.0), nacp_epu_m), 11);
+        assert_eq!(category(Some(3.0), nacp_epu_m), 10);
+        assert_eq!(category(Some(25.0), nacp_epu_m), 9);
+        assert_eq!(category(Some(100.0), nacp_epu_m), 7);
+        assert_eq!(category(Some(20_000.0), nacp_epu_m), 0);
+        assert_eq!(category(None, nacp_epu_m), 0);
+
+        // HDOP 1.0: 10 m accuracy and 30 m protection level estimated
+        let quality = GnssQuality {
+            hdop: Some(1.0),
+            ..Default::default()
+        };
+        assert_eq!(quality.gdl90_nacp(), 9);
+        assert_eq!(quality.gdl90_nic(), 9);
+    }
+
+    #[test]
+    fn only_monitored_fixes_support_an_operation() {
+        let estimated = GnssQuality {
+            fix_mode: Some(3),
+            hdop: Some(0.6),
+            ..Default::default()
+        };
+        assert!(!estimated.is_monitored());
+        assert_eq!(estimated.supported_operation(), None);
+
+        assert_eq!(
+            GnssQuality::from_gdl90(10, 9).supported_operation(),
+            Some(("LPV", 40.0))
+        );
+        assert_eq!(
+            GnssQuality::from_gdl90(7, 8).supported_operation(),
+            Some(("LNAV", 556.0))
+        );
+        assert_eq!(GnssQuality::from_gdl90(0, 9).supported_operation(), None);
+    }
+}
@@ -1,5 +1,35 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
+mod airspace;
 pub mod app;
 mod components;
+mod conflict;
+mod deviation;
+mod diagnostics;
+mod dump1090;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod emulator;
+mod feeds;
+mod fix_search;
+mod flarm;
+mod fleet;
+mod flightplan;
+mod gdl90;
+mod geo;
+mod gnss;
+mod library;
+mod navdb;
+mod nmea;
+mod ogn;
+mod ourairports;
+mod outputs;
+mod ownship;
+mod procedures;
+mod recording;
+mod replay;
+mod sbs1;
+pub mod simulator;
 mod structs;
+mod traffic;
+mod utc;
+mod xplane;
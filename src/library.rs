@@ -0,0 +1,270 @@
+//! The chart library: every chart the user has georeferenced, so we can switch between them and
+//! pick the one that covers the ownship.
+//!
+//! The active chart's control points live in `LiveChartAppData::points` while it is on screen
+//! and are written back to its library entry when another chart is opened.
+
+use crate::app::{LivechartApp, CHART_FILE};
+use crate::geo::Georeference;
+use crate::structs::{CoordinatePair, RealCoordinate};
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct LibraryChart {
+    pub name: String,
+    pub path: String,
+    /// Image size in pixels, read once when the chart is added.
+    pub size: (u32, u32),
+    pub points: Vec<CoordinatePair>,
+    /// Airport identifier, for looking up its fixes.
+    #[serde(default)]
+    pub airport: String,
+    /// Approach the chart shows, if it is a plate.
+    #[serde(default)]
+    pub procedure: String,
+}
+
+impl LibraryChart {
+    /// Whether `position` falls on the chart image.
+    pub fn covers(&self, position: &RealCoordinate) -> bool {
+        Georeference::fit(&self.points).is_some_and(|georeference| {
+            let pixel = georeference.real_to_pixel(position);
+            (0.0..self.size.0 as f32).contains(&pixel.x)
+                && (0.0..self.size.1 as f32).contains(&pixel.y)
+        })
+    }
+
+    /// Pixels per metre, higher means a more detailed chart.
+    pub fn detail(&self) -> f64 {
+        Georeference::fit(&self.points).map_or(0.0, |g| g.pixels_per_metre())
+    }
+}
+
+pub fn chart_name(path: &str) -> String {
+    std::path::Path::new(path)
+        .file_stem()
+        .map_or_else(|| path.to_owned(), |n| n.to_string_lossy().into_owned())
+}
+
+impl LivechartApp {
+    /// Path of the chart on screen. Falls back to the built-in chart.
+    pub fn active_chart_path(&self) -> &str {
+        if self.data.chart_path.is_empty() {
+            CHART_FILE
+        } else {
+            &self.data.chart_path
+        }
+    }
+
+    /// Image size of the chart on screen, cached per path.
+    pub fn active_chart_size(&mut self) -> Result<(u32, u32), String> {
+        let path = self.active_chart_path().to_owned();
+        if let Some(size) = self.live.chart_sizes.get(&path) {
+            return Ok(*size);
+        }
+
+        let size =
+            image::image_dimensions(&path).map_err(|e| format!("Could not open {path}: {e}"))?;
+        self.live.chart_sizes.insert(path, size);
+        Ok(size)
+    }
+
+    /// Writes the active chart's control points back into its library entry, adding it if needed.
+    fn store_active_chart(&mut self) {
+        let path = self.active_chart_path().to_owned();
+        let Ok(size) = self.active_chart_size() else {
+            return;
+        };
+
+        match self
+            .data
+            .library
+            .iter_mut()
+            .find(|chart| chart.path == path)
+        {
+            Some(chart) => {
+                chart.points = self.data.points.clone();
+                chart.airport = self.data.chart_airport.clone();
+                chart.procedure = self.data.chart_procedure.clone();
+            }
+            None => self.data.library.push(LibraryChart {
+                name: chart_name(&path),
+                path,
+                size,
+                points: self.data.points.clone(),
+                airport: self.data.chart_airport.clone(),
+                procedure: self.data.chart_procedure.clone(),
+            }),
+        }
+    }
+
+    pub fn open_chart(&mut self, index: usize) {
+        if self.data.library.get(index).map(|c| c.path.as_str()) == Some(self.active_chart_path()) {
+            return;
+        }
+
+        self.store_active_chart();
+        let Some(chart) = self.data.library.get(index) else {
+            return;
+        };
+        self.data.chart_path = chart.path.clone();
+        self.data.points = chart.points.clone();
+        self.data.chart_airport = chart.airport.clone();
+        self.data.chart_procedure = chart.procedure.clone();
+        self.live.procedure_georef = None;
+        self.data.view_state = None;
+    }
+
+    /// Puts the chart at `path` on screen, taking its control points from the library when it is
+    /// there and from `points` otherwise.
+    pub fn show_chart(&mut self, path: &str, points: &[CoordinatePair]) {
+        if path == self.active_chart_path() {
+            return;
+        }
+
+        if let Some(index) = self
+            .data
+            .library
+            .iter()
+            .position(|chart| chart.path == path)
+        {
+            self.open_chart(index);
+            if self.data.points.is_empty() {
+                self.data.points = points.to_vec();
+            }
+            return;
+        }
+
+        self.store_active_chart();
+        self.data.chart_path = path.to_owned();
+        self.data.points = points.to_vec();
+        self.data.chart_airport.clear();
+        self.data.chart_procedure.clear();
+        self.live.procedure_georef = None;
+        self.data.view_state = None;
+    }
+
+    /// The most detailed library chart, other than the active one, that covers `position`.
+    pub fn best_chart_for(&self, position: &RealCoordinate) -> Option<usize> {
+        let active = self.active_chart_path();
+        self.data
+            .library
+            .iter()
+            .enumerate()
+            .filter(|(_, chart)| chart.path != active && chart.covers(position))
+            .max_by(|(_, a), (_, b)| a.detail().total_cmp(&b.detail()))
+            .map(|(index, _)| index)
+    }
+
+    /// Whether the ownship is on the active chart. `None` without a fix or georeference.
+    pub fn ownship_on_chart(&mut self) -> Option<bool> {
+        let position = self.live.ownship.as_ref()?.position.clone();
+        let size = self.active_chart_size().ok()?;
+        let pixel = Georeference::fit(&self.data.points)?.real_to_pixel(&position);
+        Some((0.0..size.0 as f32).contains(&pixel.x) && (0.0..size.1 as f32).contains(&pixel.y))
+    }
+
+    /// Switches to a better chart when the ownship has left the active one, if enabled.
+    pub fn auto_select_chart(&mut self) {
+        if !self.data.auto_select_chart || self.ownship_on_chart() != Some(false) {
+            return;
+        }
+
+        let Some(position) = self.live.ownship.as_ref().map(|fix| fix.position.clone()) else {
+            return;
+        };
+        if let Some(index) = self.best_chart_for(&position) {
+            self.open_chart(index);
+        }
+    }
+
+    pub fn library_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.live.library_window_open;
+        egui::Window::new("Charts")
+            .open(&mut open)
+            .default_width(320.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Image:");
+                    ui.text_edit_singleline(&mut self.live.library_new_path);
+                    if ui.button("Add").clicked() {
+                        self.add_library_chart();
+                    }
+                });
+                if let Some(error) = &self.live.library_error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+
+                ui.checkbox(
+                    &mut self.data.auto_select_chart,
+                    "Switch to the chart covering the ownship",
+                )
+                .on_hover_text("When the ownship leaves the chart, open the most detailed chart that covers it");
+
+                ui.separator();
+                let active = self.active_chart_path().to_owned();
+                let mut action = None;
+                for (index, chart) in self.data.library.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        let georeferenced = Georeference::fit(&chart.points).is_some();
+                        ui.label(if chart.path == active {
+                            format!("▶ {}", chart.name)
+                        } else {
+                            chart.name.clone()
+                        })
+                        .on_hover_text(&chart.path);
+                        if !georeferenced {
+                            ui.weak("(not georeferenced)");
+                        }
+
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if ui.small_button("🗑").clicked() {
+                                action = Some((index, false));
+                            }
+                            if chart.path != active && ui.small_button("Open").clicked() {
+                                action = Some((index, true));
+                            }
+                        });
+                    });
+                }
+
+                if self.data.library.is_empty() {
+                    ui.label("Add a chart image to start the library.");
+                }
+
+                match action {
+                    Some((index, true)) => self.open_chart(index),
+                    Some((index, false)) => {
+                        self.data.library.remove(index);
+                    }
+                    None => {}
+                }
+            });
+        self.live.library_window_open = open;
+    }
+
+    fn add_library_chart(&mut self) {
+        let path = self.live.library_new_path.trim().to_owned();
+        if self.data.library.iter().any(|chart| chart.path == path) {
+            self.live.library_error = Some("This chart is already in the library".to_owned());
+            return;
+        }
+
+        match image::image_dimensions(&path) {
+            Ok(size) => {
+                // Make sure the chart on screen isn't lost when switching away from it
+                self.store_active_chart();
+                self.data.library.push(LibraryChart {
+                    name: chart_name(&path),
+                    path,
+                    size,
+                    points: Vec::new(),
+                    airport: String::new(),
+                    procedure: String::new(),
+                });
+                self.live.library_new_path.clear();
+                self.live.library_error = None;
+            }
+            Err(e) => self.live.library_error = Some(format!("Could not open {path}: {e}")),
+        }
+    }
+}
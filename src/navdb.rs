@@ -0,0 +1,1147 @@
+//! Navigation data from ARINC 424 files, like the FAA's CIFP (`FAACIFP18`): airports, runways,
+//! navaids, waypoints and approach procedures, indexed by identifier.
+//!
+//! Records are 132 fixed columns. Column numbers below are 1-based, as in the specification.
+//! Only primary records are read, continuation records add nothing we use.
+//!
+//! Outside the US the same database is filled from OurAirports, see [`crate::ourairports`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+
+use crate::app::LivechartApp;
+use crate::geo;
+use crate::structs::{CoordinatePair, PixelCoordinate, RealCoordinate};
+
+/// Fix lookups only offer fixes this close to the chart, when we know where the chart is.
+const MAX_LOOKUP_DISTANCE_NM: f64 = 150.0;
+const MAX_LOOKUP_RESULTS: usize = 12;
+/// Fixes from different sources closer than this are the same fix.
+const DUPLICATE_DISTANCE_M: f64 = 2000.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FixKind {
+    Airport {
+        elevation_ft: Option<i32>,
+        /// Magnetic variation, east positive. Procedure courses are magnetic.
+        variation_deg: Option<f32>,
+    },
+    /// The landing threshold.
+    Runway {
+        length_ft: Option<u32>,
+        /// Magnetic unless `bearing_true`.
+        bearing_deg: Option<f32>,
+        bearing_true: bool,
+        threshold_elevation_ft: Option<i32>,
+        displaced_threshold_ft: Option<u32>,
+        /// Threshold crossing height of the glide path.
+        crossing_height_ft: Option<u32>,
+        width_ft: Option<u32>,
+    },
+    /// VOR, VORTAC, DME, TACAN or ILS DME.
+    Vhf {
+        frequency_mhz: Option<f32>,
+        class: String,
+    },
+    Ndb {
+        frequency_khz: Option<f32>,
+    },
+    Waypoint,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavFix {
+    pub ident: String,
+    /// ICAO region, e.g. `K2`. Identifiers are only unique within a region.
+    pub region: String,
+    /// Airport the fix belongs to, for runways and terminal fixes.
+    pub airport: Option<String>,
+    pub name: String,
+    pub position: RealCoordinate,
+    pub kind: FixKind,
+}
+
+impl NavFix {
+    pub fn kind_label(&self) -> &'static str {
+        match &self.kind {
+            FixKind::Airport { .. } => "Airport",
+            FixKind::Runway { .. } => "Runway",
+            FixKind::Vhf { .. } => "VHF navaid",
+            FixKind::Ndb { .. } => "NDB",
+            FixKind::Waypoint if self.airport.is_some() => "Terminal waypoint",
+            FixKind::Waypoint => "Waypoint",
+        }
+    }
+}
+
+/// Where a procedure leg points to: the fix's identifier and region plus the section and
+/// subsection of the record that defines it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixRef {
+    pub ident: String,
+    pub region: String,
+    pub section: char,
+    pub subsection: char,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AltitudeConstraint {
+    At(i32),
+    AtOrAbove(i32),
+    AtOrBelow(i32),
+    /// Between the lower and the upper altitude.
+    Between(i32, i32),
+    /// Glide slope intercept and similar, at or above the first.
+    Other(i32),
+}
+
+/// One leg of an approach procedure.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcedureLeg {
+    /// e.g. `I16R` or `R34`.
+    pub procedure: String,
+    /// `A` approach transition, `I` ILS, `R` RNAV, `L` localizer, ...
+    pub route_type: char,
+    /// Transition identifier, empty for the final approach.
+    pub transition: String,
+    pub sequence: u16,
+    pub fix: Option<FixRef>,
+    /// Waypoint description code, e.g. `E  F` marks the final approach fix.
+    pub description: String,
+    /// `L` or `R`.
+    pub turn_direction: Option<char>,
+    /// ARINC 424 path and terminator, e.g. `TF`, `CF`, `RF`, `HM`.
+    pub path_terminator: String,
+    pub recommended_navaid: Option<FixRef>,
+    /// Magnetic unless `course_true`.
+    pub course_deg: Option<f32>,
+    pub course_true: bool,
+    pub distance_nm: Option<f32>,
+    /// Holding leg time instead of a distance.
+    pub hold_time_min: Option<f32>,
+    pub theta_deg: Option<f32>,
+    pub rho_nm: Option<f32>,
+    /// Radius of RF legs.
+    pub arc_radius_nm: Option<f32>,
+    /// Centre of RF legs.
+    pub center_fix: Option<FixRef>,
+    pub altitude: Option<AltitudeConstraint>,
+    /// Glide path, negative when descending.
+    pub vertical_angle_deg: Option<f32>,
+}
+
+impl ProcedureLeg {
+    /// The final approach fix, as flagged in the waypoint description code.
+    pub fn is_final_approach_fix(&self) -> bool {
+        self.description.chars().nth(3) == Some('F')
+    }
+
+    /// The missed approach point.
+    pub fn is_missed_approach_point(&self) -> bool {
+        self.description.chars().nth(3) == Some('M')
+    }
+}
+
+/// The parsed file, with fixes indexed by identifier and procedures by airport.
+#[derive(Debug, Default)]
+pub struct NavDatabase {
+    pub fixes: Vec<NavFix>,
+    /// Indices into `fixes`, by identifier.
+    by_ident: HashMap<String, Vec<usize>>,
+    /// Legs in sequence, by airport and procedure identifier.
+    pub procedures: BTreeMap<String, BTreeMap<String, Vec<ProcedureLeg>>>,
+    /// AIRAC cycle, e.g. `2510`.
+    pub cycle: Option<String>,
+    /// Records that were skipped as malformed.
+    pub skipped: usize,
+}
+
+/// Columns `start..=end`, trimmed.
+fn columns(line: &str, start: usize, end: usize) -> &str {
+    line.get(start - 1..end.min(line.len()))
+        .unwrap_or("")
+        .trim()
+}
+
+fn column(line: &str, position: usize) -> char {
+    line.as_bytes()
+        .get(position - 1)
+        .map_or(' ', |&byte| byte as char)
+}
+
+/// `N39513881` (hemisphere, degrees, minutes, seconds, hundredths) to decimal degrees.
+fn parse_latitude(text: &str) -> Option<f64> {
+    parse_angle(text, 2)
+}
+
+/// `W104450577`, like the latitude with three degree digits.
+fn parse_longitude(text: &str) -> Option<f64> {
+    parse_angle(text, 3)
+}
+
+fn parse_angle(text: &str, degree_digits: usize) -> Option<f64> {
+    let sign = match text.get(..1)? {
+        "N" | "E" => 1.0,
+        "S" | "W" => -1.0,
+        _ => return None,
+    };
+    let number = |range: std::ops::Range<usize>| -> Option<f64> { text.get(range)?.parse().ok() };
+    let d = degree_digits + 1;
+    let degrees = number(1..d)?;
+    let minutes = number(d..d + 2)?;
+    let seconds = number(d + 2..d + 4)?;
+    let hundredths = number(d + 4..d + 6)?;
+    Some(sign * (degrees + minutes / 60.0 + (seconds + hundredths / 100.0) / 3600.0))
+}
+
+fn parse_position(line: &str) -> Option<RealCoordinate> {
+    Some(RealCoordinate {
+        lat: parse_latitude(columns(line, 33, 41))?,
+        lon: parse_longitude(columns(line, 42, 51))?,
+    })
+}
+
+/// Numbers with implied decimals, e.g. `0900` with one decimal is 90.0.
+fn parse_scaled(text: &str, decimals: i32) -> Option<f32> {
+    let value: i32 = text.parse().ok()?;
+    Some(value as f32 / 10f32.powi(decimals))
+}
+
+/// `E0080` is 8.0° east, `W0123` 12.3° west. `T` means the airport uses true courses.
+fn parse_variation(text: &str) -> Option<f32> {
+    let sign = match text.get(..1)? {
+        "E" => 1.0,
+        "W" => -1.0,
+        "T" => return Some(0.0),
+        _ => return None,
+    };
+    Some(sign * parse_scaled(text.get(1..)?, 1)?)
+}
+
+/// Feet or a flight level (`FL180`).
+fn parse_altitude(text: &str) -> Option<i32> {
+    match text.strip_prefix("FL") {
+        Some(level) => Some(level.parse::<i32>().ok()? * 100),
+        None => text.parse().ok(),
+    }
+}
+
+/// Courses and bearings: `0900` is 090.0° magnetic, `090T` is 090° true.
+fn parse_bearing(text: &str) -> (Option<f32>, bool) {
+    match text.strip_suffix('T') {
+        Some(degrees) => (degrees.parse().ok(), true),
+        None => (parse_scaled(text, 1), false),
+    }
+}
+
+fn fix_ref(ident: &str, region: &str, section: char, subsection: char) -> Option<FixRef> {
+    (!ident.is_empty()).then(|| FixRef {
+        ident: ident.to_owned(),
+        region: region.to_owned(),
+        section,
+        subsection,
+    })
+}
+
+/// Whether this is the first record of an entry rather than a continuation.
+fn is_primary(continuation: char) -> bool {
+    matches!(continuation, '0' | '1')
+}
+
+enum Record {
+    Fix(NavFix),
+    Leg {
+        airport: String,
+        leg: Box<ProcedureLeg>,
+    },
+}
+
+/// `None` for malformed records, `Some(None)` for records we don't use.
+fn parse_record(line: &str) -> Option<Option<Record>> {
+    // Standard and tailored records, everything else (headers) is skipped
+    if !matches!(column(line, 1), 'S' | 'T') || line.len() < 123 {
+        return Some(None);
+    }
+
+    let section = column(line, 5);
+    // Airport and heliport records keep their subsection in column 13
+    let subsection = match section {
+        'P' | 'H' => column(line, 13),
+        _ => column(line, 6),
+    };
+
+    match (section, subsection) {
+        ('D', ' ') => parse_vhf(line),
+        ('D', 'B') | ('P', 'N') => parse_ndb(line, section == 'P'),
+        ('E', 'A') | ('P', 'C') => parse_waypoint(line, section == 'P'),
+        ('P', 'A') => parse_airport(line),
+        ('P', 'G') => parse_runway(line),
+        ('P', 'F') => parse_leg(line),
+        _ => Some(None),
+    }
+}
+
+fn parse_vhf(line: &str) -> Option<Option<Record>> {
+    if !is_primary(column(line, 22)) {
+        return Some(None);
+    }
+    // DME-only stations have their position in the DME columns
+    let position = parse_position(line).or_else(|| {
+        Some(RealCoordinate {
+            lat: parse_latitude(columns(line, 56, 64))?,
+            lon: parse_longitude(columns(line, 65, 74))?,
+        })
+    })?;
+    let airport = columns(line, 7, 10);
+
+    Some(Some(Record::Fix(NavFix {
+        ident: columns(line, 14, 17).to_owned(),
+        region: columns(line, 20, 21).to_owned(),
+        airport: (!airport.is_empty()).then(|| airport.to_owned()),
+        name: columns(line, 94, 123).to_owned(),
+        position,
+        kind: FixKind::Vhf {
+            frequency_mhz: parse_scaled(columns(line, 23, 27), 2),
+            class: columns(line, 28, 32).to_owned(),
+        },
+    })))
+}
+
+fn parse_ndb(line: &str, terminal: bool) -> Option<Option<Record>> {
+    if !is_primary(column(line, 22)) {
+        return Some(None);
+    }
+    let airport = columns(line, 7, 10);
+
+    Some(Some(Record::Fix(NavFix {
+        ident: columns(line, 14, 17).to_owned(),
+        region: columns(line, 20, 21).to_owned(),
+        airport: (terminal && !airport.is_empty()).then(|| airport.to_owned()),
+        name: columns(line, 94, 123).to_owned(),
+        position: parse_position(line)?,
+        kind: FixKind::Ndb {
+            frequency_khz: parse_scaled(columns(line, 23, 27), 1),
+        },
+    })))
+}
+
+fn parse_waypoint(line: &str, terminal: bool) -> Option<Option<Record>> {
+    if !is_primary(column(line, 22)) {
+        return Some(None);
+    }
+
+    Some(Some(Record::Fix(NavFix {
+        ident: columns(line, 14, 18).to_owned(),
+        region: columns(line, 20, 21).to_owned(),
+        airport: terminal.then(|| columns(line, 7, 10).to_owned()),
+        name: columns(line, 99, 123).to_owned(),
+        position: parse_position(line)?,
+        kind: FixKind::Waypoint,
+    })))
+}
+
+fn parse_airport(line: &str) -> Option<Option<Record>> {
+    if !is_primary(column(line, 22)) {
+        return Some(None);
+    }
+
+    Some(Some(Record::Fix(NavFix {
+        ident: columns(line, 7, 10).to_owned(),
+        region: columns(line, 11, 12).to_owned(),
+        airport: None,
+        name: columns(line, 94, 123).to_owned(),
+        position: parse_position(line)?,
+        kind: FixKind::Airport {
+            elevation_ft: columns(line, 57, 61).parse().ok(),
+            variation_deg: parse_variation(columns(line, 52, 56)),
+        },
+    })))
+}
+
+fn parse_runway(line: &str) -> Option<Option<Record>> {
+    if !is_primary(column(line, 22)) {
+        return Some(None);
+    }
+    let (bearing_deg, bearing_true) = parse_bearing(columns(line, 28, 31));
+
+    Some(Some(Record::Fix(NavFix {
+        ident: columns(line, 14, 18).to_owned(),
+        region: columns(line, 11, 12).to_owned(),
+        airport: Some(columns(line, 7, 10).to_owned()),
+        name: columns(line, 102, 123).to_owned(),
+        position: parse_position(line)?,
+        kind: FixKind::Runway {
+            length_ft: columns(line, 23, 27).parse().ok(),
+            bearing_deg,
+            bearing_true,
+            threshold_elevation_ft: columns(line, 67, 71).parse().ok(),
+            displaced_threshold_ft: columns(line, 72, 75).parse().ok(),
+            crossing_height_ft: columns(line, 76, 77).parse().ok(),
+            width_ft: columns(line, 78, 80).parse().ok(),
+        },
+    })))
+}
+
+fn parse_leg(line: &str) -> Option<Option<Record>> {
+    if !is_primary(column(line, 39)) {
+        return Some(None);
+    }
+
+    let (course_deg, course_true) = parse_bearing(columns(line, 71, 74));
+    let distance = columns(line, 75, 78);
+    let (distance_nm, hold_time_min) = match distance.strip_prefix('T') {
+        Some(time) => (None, parse_scaled(time, 1)),
+        None => (parse_scaled(distance, 1), None),
+    };
+
+    let altitude_1 = parse_altitude(columns(line, 85, 89));
+    let altitude_2 = parse_altitude(columns(line, 90, 94));
+    let altitude = altitude_1.map(|first| match (column(line, 83), altitude_2) {
+        ('+', _) => AltitudeConstraint::AtOrAbove(first),
+        ('-', _) => AltitudeConstraint::AtOrBelow(first),
+        ('B', Some(second)) => AltitudeConstraint::Between(second.min(first), second.max(first)),
+        (' ' | '@', _) => AltitudeConstraint::At(first),
+        _ => AltitudeConstraint::Other(first),
+    });
+
+    let turn_direction = column(line, 44);
+    Some(Some(Record::Leg {
+        airport: columns(line, 7, 10).to_owned(),
+        leg: Box::new(ProcedureLeg {
+            procedure: columns(line, 14, 19).to_owned(),
+            route_type: column(line, 20),
+            transition: columns(line, 21, 25).to_owned(),
+            sequence: columns(line, 27, 29).parse().ok()?,
+            fix: fix_ref(
+                columns(line, 30, 34),
+                columns(line, 35, 36),
+                column(line, 37),
+                column(line, 38),
+            ),
+            description: line.get(39..43).unwrap_or("").to_owned(),
+            turn_direction: matches!(turn_direction, 'L' | 'R').then_some(turn_direction),
+            path_terminator: columns(line, 48, 49).to_owned(),
+            recommended_navaid: fix_ref(
+                columns(line, 51, 54),
+                columns(line, 55, 56),
+                column(line, 79),
+                column(line, 80),
+            ),
+            course_deg,
+            course_true,
+            distance_nm,
+            hold_time_min,
+            theta_deg: parse_scaled(columns(line, 63, 66), 1),
+            rho_nm: parse_scaled(columns(line, 67, 70), 1),
+            arc_radius_nm: parse_scaled(columns(line, 57, 62), 3),
+            center_fix: fix_ref(
+                columns(line, 107, 111),
+                columns(line, 113, 114),
+                column(line, 115),
+                column(line, 116),
+            ),
+            altitude,
+            vertical_angle_deg: parse_scaled(columns(line, 103, 106), 2),
+        }),
+    }))
+}
+
+impl NavDatabase {
+    pub fn parse(text: &str) -> Self {
+        let mut database = Self::default();
+        for line in text.lines() {
+            match parse_record(line) {
+                Some(Some(Record::Fix(fix))) => database.fixes.push(fix),
+                Some(Some(Record::Leg { airport, leg })) => database
+                    .procedures
+                    .entry(airport)
+                    .or_default()
+                    .entry(leg.procedure.clone())
+                    .or_default()
+                    .push(*leg),
+                Some(None) => {}
+                None => database.skipped += 1,
+            }
+            if database.cycle.is_none() && column(line, 1) == 'S' {
+                database.cycle = Some(columns(line, 129, 132).to_owned()).filter(|c| !c.is_empty());
+            }
+        }
+
+        for legs in database
+            .procedures
+            .values_mut()
+            .flat_map(|p| p.values_mut())
+        {
+            legs.sort_by(|a, b| {
+                (&a.transition, a.route_type, a.sequence).cmp(&(
+                    &b.transition,
+                    b.route_type,
+                    b.sequence,
+                ))
+            });
+        }
+        for (index, fix) in database.fixes.iter().enumerate() {
+            database
+                .by_ident
+                .entry(fix.ident.clone())
+                .or_default()
+                .push(index);
+        }
+        database
+    }
+
+    /// A CIFP file and an OurAirports folder, either of which may be empty. CIFP wins where both
+    /// have the same fix.
+    pub fn load(cifp: &str, ourairports: &str) -> Result<Self, String> {
+        let mut database = if cifp.is_empty() {
+            Self::default()
+        } else {
+            let bytes = std::fs::read(cifp).map_err(|e| format!("Could not read {cifp}: {e}"))?;
+            let database = Self::parse(&String::from_utf8_lossy(&bytes));
+            if database.fixes.is_empty() {
+                return Err(format!("{cifp} has no ARINC 424 records"));
+            }
+            database
+        };
+        if !ourairports.is_empty() {
+            crate::ourairports::import(&mut database, std::path::Path::new(ourairports))?;
+        }
+        Ok(database)
+    }
+
+    /// Adds a fix unless there already is one of the same kind with the same identifier close
+    /// by, which happens when a fix comes from more than one source.
+    pub fn add_fix(&mut self, fix: NavFix) -> bool {
+        let duplicate = self.lookup(&fix.ident).any(|known| {
+            std::mem::discriminant(&known.kind) == std::mem::discriminant(&fix.kind)
+                && known.airport == fix.airport
+                && geo::distance_m(&known.position, &fix.position) < DUPLICATE_DISTANCE_M
+        });
+        if duplicate {
+            return false;
+        }
+        self.by_ident
+            .entry(fix.ident.clone())
+            .or_default()
+            .push(self.fixes.len());
+        self.fixes.push(fix);
+        true
+    }
+
+    /// Every fix with this identifier, in file order.
+    pub fn lookup(&self, ident: &str) -> impl Iterator<Item = &NavFix> {
+        self.by_ident
+            .get(&ident.trim().to_uppercase())
+            .into_iter()
+            .flatten()
+            .map(|&index| &self.fixes[index])
+    }
+
+    /// The fix a procedure leg refers to. Terminal fixes are looked up at `airport`.
+    pub fn resolve(&self, fix: &FixRef, airport: &str) -> Option<&NavFix> {
+        let mut candidates = self.lookup(&fix.ident).filter(|candidate| {
+            candidate.region == fix.region || fix.region.is_empty() || candidate.region.is_empty()
+        });
+        let terminal = fix.section == 'P';
+        candidates.find(|candidate| {
+            if terminal {
+                candidate.airport.as_deref() == Some(airport)
+            } else {
+                candidate.airport.is_none() || matches!(candidate.kind, FixKind::Vhf { .. })
+            }
+        })
+    }
+
+    /// Procedure identifiers at an airport.
+    pub fn procedures_at(&self, airport: &str) -> impl Iterator<Item = &str> {
+        self.procedures
+            .get(airport)
+            .into_iter()
+            .flat_map(|procedures| procedures.keys().map(String::as_str))
+    }
+
+    pub fn legs(&self, airport: &str, procedure: &str) -> &[ProcedureLeg] {
+        self.procedures
+            .get(airport)
+            .and_then(|procedures| procedures.get(procedure))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    pub fn procedure_count(&self) -> usize {
+        self.procedures.values().map(BTreeMap::len).sum()
+    }
+
+    pub fn airport(&self, ident: &str) -> Option<&NavFix> {
+        self.lookup(ident)
+            .find(|fix| matches!(fix.kind, FixKind::Airport { .. }))
+    }
+
+    /// Fixes matching a query like `ZUNAD`, `IAD VOR` or `RW16R KDEN`, nearest to `near` first.
+    ///
+    /// The first word is the identifier. `VOR`, `DME`, `NDB`, `WPT` or `APT` narrow down the kind
+    /// and any other word is the airport a terminal fix or runway belongs to.
+    pub fn search(
+        &self,
+        query: &str,
+        near: Option<&RealCoordinate>,
+    ) -> Vec<(&NavFix, Option<f64>)> {
+        let query = query.to_uppercase();
+        let mut words = query.split_whitespace();
+        let Some(ident) = words.next() else {
+            return Vec::new();
+        };
+        let mut kind = None;
+        let mut airport = None;
+        for word in words {
+            match word {
+                "VOR" | "VORTAC" | "VOR/DME" | "DME" | "TACAN" | "NDB" | "WPT" | "FIX" | "APT"
+                | "AIRPORT" => kind = Some(word),
+                _ => airport = Some(word),
+            }
+        }
+
+        let mut found: Vec<_> = self
+            .lookup(ident)
+            .filter(|fix| match (kind, &fix.kind) {
+                (None, _) => true,
+                (Some("VOR" | "VORTAC" | "VOR/DME"), FixKind::Vhf { class, .. }) => {
+                    class.starts_with('V')
+                }
+                (Some("DME" | "TACAN"), FixKind::Vhf { .. }) => true,
+                (Some("NDB"), FixKind::Ndb { .. }) => true,
+                (Some("WPT" | "FIX"), FixKind::Waypoint) => true,
+                (Some("APT" | "AIRPORT"), FixKind::Airport { .. }) => true,
+                _ => false,
+            })
+            .filter(|fix| airport.map_or(true, |airport| fix.airport.as_deref() == Some(airport)))
+            .map(|fix| {
+                (
+                    fix,
+                    near.map(|near| crate::geo::distance_m(near, &fix.position)),
+                )
+            })
+            .collect();
+        found.sort_by(|a, b| {
+            a.1.unwrap_or(f64::INFINITY)
+                .total_cmp(&b.1.unwrap_or(f64::INFINITY))
+        });
+        found
+    }
+}
+
+impl LivechartApp {
+    /// Loads the configured database in the background whenever the path changes.
+    pub fn sync_navdb(&mut self, ctx: &egui::Context) {
+        if let Some(receiver) = &self.live.navdb_loading {
+            match receiver.try_recv() {
+                Ok(Ok(database)) => {
+                    let mut status = format!(
+                        "{} fixes, {} procedures at {} airports",
+                        database.fixes.len(),
+                        database.procedure_count(),
+                        database.procedures.len(),
+                    );
+                    if let Some(cycle) = &database.cycle {
+                        status += &format!(", cycle {cycle}");
+                    }
+                    self.live.navdb_status = Some(status);
+                    self.live.navdb = Some(Arc::new(database));
+                    self.live.navdb_loading = None;
+                }
+                Ok(Err(e)) => {
+                    self.live.navdb_status = Some(e);
+                    self.live.navdb_loading = None;
+                }
+                Err(std::sync::mpsc::TryRecvError::Empty) => {}
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                    self.live.navdb_status = Some("Loading stopped unexpectedly".to_owned());
+                    self.live.navdb_loading = None;
+                }
+            }
+        }
+
+        let source = (
+            self.data.navdb_path.clone(),
+            self.data.ourairports_path.clone(),
+        );
+        if (source.0.is_empty() && source.1.is_empty())
+            || self.live.navdb_source.as_ref() == Some(&source)
+        {
+            return;
+        }
+        self.live.navdb_source = Some(source.clone());
+        self.live.navdb = None;
+        self.live.navdb_loading = Some(load_in_background(source.0, source.1, ctx.clone()));
+        self.live.navdb_status = Some("Loading…".to_owned());
+    }
+
+    pub fn navdb_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.live.navdb_window_open;
+        egui::Window::new("Navigation data")
+            .open(&mut open)
+            .default_width(380.0)
+            .show(ctx, |ui| {
+                if self.live.navdb_new_path.is_empty() && self.live.ourairports_new_path.is_empty()
+                {
+                    self.live.navdb_new_path = self.data.navdb_path.clone();
+                    self.live.ourairports_new_path = self.data.ourairports_path.clone();
+                }
+                egui::Grid::new("navdb_paths")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("CIFP file").on_hover_text(
+                            "ARINC 424, e.g. FAACIFP18 from the FAA's CIFP download",
+                        );
+                        ui.text_edit_singleline(&mut self.live.navdb_new_path);
+                        ui.end_row();
+                        ui.label("OurAirports folder").on_hover_text(format!(
+                            "Folder with {}, {} and {} from ourairports.com",
+                            crate::ourairports::AIRPORTS_FILE,
+                            crate::ourairports::RUNWAYS_FILE,
+                            crate::ourairports::NAVAIDS_FILE
+                        ));
+                        ui.text_edit_singleline(&mut self.live.ourairports_new_path);
+                        ui.end_row();
+                    });
+
+                ui.horizontal(|ui| {
+                    let loading = self.live.navdb_loading.is_some();
+                    if ui
+                        .add_enabled(!loading, egui::Button::new("Load"))
+                        .clicked()
+                    {
+                        self.data.navdb_path = self.live.navdb_new_path.trim().to_owned();
+                        self.data.ourairports_path =
+                            self.live.ourairports_new_path.trim().to_owned();
+                        // Loading the same paths again re-reads them
+                        self.live.navdb_source = None;
+                    }
+                    if loading {
+                        ui.spinner();
+                    }
+                });
+
+                if let Some(status) = &self.live.navdb_status {
+                    ui.weak(status);
+                }
+            });
+        self.live.navdb_window_open = open;
+    }
+}
+
+/// The control point whose coordinate is being looked up, and what was typed so far.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixLookup {
+    pub pixels: PixelCoordinate,
+    pub query: String,
+    /// The query field takes the focus once, when the lookup opens.
+    focus: bool,
+}
+
+impl FixLookup {
+    pub fn new(pixels: PixelCoordinate) -> Self {
+        Self {
+            pixels,
+            query: String::new(),
+            focus: true,
+        }
+    }
+}
+
+impl LivechartApp {
+    /// Where the chart is, for telling apart fixes with the same identifier: its airport, else
+    /// the control points already placed, else the ownship.
+    pub fn chart_reference_position(&self) -> Option<RealCoordinate> {
+        let airport = self.data.chart_airport.trim().to_uppercase();
+        if let Some(database) = self.live.navdb.as_ref().filter(|_| !airport.is_empty()) {
+            if let Some(fix) = database.airport(&airport) {
+                return Some(fix.position.clone());
+            }
+        }
+
+        let placed: Vec<_> = self
+            .data
+            .points
+            .iter()
+            .filter_map(|p| p.real.as_ref())
+            .collect();
+        if !placed.is_empty() {
+            let count = placed.len() as f64;
+            return Some(RealCoordinate {
+                lat: placed.iter().map(|p| p.lat).sum::<f64>() / count,
+                lon: placed.iter().map(|p| p.lon).sum::<f64>() / count,
+            });
+        }
+
+        self.live.ownship.as_ref().map(|fix| fix.position.clone())
+    }
+
+    /// The chart's airport, used to rank fix lookups.
+    pub fn chart_airport_editor(&mut self, ui: &mut egui::Ui) {
+        let Some(database) = self.live.navdb.clone() else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            ui.label("Airport");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.data.chart_airport)
+                    .hint_text("KDEN")
+                    .desired_width(60.0),
+            );
+            let airport = self.data.chart_airport.trim().to_uppercase();
+            if let Some(fix) = database.airport(&airport) {
+                ui.weak(&fix.name);
+            } else if !airport.is_empty() {
+                ui.colored_label(ui.visuals().warn_fg_color, "Not in the nav data");
+            }
+        });
+    }
+
+    /// Looks up a fix by identifier and fills in the point's coordinate from it.
+    pub fn fix_lookup_editor(&mut self, ui: &mut egui::Ui, point: &CoordinatePair) {
+        let Some(database) = self.live.navdb.clone() else {
+            return;
+        };
+        let active = self
+            .live
+            .fix_lookup
+            .as_ref()
+            .is_some_and(|lookup| lookup.pixels == point.pixels);
+        if !active {
+            if ui.button("Find fix").clicked() {
+                self.live.fix_lookup = Some(FixLookup::new(point.pixels.clone()));
+            }
+            return;
+        }
+
+        let near = self.chart_reference_position();
+        let Some(lookup) = &mut self.live.fix_lookup else {
+            return;
+        };
+        let mut chosen = None;
+        let mut close = false;
+        ui.vertical(|ui| {
+            let response = ui
+                .horizontal(|ui| {
+                    close = ui.small_button("✖").clicked()
+                        || ui.input(|input| input.key_pressed(egui::Key::Escape));
+                    ui.add(
+                        egui::TextEdit::singleline(&mut lookup.query)
+                            .hint_text("ZUNAD, IAD VOR")
+                            .desired_width(120.0),
+                    )
+                })
+                .inner;
+            if std::mem::take(&mut lookup.focus) {
+                response.request_focus();
+            }
+
+            let in_range = |distance: &Option<f64>| {
+                distance.map_or(true, |d| d <= MAX_LOOKUP_DISTANCE_NM * geo::METRES_PER_NM)
+            };
+            let found = database.search(&lookup.query, near.as_ref());
+            let (nearby, further): (Vec<_>, Vec<_>) =
+                found.iter().partition(|(_, distance)| in_range(distance));
+            // Enter picks the only candidate near the chart
+            if response.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter)) {
+                if let [(fix, _)] = nearby[..] {
+                    chosen = Some(fix.position.clone());
+                }
+            }
+
+            for (fix, distance) in nearby.iter().take(MAX_LOOKUP_RESULTS) {
+                let mut text = format!("{} {}", fix.ident, fix.kind_label());
+                if let Some(airport) = &fix.airport {
+                    text += &format!(" at {airport}");
+                }
+                if let Some(distance) = distance {
+                    text += &format!(", {:.0} NM", distance / geo::METRES_PER_NM);
+                }
+                if ui
+                    .selectable_label(false, text)
+                    .on_hover_text(format!(
+                        "{}\n{:.6}, {:.6}",
+                        fix.name, fix.position.lat, fix.position.lon
+                    ))
+                    .clicked()
+                {
+                    chosen = Some(fix.position.clone());
+                }
+            }
+            let more = nearby.len().saturating_sub(MAX_LOOKUP_RESULTS);
+            if more > 0 {
+                ui.weak(format!(
+                    "{more} more, add the airport or kind to narrow down"
+                ));
+            }
+            if !further.is_empty() {
+                ui.weak(format!(
+                    "{} more further than {MAX_LOOKUP_DISTANCE_NM:.0} NM from the chart",
+                    further.len()
+                ));
+            } else if found.is_empty() && !lookup.query.trim().is_empty() {
+                ui.weak("No fix with this identifier");
+            }
+        });
+
+        if let Some(position) = chosen {
+            if let Some(stored) = self
+                .data
+                .points
+                .iter_mut()
+                .find(|p| p.pixels == point.pixels)
+            {
+                stored.real = Some(position);
+            }
+            close = true;
+        }
+        if close {
+            self.live.fix_lookup = None;
+        }
+    }
+}
+
+/// Parsing a full CIFP takes a few seconds, so it happens off the UI thread.
+fn load_in_background(
+    cifp: String,
+    ourairports: String,
+    ctx: egui::Context,
+) -> Receiver<Result<NavDatabase, String>> {
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let spawned = std::thread::Builder::new()
+            .name("navdb".to_owned())
+            .spawn(move || {
+                let _ = sender.send(NavDatabase::load(&cifp, &ourairports));
+                ctx.request_repaint();
+            });
+        if let Err(e) = spawned {
+            log::error!("Could not start the navigation data thread: {e}");
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = (cifp, ourairports);
+        let _ = sender.send(Err("Navigation data can't be loaded on the web".to_owned()));
+        ctx.request_repaint();
+    }
+
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AIRPORT: &str =
+        "SUSAP KDENK2ADEN     0     160Y N39514200W104402300E008005434                                DENVER INTL                   123452510";
+    const RUNWAY: &str =
+        "SUSAP KDENK2GRW16R   0160001739 N39534370W104414770               05330000055200                                           123452510";
+    const VOR: &str =
+        "SUSAD        BJC   K2011540VDHW N39543878W105082098BJC N39543878W105082098E012005670         JEFFCO                        123452510";
+    const NDB: &str =
+        "SUSADB       LAA   K2003560HW   N38011920W102411220                       E0090              LAMAR                         123452510";
+    const ENROUTE_WAYPOINT: &str =
+        "SUSAEAENRT   TOMSN K20    C     N39412100W104113600                       E0080                   TOMSN                    123452510";
+    const TERMINAL_WAYPOINT: &str =
+        "SUSAP KDENK2CKIPPR K20    C     N40023100W104370200                       E0080                   KIPPR                    123452510";
+    const APPROACH_LEG: &str =
+        "SUSAP KDENK2FR16RY R      030KIPPRK2PC0E  F    TF                     17390050    @ 08000             -300                 123452510";
+
+    fn parse_one(line: &str) -> NavDatabase {
+        let database = NavDatabase::parse(line);
+        assert_eq!(database.skipped, 0);
+        assert_eq!(database.cycle.as_deref(), Some("2510"));
+        database
+    }
+
+    fn only_fix(line: &str) -> NavFix {
+        let database = parse_one(line);
+        assert_eq!(database.fixes.len(), 1);
+        database.fixes[0].clone()
+    }
+
+    fn assert_position(fix: &NavFix, lat: f64, lon: f64) {
+        assert!(
+            (fix.position.lat - lat).abs() < 1e-6,
+            "{}",
+            fix.position.lat
+        );
+        assert!(
+            (fix.position.lon - lon).abs() < 1e-6,
+            "{}",
+            fix.position.lon
+        );
+    }
+
+    #[test]
+    fn parses_airports() {
+        let fix = only_fix(AIRPORT);
+        assert_eq!(fix.ident, "KDEN");
+        assert_eq!(fix.region, "K2");
+        assert_eq!(fix.airport, None);
+        assert_eq!(fix.name, "DENVER INTL");
+        assert_position(
+            &fix,
+            39.0 + 51.0 / 60.0 + 42.0 / 3600.0,
+            -(104.0 + 40.0 / 60.0 + 23.0 / 3600.0),
+        );
+        assert_eq!(
+            fix.kind,
+            FixKind::Airport {
+                elevation_ft: Some(5434),
+                variation_deg: Some(8.0),
+            }
+        );
+    }
+
+    #[test]
+    fn parses_runways() {
+        let fix = only_fix(RUNWAY);
+        assert_eq!(fix.ident, "RW16R");
+        assert_eq!(fix.airport.as_deref(), Some("KDEN"));
+        assert_position(
+            &fix,
+            39.0 + 53.0 / 60.0 + 43.70 / 3600.0,
+            -(104.0 + 41.0 / 60.0 + 47.70 / 3600.0),
+        );
+        assert_eq!(
+            fix.kind,
+            FixKind::Runway {
+                length_ft: Some(16000),
+                bearing_deg: Some(173.9),
+                bearing_true: false,
+                threshold_elevation_ft: Some(5330),
+                displaced_threshold_ft: Some(0),
+                crossing_height_ft: Some(55),
+                width_ft: Some(200),
+            }
+        );
+    }
+
+    #[test]
+    fn parses_navaids() {
+        let vor = only_fix(VOR);
+        assert_eq!((vor.ident.as_str(), vor.name.as_str()), ("BJC", "JEFFCO"));
+        assert_eq!(vor.airport, None);
+        assert_position(
+            &vor,
+            39.0 + 54.0 / 60.0 + 38.78 / 3600.0,
+            -(105.0 + 8.0 / 60.0 + 20.98 / 3600.0),
+        );
+        assert_eq!(
+            vor.kind,
+            FixKind::Vhf {
+                frequency_mhz: Some(115.4),
+                class: "VDHW".to_owned(),
+            }
+        );
+
+        let ndb = only_fix(NDB);
+        assert_eq!((ndb.ident.as_str(), ndb.name.as_str()), ("LAA", "LAMAR"));
+        assert_eq!(ndb.airport, None);
+        assert_eq!(
+            ndb.kind,
+            FixKind::Ndb {
+                frequency_khz: Some(356.0),
+            }
+        );
+    }
+
+    #[test]
+    fn parses_waypoints() {
+        let enroute = only_fix(ENROUTE_WAYPOINT);
+        assert_eq!(enroute.ident, "TOMSN");
+        assert_eq!(enroute.airport, None);
+        assert_eq!(enroute.kind, FixKind::Waypoint);
+        assert_eq!(enroute.kind_label(), "Waypoint");
+        assert_position(
+            &enroute,
+            39.0 + 41.0 / 60.0 + 21.0 / 3600.0,
+            -(104.0 + 11.0 / 60.0 + 36.0 / 3600.0),
+        );
+
+        let terminal = only_fix(TERMINAL_WAYPOINT);
+        assert_eq!(terminal.ident, "KIPPR");
+        assert_eq!(terminal.airport.as_deref(), Some("KDEN"));
+        assert_eq!(terminal.kind_label(), "Terminal waypoint");
+    }
+
+    #[test]
+    fn parses_approach_legs() {
+        let database = parse_one(APPROACH_LEG);
+        assert!(database.fixes.is_empty());
+        assert_eq!(
+            database.procedures_at("KDEN").collect::<Vec<_>>(),
+            ["R16RY"]
+        );
+
+        let leg = &database.legs("KDEN", "R16RY")[0];
+        assert_eq!(leg.route_type, 'R');
+        assert_eq!(leg.transition, "");
+        assert_eq!(leg.sequence, 30);
+        assert_eq!(
+            leg.fix,
+            Some(FixRef {
+                ident: "KIPPR".to_owned(),
+                region: "K2".to_owned(),
+                section: 'P',
+                subsection: 'C',
+            })
+        );
+        assert!(leg.is_final_approach_fix());
+        assert!(!leg.is_missed_approach_point());
+        assert_eq!(leg.turn_direction, None);
+        assert_eq!(leg.path_terminator, "TF");
+        assert_eq!((leg.course_deg, leg.course_true), (Some(173.9), false));
+        assert_eq!((leg.distance_nm, leg.hold_time_min), (Some(5.0), None));
+        assert_eq!(leg.altitude, Some(AltitudeConstraint::At(8000)));
+        assert_eq!(leg.vertical_angle_deg, Some(-3.0));
+        assert_eq!(leg.recommended_navaid, None);
+        assert_eq!(leg.center_fix, None);
+    }
+
+    #[test]
+    fn resolves_terminal_fixes_at_their_airport() {
+        let database = NavDatabase::parse(
+            &[
+                AIRPORT,
+                RUNWAY,
+                VOR,
+                NDB,
+                ENROUTE_WAYPOINT,
+                TERMINAL_WAYPOINT,
+                APPROACH_LEG,
+            ]
+            .join("\n"),
+        );
+        assert_eq!(database.fixes.len(), 6);
+        assert_eq!(database.procedure_count(), 1);
+
+        let leg = &database.legs("KDEN", "R16RY")[0];
+        let fix = leg.fix.as_ref().unwrap();
+        assert_eq!(
+            database.resolve(fix, "KDEN").map(|fix| fix.ident.as_str()),
+            Some("KIPPR")
+        );
+        assert_eq!(database.resolve(fix, "KCOS"), None);
+        assert_eq!(
+            database.airport("kden").map(|fix| fix.name.as_str()),
+            Some("DENVER INTL")
+        );
+    }
+
+    #[test]
+    fn skips_headers_and_continuations_and_counts_malformed_records() {
+        let continuation = RUNWAY.replacen("RW16R   0", "RW16R   2", 1);
+        let malformed = AIRPORT.replacen("N39514200", "X39514200", 1);
+        let database =
+            NavDatabase::parse(&["HDR01CIFP 2510", &continuation, &malformed].join("\n"));
+        assert!(database.fixes.is_empty());
+        assert_eq!(database.skipped, 1);
+    }
+}
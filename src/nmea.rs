@@ -0,0 +1,337 @@
+//! NMEA 0183 sentence parsing.
+
+use crate::flarm;
+use crate::geo::METRES_PER_FT;
+use crate::gnss::{self, GnssQuality};
+use crate::structs::{Fix, RealCoordinate};
+use crate::traffic::TrafficUpdate;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sentence {
+    Rmc(Rmc),
+    Gga(Gga),
+    Pflau(flarm::Pflau),
+    Pflaa(flarm::Pflaa),
+    Gsa(gnss::Gsa),
+    Gsv(gnss::Gsv),
+    /// Valid sentence we don't decode, with its type (e.g. `"VTG"`).
+    Other(String),
+}
+
+/// Recommended minimum data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rmc {
+    pub seconds_of_day: Option<f64>,
+    /// Days since the Unix epoch.
+    pub date: Option<i64>,
+    pub valid: bool,
+    pub position: Option<RealCoordinate>,
+    pub groundspeed_kt: Option<f32>,
+    pub track_deg: Option<f32>,
+}
+
+/// Fix data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gga {
+    pub seconds_of_day: Option<f64>,
+    pub position: Option<RealCoordinate>,
+    pub quality: u8,
+    pub satellites: Option<u8>,
+    pub hdop: Option<f32>,
+    pub altitude_ft: Option<f32>,
+}
+
+/// Parses a single sentence. Returns `None` for anything malformed or failing its checksum.
+pub fn parse(line: &str) -> Option<Sentence> {
+    let line = line.trim();
+    let body = line.strip_prefix('$')?;
+
+    // The checksum is optional in the standard, but if present it has to match
+    let body = match body.rsplit_once('*') {
+        Some((body, checksum)) => {
+            let expected = u8::from_str_radix(checksum.get(..2)?, 16).ok()?;
+            if self::checksum(body) != expected {
+                return None;
+            }
+            body
+        }
+        None => body,
+    };
+
+    let fields: Vec<&str> = body.split(',').collect();
+    let address = *fields.first()?;
+    // Skip the talker id ("GP", "GN", ...) unless it's a proprietary sentence
+    let kind = if address.starts_with('P') {
+        address
+    } else {
+        address.get(2..)?
+    };
+
+    let field = |i: usize| fields.get(i).copied().unwrap_or("");
+
+    Some(match kind {
+        "RMC" => Sentence::Rmc(Rmc {
+            seconds_of_day: parse_clock(field(1)),
+            valid: field(2) == "A",
+            position: parse_position(field(3), field(4), field(5), field(6)),
+            groundspeed_kt: field(7).parse().ok(),
+            track_deg: field(8).parse().ok(),
+            date: parse_date(field(9)),
+        }),
+        "GGA" => Sentence::Gga(Gga {
+            seconds_of_day: parse_clock(field(1)),
+            position: parse_position(field(2), field(3), field(4), field(5)),
+            quality: field(6).parse().unwrap_or(0),
+            satellites: field(7).parse().ok(),
+            hdop: field(8).parse().ok(),
+            altitude_ft: field(9)
+                .parse::<f64>()
+                .ok()
+                .map(|m| (m / METRES_PER_FT) as f32),
+        }),
+        "PFLAU" => Sentence::Pflau(flarm::parse_pflau(&fields)?),
+        "PFLAA" => Sentence::Pflaa(flarm::parse_pflaa(&fields)?),
+        "GSA" => Sentence::Gsa(gnss::parse_gsa(&address[..2], &fields)?),
+        "GSV" => Sentence::Gsv(gnss::parse_gsv(&address[..2], &fields)?),
+        other => Sentence::Other(other.to_owned()),
+    })
+}
+
+/// XOR of every byte between `$` and `*`.
+pub fn checksum(body: &str) -> u8 {
+    body.bytes().fold(0, |acc, b| acc ^ b)
+}
+
+/// Wraps a sentence body in `$`, checksum and line ending.
+pub fn sentence(body: &str) -> String {
+    format!("${body}*{:02X}\r\n", checksum(body))
+}
+
+/// RMC for a fix, for other apps that take NMEA.
+pub fn format_rmc(fix: &Fix) -> String {
+    let (year, month, day) = crate::utc::civil_from_days(fix.time.div_euclid(86_400.0) as i64);
+    // Magnetic variation is left empty
+    sentence(&format!(
+        "GPRMC,{},A,{},{},{},{day:02}{month:02}{:02},,,A",
+        format_clock(fix.time.rem_euclid(86_400.0)),
+        format_position(&fix.position),
+        fix.groundspeed_kt
+            .map_or(String::new(), |s| format!("{s:.1}")),
+        fix.track_deg.map_or(String::new(), |t| format!("{t:.1}")),
+        year.rem_euclid(100),
+    ))
+}
+
+/// GGA for a fix. Satellite count and HDOP come from `quality`, empty when unknown.
+pub fn format_gga(fix: &Fix, quality: Option<&GnssQuality>) -> String {
+    sentence(&format!(
+        "GPGGA,{},{},1,{},{},{},M,,M,,",
+        format_clock(fix.time.rem_euclid(86_400.0)),
+        format_position(&fix.position),
+        quality
+            .and_then(|q| q.satellites_used)
+            .map_or(String::new(), |n| format!("{n:02}")),
+        quality
+            .and_then(|q| q.hdop)
+            .map_or(String::new(), |hdop| format!("{hdop:.1}")),
+        fix.altitude_ft.map_or(String::new(), |a| format!(
+            "{:.1}",
+            f64::from(a) * METRES_PER_FT
+        )),
+    ))
+}
+
+fn format_clock(seconds_of_day: f64) -> String {
+    let hundredths = (seconds_of_day * 100.0).round() as u64;
+    format!(
+        "{:02}{:02}{:02}.{:02}",
+        hundredths / 360_000,
+        hundredths / 6_000 % 60,
+        hundredths / 100 % 60,
+        hundredths % 100
+    )
+}
+
+/// Decimal degrees to `ddmm.mmmm,N,dddmm.mmmm,E`.
+fn format_position(position: &RealCoordinate) -> String {
+    let degrees_minutes = |angle: f64, degree_digits: usize| {
+        // Round in minutes first so 59.99999 doesn't print as 60.0000
+        let minutes = (angle.abs() * 60.0 * 10_000.0).round() / 10_000.0;
+        let degrees = (minutes / 60.0).floor();
+        format!(
+            "{:0width$}{:07.4}",
+            degrees as u32,
+            minutes - degrees * 60.0,
+            width = degree_digits
+        )
+    };
+    format!(
+        "{},{},{},{}",
+        degrees_minutes(position.lat, 2),
+        if position.lat < 0.0 { "S" } else { "N" },
+        degrees_minutes(position.lon, 3),
+        if position.lon < 0.0 { "W" } else { "E" },
+    )
+}
+
+/// `hhmmss.ss` to seconds of day.
+fn parse_clock(text: &str) -> Option<f64> {
+    let hours: f64 = text.get(0..2)?.parse().ok()?;
+    let minutes: f64 = text.get(2..4)?.parse().ok()?;
+    let seconds: f64 = text.get(4..)?.parse().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// `ddmmyy` to days since the Unix epoch.
+fn parse_date(text: &str) -> Option<i64> {
+    let day = text.get(0..2)?.parse().ok()?;
+    let month = text.get(2..4)?.parse().ok()?;
+    let year: i32 = text.get(4..6)?.parse().ok()?;
+    Some(crate::utc::days_from_civil(2000 + year, month, day))
+}
+
+/// `ddmm.mmmm,N,dddmm.mmmm,E` to decimal degrees.
+fn parse_position(lat: &str, ns: &str, lon: &str, ew: &str) -> Option<RealCoordinate> {
+    let degrees_minutes = |text: &str, degree_digits: usize| -> Option<f64> {
+        let degrees: f64 = text.get(..degree_digits)?.parse().ok()?;
+        let minutes: f64 = text.get(degree_digits..)?.parse().ok()?;
+        Some(degrees + minutes / 60.0)
+    };
+
+    let mut lat = degrees_minutes(lat, 2)?;
+    let mut lon = degrees_minutes(lon, 3)?;
+    match ns {
+        "N" => {}
+        "S" => lat = -lat,
+        _ => return None,
+    }
+    match ew {
+        "E" => {}
+        "W" => lon = -lon,
+        _ => return None,
+    }
+    Some(RealCoordinate { lat, lon })
+}
+
+/// What a line contributed, see [`Decoder::push`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decoded {
+    Fix(Fix),
+    Traffic(TrafficUpdate),
+    /// Device status worth showing to the user, e.g. from `PFLAU`.
+    Status(String),
+}
+
+/// Turns a stream of sentences into ownship fixes and FLARM traffic.
+///
+/// A fix is emitted for every valid RMC, using the altitude of the most recent GGA. Feeds that
+/// only send GGA still produce fixes, but without a date or velocity.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    source: String,
+    date: Option<i64>,
+    altitude_ft: Option<f32>,
+    seen_rmc: bool,
+    /// FLARM traffic is relative to this.
+    last_fix: Option<Fix>,
+    gnss: GnssQuality,
+    gnss_changed: bool,
+}
+
+impl Decoder {
+    pub fn new(source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Like [`Decoder::push`], but only for the ownship.
+    pub fn push_line(&mut self, line: &str) -> Option<Fix> {
+        match self.push(line)? {
+            Decoded::Fix(fix) => Some(fix),
+            _ => None,
+        }
+    }
+
+    pub fn push(&mut self, line: &str) -> Option<Decoded> {
+        self.push_sentence(parse(line)?)
+    }
+
+    pub fn push_sentence(&mut self, sentence: Sentence) -> Option<Decoded> {
+        let fix = match sentence {
+            Sentence::Pflau(pflau) => return Some(Decoded::Status(pflau.status())),
+            Sentence::Gsa(gsa) => {
+                self.gnss.push_gsa(gsa);
+                self.gnss_changed = true;
+                return None;
+            }
+            Sentence::Gsv(gsv) => {
+                self.gnss_changed |= self.gnss.push_gsv(gsv);
+                return None;
+            }
+            Sentence::Pflaa(pflaa) => {
+                let update = pflaa.to_update(self.last_fix.as_ref()?, &self.source)?;
+                return Some(Decoded::Traffic(update));
+            }
+            sentence => self.ownship(sentence)?,
+        };
+        self.last_fix = Some(fix.clone());
+        Some(Decoded::Fix(fix))
+    }
+
+    /// The receiver's fix quality, if any sentence changed it since the last call.
+    pub fn take_quality(&mut self) -> Option<GnssQuality> {
+        std::mem::take(&mut self.gnss_changed).then(|| self.gnss.clone())
+    }
+
+    fn ownship(&mut self, sentence: Sentence) -> Option<Fix> {
+        match sentence {
+            Sentence::Rmc(rmc) => {
+                self.seen_rmc = true;
+                if let Some(date) = rmc.date {
+                    self.date = Some(date);
+                }
+                if !rmc.valid {
+                    return None;
+                }
+
+                Some(Fix {
+                    time: self.time(rmc.seconds_of_day?),
+                    position: rmc.position?,
+                    altitude_ft: self.altitude_ft,
+                    altitude_hae: false,
+                    groundspeed_kt: rmc.groundspeed_kt,
+                    track_deg: rmc.track_deg,
+                    source: self.source.clone(),
+                })
+            }
+            Sentence::Gga(gga) => {
+                self.gnss.push_gga(gga.hdop, gga.satellites);
+                self.gnss_changed = true;
+                if gga.quality == 0 {
+                    return None;
+                }
+                self.altitude_ft = gga.altitude_ft;
+                if self.seen_rmc {
+                    return None;
+                }
+
+                Some(Fix {
+                    time: self.time(gga.seconds_of_day?),
+                    position: gga.position?,
+                    altitude_ft: gga.altitude_ft,
+                    altitude_hae: false,
+                    groundspeed_kt: None,
+                    track_deg: None,
+                    source: self.source.clone(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn time(&self, seconds_of_day: f64) -> f64 {
+        self.date.unwrap_or(0) as f64 * 86_400.0 + seconds_of_day
+    }
+}
@@ -0,0 +1,114 @@
+//! OGN beacons in APRS format, as served by APRS-IS (`aprs.glidernet.org:14580`) or a local
+//! stand-in.
+//!
+//! `FLRDDE626>APRS,qAS,EGHL:/074548h5111.32N/00102.04W'086/007/A=000607 !W80! id0ADDE626 -019fpm`
+
+use crate::structs::RealCoordinate;
+use crate::traffic::TrafficUpdate;
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:14580";
+
+/// The line APRS-IS expects before it sends anything. Read-only logins use passcode -1.
+pub fn login(filter: &str) -> String {
+    let version = env!("CARGO_PKG_VERSION");
+    if filter.is_empty() {
+        format!("user N0CALL pass -1 vers livechart {version}\r\n")
+    } else {
+        format!("user N0CALL pass -1 vers livechart {version} filter {filter}\r\n")
+    }
+}
+
+/// Parses one aircraft beacon. Server comments (`#`), receiver beacons and anything without an
+/// OGN id are skipped.
+pub fn parse(line: &str, source: &str) -> Option<TrafficUpdate> {
+    let line = line.trim();
+    if line.starts_with('#') {
+        return None;
+    }
+    let (header, payload) = line.split_once(':')?;
+    if !header.contains('>') {
+        return None;
+    }
+
+    // Position with timestamp, `/` or `@`, then `hhmmssh`
+    let report = payload
+        .strip_prefix('/')
+        .or_else(|| payload.strip_prefix('@'))?;
+    let report = report.get(7..)?;
+
+    let lat = report.get(0..8)?;
+    let lon = report.get(9..18)?;
+    let rest = report.get(19..)?;
+
+    let mut lat_minutes_extra = 0.0;
+    let mut lon_minutes_extra = 0.0;
+    let mut id = None;
+    let mut vertical_rate_fpm = None;
+    for word in rest.split_whitespace() {
+        // `!Wab!` adds a third decimal to the latitude and longitude minutes
+        if let Some(extra) = word.strip_prefix("!W").and_then(|w| w.strip_suffix('!')) {
+            let mut digits = extra.chars().filter_map(|c| c.to_digit(10));
+            lat_minutes_extra = f64::from(digits.next().unwrap_or(0)) / 1000.0;
+            lon_minutes_extra = f64::from(digits.next().unwrap_or(0)) / 1000.0;
+        } else if let Some(word) = word.strip_prefix("id").filter(|w| w.len() == 8) {
+            // STttttaa: stealth, no-track, aircraft type, address type
+            let flags = u8::from_str_radix(word.get(0..2)?, 16).ok()?;
+            if flags & 0x40 != 0 {
+                // The pilot asked not to be tracked
+                return None;
+            }
+            id = crate::flarm::target_id(Some(flags & 0x03), word.get(2..)?);
+        } else if let Some(rate) = word.strip_suffix("fpm") {
+            vertical_rate_fpm = rate.parse().ok();
+        }
+    }
+    let id = id?;
+
+    let position = RealCoordinate {
+        lat: aprs_angle(lat, 2, lat_minutes_extra, 'N', 'S')?,
+        lon: aprs_angle(lon, 3, lon_minutes_extra, 'E', 'W')?,
+    };
+
+    // `ccc/sss` course and speed, then `/A=aaaaaa` altitude in feet
+    let extension = rest.get(..7).filter(|e| e.as_bytes().get(3) == Some(&b'/'));
+    let altitude_ft = rest
+        .split_once("/A=")
+        .and_then(|(_, altitude)| altitude.get(..6)?.parse().ok());
+
+    Some(TrafficUpdate {
+        id,
+        // OGN callsigns are the device prefix and address ("FLRDDE626"), not a flight number
+        callsign: None,
+        position: Some(position),
+        position_age_s: 0.0,
+        altitude_ft,
+        altitude_geometric: true,
+        groundspeed_kt: extension.and_then(|e| e.get(4..7)?.parse().ok()),
+        // 0 means unknown, north is 360
+        track_deg: extension
+            .and_then(|e| e.get(0..3)?.parse::<f32>().ok())
+            .filter(|&track| track > 0.0),
+        vertical_rate_fpm,
+        on_ground: None,
+        alert: None,
+        source: source.to_owned(),
+    })
+}
+
+/// `ddmm.mmN` or `dddmm.mmW` to decimal degrees.
+fn aprs_angle(
+    text: &str,
+    degree_digits: usize,
+    minutes_extra: f64,
+    positive: char,
+    negative: char,
+) -> Option<f64> {
+    let degrees: f64 = text.get(..degree_digits)?.parse().ok()?;
+    let minutes: f64 = text.get(degree_digits..text.len() - 1)?.parse().ok()?;
+    let angle = degrees + (minutes + minutes_extra) / 60.0;
+    match text.chars().last()? {
+        c if c == positive => Some(angle),
+        c if c == negative => Some(-angle),
+        _ => None,
+    }
+}
@@ -0,0 +1,341 @@
+//! Airports, runways and navaids from the OurAirports CSV downloads (`airports.csv`,
+//! `runways.csv`, `navaids.csv`), for flying where there is no FAA data.
+//!
+//! Columns are looked up by their header, so the files may gain columns without breaking us.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::geo;
+use crate::navdb::{FixKind, NavDatabase, NavFix};
+use crate::structs::RealCoordinate;
+
+pub const AIRPORTS_FILE: &str = "airports.csv";
+pub const RUNWAYS_FILE: &str = "runways.csv";
+pub const NAVAIDS_FILE: &str = "navaids.csv";
+
+/// Splits one CSV line, with double quotes around fields that contain commas and doubled
+/// quotes inside them.
+fn split_csv(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Rows of a CSV file as maps from header to value.
+struct Table {
+    columns: HashMap<String, usize>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn parse(text: &str) -> Self {
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let columns = lines
+            .next()
+            .map(split_csv)
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+            .map(|(index, name)| (name.trim().to_owned(), index))
+            .collect();
+        Self {
+            columns,
+            rows: lines.map(split_csv).collect(),
+        }
+    }
+
+    fn get<'a>(&self, row: &'a [String], column: &str) -> &'a str {
+        self.columns
+            .get(column)
+            .and_then(|&index| row.get(index))
+            .map_or("", |value| value.trim())
+    }
+
+    fn number(&self, row: &[String], column: &str) -> Option<f64> {
+        self.get(row, column).parse().ok()
+    }
+
+    fn position(&self, row: &[String], lat: &str, lon: &str) -> Option<RealCoordinate> {
+        Some(RealCoordinate {
+            lat: self.number(row, lat)?,
+            lon: self.number(row, lon)?,
+        })
+    }
+}
+
+fn read(folder: &Path, file: &str) -> Result<String, String> {
+    let path = folder.join(file);
+    let bytes =
+        std::fs::read(&path).map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Adds everything in an OurAirports download folder to `database`. Fixes the database already
+/// has, e.g. from CIFP, are kept as they are. Returns how many fixes were added.
+pub fn import(database: &mut NavDatabase, folder: &Path) -> Result<usize, String> {
+    let before = database.fixes.len();
+    import_airports(database, &Table::parse(&read(folder, AIRPORTS_FILE)?));
+    // Runways and navaids are optional, the airports alone already help
+    if let Ok(text) = read(folder, RUNWAYS_FILE) {
+        import_runways(database, &Table::parse(&text));
+    }
+    if let Ok(text) = read(folder, NAVAIDS_FILE) {
+        import_navaids(database, &Table::parse(&text));
+    }
+    Ok(database.fixes.len() - before)
+}
+
+fn import_airports(database: &mut NavDatabase, table: &Table) {
+    for row in &table.rows {
+        if matches!(
+            table.get(row, "type"),
+            "closed" | "heliport" | "balloonport" | "seaplane_base"
+        ) {
+            continue;
+        }
+        let Some(position) = table.position(row, "latitude_deg", "longitude_deg") else {
+            continue;
+        };
+        database.add_fix(NavFix {
+            ident: table.get(row, "ident").to_uppercase(),
+            region: table.get(row, "iso_country").to_owned(),
+            airport: None,
+            name: table.get(row, "name").to_owned(),
+            position,
+            kind: FixKind::Airport {
+                elevation_ft: table.number(row, "elevation_ft").map(|ft| ft as i32),
+                variation_deg: None,
+            },
+        });
+    }
+}
+
+/// Each runway row has both ends, `le_` (low end) and `he_` (high end).
+fn import_runways(database: &mut NavDatabase, table: &Table) {
+    for row in &table.rows {
+        if table.get(row, "closed") == "1" {
+            continue;
+        }
+        let airport = table.get(row, "airport_ident").to_uppercase();
+        for end in ["le", "he"] {
+            let column = |name: &str| format!("{end}_{name}");
+            let designator = table.get(row, &column("ident")).to_uppercase();
+            let Some(end_position) =
+                table.position(row, &column("latitude_deg"), &column("longitude_deg"))
+            else {
+                continue;
+            };
+            if designator.is_empty() {
+                continue;
+            }
+
+            let heading = table.number(row, &column("heading_degT"));
+            let displaced = table.number(row, &column("displaced_threshold_ft"));
+            // The coordinates are the runway end, the threshold is past any displacement
+            let position = match (heading, displaced) {
+                (Some(heading), Some(displaced)) if displaced > 0.0 => {
+                    geo::destination(&end_position, heading, displaced * geo::METRES_PER_FT)
+                }
+                _ => end_position,
+            };
+
+            database.add_fix(NavFix {
+                ident: format!("RW{designator}"),
+                region: String::new(),
+                airport: Some(airport.clone()),
+                name: format!("Runway {designator}"),
+                position,
+                kind: FixKind::Runway {
+                    length_ft: table.number(row, "length_ft").map(|ft| ft as u32),
+                    bearing_deg: heading.map(|heading| heading as f32),
+                    bearing_true: true,
+                    threshold_elevation_ft: table
+                        .number(row, &column("elevation_ft"))
+                        .map(|ft| ft as i32),
+                    displaced_threshold_ft: displaced.map(|ft| ft as u32),
+                    crossing_height_ft: None,
+                    width_ft: table.number(row, "width_ft").map(|ft| ft as u32),
+                },
+            });
+        }
+    }
+}
+
+fn import_navaids(database: &mut NavDatabase, table: &Table) {
+    for row in &table.rows {
+        let kind = table.get(row, "type");
+        let frequency_khz = table.number(row, "frequency_khz");
+        let kind = match kind {
+            "NDB" | "NDB-DME" => FixKind::Ndb {
+                frequency_khz: frequency_khz.map(|khz| khz as f32),
+            },
+            "VOR" | "VOR-DME" | "VORTAC" | "DME" | "TACAN" => FixKind::Vhf {
+                frequency_mhz: frequency_khz.map(|khz| (khz / 1000.0) as f32),
+                class: kind.to_owned(),
+            },
+            _ => continue,
+        };
+        // DME-only stations may only have the DME position
+        let Some(position) = table
+            .position(row, "latitude_deg", "longitude_deg")
+            .or_else(|| table.position(row, "dme_latitude_deg", "dme_longitude_deg"))
+        else {
+            continue;
+        };
+        let airport = table.get(row, "associated_airport");
+
+        database.add_fix(NavFix {
+            ident: table.get(row, "ident").to_uppercase(),
+            region: table.get(row, "iso_country").to_owned(),
+            airport: (!airport.is_empty()).then(|| airport.to_uppercase()),
+            name: table.get(row, "name").to_owned(),
+            position,
+            kind,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AIRPORTS: &str = r#""id","ident","type","name","latitude_deg","longitude_deg","elevation_ft","continent","iso_country","iso_region","municipality","scheduled_service","gps_code","iata_code","local_code","home_link","wikipedia_link","keywords"
+3622,"KJFK","large_airport","John F Kennedy International Airport",40.639447,-73.779317,13,"NA","US","US-NY","New York","yes","KJFK","JFK","JFK","https://www.jfkairport.com/","https://en.wikipedia.org/wiki/John_F._Kennedy_International_Airport","Manhattan, New York City, NYC, Idlewild, IDL, KIDL"
+2434,"LSZH","large_airport","Zurich Airport",47.458056,8.548056,1417,"EU","CH","CH-ZH","Zurich","yes","LSZH","ZRH",,"https://www.zurich-airport.com/","https://en.wikipedia.org/wiki/Z%C3%BCrich_Airport","Kloten"
+6523,"00A","closed","Total RF Heliport",40.070985,-74.933689,11,"NA","US","US-PA","Bensalem","no",,,,,,
+"#;
+
+    const RUNWAYS: &str = r#""id","airport_ref","airport_ident","length_ft","width_ft","surface","lighted","closed","le_ident","le_latitude_deg","le_longitude_deg","le_elevation_ft","le_heading_degT","le_displaced_threshold_ft","he_ident","he_latitude_deg","he_longitude_deg","he_elevation_ft","he_heading_degT","he_displaced_threshold_ft"
+235373,2434,"LSZH",10827,197,"CON",1,0,"14",47.4823,8.53499,1402,137.5,,"32",47.4599,8.56287,1388,317.5,1378
+"#;
+
+    const NAVAIDS: &str = r#""id","filename","ident","name","type","frequency_khz","latitude_deg","longitude_deg","elevation_ft","iso_country","dme_frequency_khz","dme_channel","dme_latitude_deg","dme_longitude_deg","dme_elevation_ft","slaved_variation_deg","magnetic_variation_deg","usageType","power","associated_airport"
+86800,"Kloten_VOR-DME_CH","KLO","Kloten","VOR-DME",114850,47.4578,8.5478,1430,"CH",114850,"095Y",47.4578,8.5478,1430,,2.0,"BOTH","HIGH","LSZH"
+86777,"Zurich East_NDB_CH","ZUE","Zurich East","NDB",344,47.5923,8.8178,,"CH",,,,,,,2.0,"TERMINAL","MEDIUM",
+"#;
+
+    #[test]
+    fn splits_quoted_fields() {
+        assert_eq!(
+            split_csv(r#"1,"Manhattan, New York City",,"6"" Field""#),
+            ["1", "Manhattan, New York City", "", "6\" Field"]
+        );
+    }
+
+    #[test]
+    fn reads_columns_by_header() {
+        let table = Table::parse(AIRPORTS);
+        assert_eq!(table.rows.len(), 3);
+        let jfk = &table.rows[0];
+        assert_eq!(
+            table.get(jfk, "keywords"),
+            "Manhattan, New York City, NYC, Idlewild, IDL, KIDL"
+        );
+        assert_eq!(table.get(jfk, "iso_country"), "US");
+        assert_eq!(table.number(jfk, "elevation_ft"), Some(13.0));
+        assert_eq!(table.get(jfk, "no such column"), "");
+    }
+
+    #[test]
+    fn imports_airports_without_closed_ones() {
+        let mut database = NavDatabase::default();
+        import_airports(&mut database, &Table::parse(AIRPORTS));
+        assert_eq!(database.fixes.len(), 2);
+
+        let jfk = database.airport("KJFK").unwrap();
+        assert_eq!(jfk.name, "John F Kennedy International Airport");
+        assert_eq!(jfk.region, "US");
+        assert_eq!(jfk.position.lat, 40.639447);
+        assert_eq!(jfk.position.lon, -73.779317);
+        assert_eq!(
+            jfk.kind,
+            FixKind::Airport {
+                elevation_ft: Some(13),
+                variation_deg: None,
+            }
+        );
+        assert!(database.airport("00A").is_none());
+    }
+
+    #[test]
+    fn imports_both_runway_ends_at_the_threshold() {
+        let mut database = NavDatabase::default();
+        import_runways(&mut database, &Table::parse(RUNWAYS));
+        assert_eq!(database.fixes.len(), 2);
+
+        let rw14 = database.lookup("RW14").next().unwrap();
+        assert_eq!(rw14.airport.as_deref(), Some("LSZH"));
+        assert_eq!(rw14.position.lat, 47.4823);
+        assert_eq!(
+            rw14.kind,
+            FixKind::Runway {
+                length_ft: Some(10827),
+                bearing_deg: Some(137.5),
+                bearing_true: true,
+                threshold_elevation_ft: Some(1402),
+                displaced_threshold_ft: None,
+                crossing_height_ft: None,
+                width_ft: Some(197),
+            }
+        );
+
+        // 32 is displaced 1378 ft down the runway from its end
+        let rw32 = database.lookup("RW32").next().unwrap();
+        let end = RealCoordinate {
+            lat: 47.4599,
+            lon: 8.56287,
+        };
+        let displaced = 1378.0 * geo::METRES_PER_FT;
+        assert!((geo::distance_m(&end, &rw32.position) - displaced).abs() < 0.5);
+        assert!((geo::bearing_deg(&end, &rw32.position) - 317.5).abs() < 0.1);
+    }
+
+    #[test]
+    fn imports_navaids() {
+        let mut database = NavDatabase::default();
+        import_navaids(&mut database, &Table::parse(NAVAIDS));
+
+        let klo = database.lookup("KLO").next().unwrap();
+        assert_eq!(klo.airport.as_deref(), Some("LSZH"));
+        assert_eq!(
+            klo.kind,
+            FixKind::Vhf {
+                frequency_mhz: Some(114.85),
+                class: "VOR-DME".to_owned(),
+            }
+        );
+
+        let zue = database.lookup("ZUE").next().unwrap();
+        assert_eq!(zue.name, "Zurich East");
+        assert_eq!(zue.airport, None);
+        assert_eq!(
+            zue.kind,
+            FixKind::Ndb {
+                frequency_khz: Some(344.0),
+            }
+        );
+    }
+
+    #[test]
+    fn keeps_fixes_the_database_already_has() {
+        let mut database = NavDatabase::default();
+        import_airports(&mut database, &Table::parse(AIRPORTS));
+        import_airports(&mut database, &Table::parse(AIRPORTS));
+        assert_eq!(database.fixes.len(), 2);
+    }
+}
@@ -0,0 +1,504 @@
+//! Re-broadcasts the ownship and traffic so other apps on the network can use LiveChart as their
+//! position source.
+//!
+//! Sockets are non-blocking and serviced from the UI thread, sending only ever takes a few
+//! datagrams per output and tick.
+
+use crate::app::LivechartApp;
+use crate::conflict::ThreatLevel;
+use crate::flarm::{self, Pflaa, Pflau};
+use crate::gdl90;
+use crate::gnss::GnssQuality;
+use crate::nmea;
+use crate::traffic::NON_ICAO_FLAG;
+use crate::utc;
+
+/// Without a new fix for this long the outputs report the position as invalid.
+const OWNSHIP_TIMEOUT_S: f64 = 5.0;
+/// GDL 90 receivers expect a heartbeat every second, whatever the update rate.
+const HEARTBEAT_INTERVAL_S: f64 = 1.0;
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+pub struct OutputConfig {
+    pub id: u64,
+    pub enabled: bool,
+    pub kind: OutputKind,
+    /// Updates per second.
+    pub rate_hz: f32,
+    pub traffic: bool,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+pub enum OutputKind {
+    /// RMC/GGA and FLARM PFLAU/PFLAA datagrams to `host:port`, which may be a broadcast address.
+    NmeaUdp { target: String },
+    /// The same sentences to every client connected to `port`.
+    NmeaTcp { port: u16 },
+    /// GDL 90 to `host:port`, which may be a broadcast address.
+    Gdl90Udp { target: String },
+}
+
+impl OutputKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::NmeaUdp { .. } => "NMEA UDP",
+            Self::NmeaTcp { .. } => "NMEA TCP",
+            Self::Gdl90Udp { .. } => "GDL 90",
+        }
+    }
+}
+
+/// A running output, owned by the UI thread.
+pub struct Output {
+    pub config: OutputConfig,
+    pub status: String,
+    pub sent: u64,
+    /// Unix time of the next update.
+    next_send: f64,
+    /// Unix time of the next GDL 90 heartbeat.
+    next_heartbeat: f64,
+    #[cfg(not(target_arch = "wasm32"))]
+    transport: Option<native::Transport>,
+}
+
+impl Output {
+    pub fn start(config: OutputConfig) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        let (transport, status) = match native::Transport::open(&config.kind) {
+            Ok((transport, status)) => (Some(transport), status),
+            Err(e) => (None, e),
+        };
+        #[cfg(target_arch = "wasm32")]
+        let status = "Network outputs are not available on the web".to_owned();
+
+        Self {
+            config,
+            status,
+            sent: 0,
+            next_send: 0.0,
+            next_heartbeat: 0.0,
+            #[cfg(not(target_arch = "wasm32"))]
+            transport,
+        }
+    }
+
+    fn send(&mut self, payload: &[u8]) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(transport) = &mut self.transport {
+            match transport.send(payload) {
+                Ok(()) => self.sent += 1,
+                Err(e) => self.status = e,
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = payload;
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod native {
+    use std::io::Write as _;
+    use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs as _, UdpSocket};
+    use std::sync::Mutex;
+
+    use super::OutputKind;
+
+    /// Local ports of the running UDP outputs. Broadcasts also reach our own feeds, which must
+    /// not take back what we sent.
+    static OWN_PORTS: Mutex<Vec<u16>> = Mutex::new(Vec::new());
+
+    /// Whether a datagram was sent by one of our UDP outputs: from one of their ports and an
+    /// address of this machine.
+    pub fn is_own_datagram(source: &SocketAddr) -> bool {
+        let own_port = OWN_PORTS
+            .lock()
+            .is_ok_and(|ports| ports.contains(&source.port()));
+        // Only addresses of this machine can be bound, checked last as it's the slow part
+        own_port && UdpSocket::bind((source.ip(), 0)).is_ok()
+    }
+
+    pub enum Transport {
+        Udp {
+            socket: UdpSocket,
+            target: SocketAddr,
+        },
+        Tcp {
+            listener: TcpListener,
+            clients: Vec<TcpStream>,
+        },
+    }
+
+    impl Transport {
+        /// Opens the socket, returns it with a status line for the user.
+        pub fn open(kind: &OutputKind) -> Result<(Self, String), String> {
+            match kind {
+                OutputKind::NmeaUdp { target } | OutputKind::Gdl90Udp { target } => {
+                    Self::udp(target)
+                }
+                OutputKind::NmeaTcp { port } => Self::tcp(*port),
+            }
+        }
+
+        /// Datagrams to `target`, which may be a broadcast address.
+        pub fn udp(target: &str) -> Result<(Self, String), String> {
+            let address = target
+                .to_socket_addrs()
+                .map_err(|e| format!("Invalid target {target}: {e}"))?
+                .next()
+                .ok_or_else(|| format!("{target} did not resolve"))?;
+            let socket = UdpSocket::bind(("0.0.0.0", 0))
+                .and_then(|socket| {
+                    socket.set_broadcast(true)?;
+                    socket.set_nonblocking(true)?;
+                    Ok(socket)
+                })
+                .map_err(|e| format!("Could not open a UDP socket: {e}"))?;
+            if let (Ok(local), Ok(mut ports)) = (socket.local_addr(), OWN_PORTS.lock()) {
+                ports.push(local.port());
+            }
+            Ok((
+                Self::Udp {
+                    socket,
+                    target: address,
+                },
+                format!("Sending to {address}"),
+            ))
+        }
+
+        /// A stream to every client connecting on `port`.
+        pub fn tcp(port: u16) -> Result<(Self, String), String> {
+            let listener = TcpListener::bind(("0.0.0.0", port))
+                .and_then(|listener| {
+                    listener.set_nonblocking(true)?;
+                    Ok(listener)
+                })
+                .map_err(|e| format!("Could not listen on TCP {port}: {e}"))?;
+            Ok((
+                Self::Tcp {
+                    listener,
+                    clients: Vec::new(),
+                },
+                format!("Listening on TCP {port}"),
+            ))
+        }
+
+        pub fn send(&mut self, payload: &[u8]) -> Result<(), String> {
+            match self {
+                Self::Udp { socket, target } => socket
+                    .send_to(payload, *target)
+                    .map(|_| ())
+                    .map_err(|e| format!("Could not send to {target}: {e}")),
+                Self::Tcp { listener, clients } => {
+                    while let Ok((client, _)) = listener.accept() {
+                        let _ = client.set_nonblocking(true);
+                        clients.push(client);
+                    }
+                    // Clients too slow to keep up are dropped rather than buffered for
+                    clients.retain_mut(|client| client.write_all(payload).is_ok());
+                    Ok(())
+                }
+            }
+        }
+
+        pub fn clients(&self) -> Option<usize> {
+            match self {
+                Self::Udp { .. } => None,
+                Self::Tcp { clients, .. } => Some(clients.len()),
+            }
+        }
+    }
+
+    impl Drop for Transport {
+        fn drop(&mut self) {
+            if let Self::Udp { socket, .. } = self {
+                if let (Ok(local), Ok(mut ports)) = (socket.local_addr(), OWN_PORTS.lock()) {
+                    ports.retain(|&port| port != local.port());
+                }
+            }
+        }
+    }
+}
+
+impl LivechartApp {
+    /// Starts enabled outputs that aren't running and stops the ones that were disabled, removed
+    /// or reconfigured.
+    pub fn sync_outputs(&mut self) {
+        let configs = &self.data.outputs;
+        self.live
+            .outputs
+            .retain(|output| configs.iter().any(|c| c.enabled && *c == output.config));
+
+        for config in configs.iter().filter(|c| c.enabled) {
+            if !self
+                .live
+                .outputs
+                .iter()
+                .any(|output| output.config.id == config.id)
+            {
+                self.live.outputs.push(Output::start(config.clone()));
+            }
+        }
+    }
+
+    /// Sends an update on every output that is due.
+    pub fn broadcast_outputs(&mut self, ctx: &egui::Context) {
+        let now = utc::now();
+        let mut outputs = std::mem::take(&mut self.live.outputs);
+        for output in &mut outputs {
+            let interval = 1.0 / f64::from(output.config.rate_hz.max(0.1));
+            if now >= output.next_send {
+                let payload = match output.config.kind {
+                    OutputKind::NmeaUdp { .. } | OutputKind::NmeaTcp { .. } => {
+                        self.nmea_output(output.config.traffic).into_bytes()
+                    }
+                    OutputKind::Gdl90Udp { .. } => self.gdl90_output(output.config.traffic),
+                };
+                if !payload.is_empty() {
+                    output.send(&payload);
+                }
+                // Don't try to catch up after the UI was idle
+                output.next_send = (output.next_send + interval).max(now);
+            }
+
+            let mut next_wake = output.next_send;
+            if let OutputKind::Gdl90Udp { .. } = output.config.kind {
+                if now >= output.next_heartbeat {
+                    output.send(&self.gdl90_heartbeat(now));
+                    output.next_heartbeat = (output.next_heartbeat + HEARTBEAT_INTERVAL_S).max(now);
+                }
+                next_wake = next_wake.min(output.next_heartbeat);
+            }
+            ctx.request_repaint_after(std::time::Duration::from_secs_f64(
+                (next_wake - now).max(0.0),
+            ));
+        }
+        self.live.outputs = outputs;
+    }
+
+    /// Pressure altitude of `target`. FLARM and OGN report GNSS altitude, which can only be
+    /// converted with the ownship's offset between the two.
+    fn pressure_altitude_ft(&self, target: &crate::traffic::Target) -> Option<i32> {
+        let altitude = target.altitude_ft?;
+        if !target.altitude_geometric {
+            return Some(altitude);
+        }
+        let offset = self.live.pressure_offset_ft?;
+        Some((altitude as f32 - offset).round() as i32)
+    }
+
+    /// The ownship fix, unless it stopped updating.
+    fn fresh_ownship(&self) -> Option<&crate::structs::Fix> {
+        let received = self.live.ownship_received?;
+        (utc::now() - received <= OWNSHIP_TIMEOUT_S)
+            .then_some(self.live.ownship.as_ref())
+            .flatten()
+    }
+
+    /// Traffic worth re-broadcasting: with a position that isn't stale.
+    fn output_targets(&self) -> impl Iterator<Item = &crate::traffic::Target> {
+        let now = utc::now();
+        let stale_after = f64::from(self.data.traffic_display.stale_after_s);
+        self.live.traffic.targets.values().filter(move |target| {
+            target.position.is_some() && now - target.position_time <= stale_after
+        })
+    }
+
+    fn nmea_output(&self, traffic: bool) -> String {
+        let Some(fix) = self.fresh_ownship() else {
+            return String::new();
+        };
+        let mut sentences = nmea::format_rmc(fix) + &nmea::format_gga(fix, self.fresh_gnss());
+        if !traffic {
+            return sentences;
+        }
+
+        let mut received = 0u8;
+        let mut highest = ThreatLevel::None;
+        for target in self.output_targets() {
+            let level = self.threat_level(target.id);
+            let relative_altitude = self.relative_altitude_ft(target);
+            if let Some(pflaa) =
+                Pflaa::relative_to(fix, target, relative_altitude, flarm::alarm_level(level))
+            {
+                sentences += &pflaa.format();
+                received = received.saturating_add(1);
+                highest = highest.max(level);
+            }
+        }
+        sentences += &Pflau {
+            received,
+            transmitting: false,
+            gps: 2,
+            alarm_level: flarm::alarm_level(highest),
+        }
+        .format();
+        sentences
+    }
+
+    /// Heartbeat and ForeFlight identification, sent once a second.
+    fn gdl90_heartbeat(&self, now: f64) -> Vec<u8> {
+        [
+            gdl90::encode_heartbeat(&gdl90::Heartbeat {
+                gps_valid: self.fresh_ownship().is_some(),
+                seconds_of_day: now.rem_euclid(86_400.0) as u32,
+            }),
+            gdl90::encode_foreflight_id("LiveChrt", "LiveChart"),
+        ]
+        .iter()
+        .flat_map(|m| gdl90::frame(m))
+        .collect()
+    }
+
+    fn gdl90_output(&self, traffic: bool) -> Vec<u8> {
+        let mut messages = Vec::new();
+        if let Some(fix) = self.fresh_ownship() {
+            let vertical_rate = self.live.trail.vertical_rate_fpm() as i32;
+            let gnss = self.fresh_gnss();
+            let report = gdl90::Report {
+                // Only known with a pressure altitude source among the feeds
+                altitude_ft: fix
+                    .altitude_ft
+                    .zip(self.live.pressure_offset_ft)
+                    .map(|(altitude, offset)| (altitude - offset).round() as i32),
+                nic: gnss.map_or(0, GnssQuality::gdl90_nic),
+                nacp: gnss.map_or(0, GnssQuality::gdl90_nacp),
+                ..gdl90::Report::ownship(fix, Some(vertical_rate), "LIVECHRT")
+            };
+            messages.push(gdl90::encode_report(10, &report));
+            if let Some(altitude) = fix.altitude_ft {
+                messages.push(gdl90::encode_geometric_altitude(altitude.round() as i32));
+            }
+        }
+
+        if traffic {
+            for target in self.output_targets() {
+                messages.push(gdl90::encode_report(
+                    20,
+                    &gdl90::Report {
+                        alert: self.threat_level(target.id) != ThreatLevel::None,
+                        // ADS-B with ICAO address, or self-assigned
+                        address_type: u8::from(target.id & NON_ICAO_FLAG != 0),
+                        address: target.id & (NON_ICAO_FLAG - 1),
+                        position: target.position.clone(),
+                        altitude_ft: self.pressure_altitude_ft(target),
+                        airborne: !target.on_ground,
+                        nic: 8,
+                        nacp: 8,
+                        groundspeed_kt: target.groundspeed_kt.map(|s| s.round() as u16),
+                        vertical_rate_fpm: target.vertical_rate_fpm,
+                        track_deg: target.track_deg,
+                        emitter_category: 0,
+                        callsign: target.callsign.clone().unwrap_or_default(),
+                    },
+                ));
+            }
+        }
+
+        messages.iter().flat_map(|m| gdl90::frame(m)).collect()
+    }
+
+    pub fn outputs_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.live.outputs_window_open;
+        egui::Window::new("Outputs")
+            .open(&mut open)
+            .default_width(380.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Add:");
+                    let next_id = self
+                        .data
+                        .outputs
+                        .iter()
+                        .map(|c| c.id + 1)
+                        .max()
+                        .unwrap_or(0);
+                    let mut add = |kind, rate_hz| {
+                        self.data.outputs.push(OutputConfig {
+                            id: next_id,
+                            enabled: false,
+                            kind,
+                            rate_hz,
+                            traffic: true,
+                        });
+                    };
+                    if ui.button("NMEA UDP").clicked() {
+                        add(
+                            OutputKind::NmeaUdp {
+                                target: "255.255.255.255:10111".to_owned(),
+                            },
+                            1.0,
+                        );
+                    }
+                    if ui.button("NMEA TCP").clicked() {
+                        add(OutputKind::NmeaTcp { port: 10111 }, 1.0);
+                    }
+                    if ui.button("GDL 90").clicked() {
+                        // Not 4000, that's where the GDL 90 feed listens
+                        add(
+                            OutputKind::Gdl90Udp {
+                                target: "255.255.255.255:4001".to_owned(),
+                            },
+                            1.0,
+                        );
+                    }
+                });
+                ui.separator();
+
+                if self.data.outputs.is_empty() {
+                    ui.label("No outputs configured.");
+                }
+
+                let mut remove = None;
+                for (index, config) in self.data.outputs.iter_mut().enumerate() {
+                    let running = self.live.outputs.iter().find(|o| o.config.id == config.id);
+
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut config.enabled, config.kind.label());
+                        match &mut config.kind {
+                            OutputKind::NmeaUdp { target } | OutputKind::Gdl90Udp { target } => {
+                                ui.add(egui::TextEdit::singleline(target).desired_width(140.0))
+                                    .on_hover_text(
+                                        "host:port, may be a broadcast address. ForeFlight, \
+                                         Garmin Pilot, SkyDemon, ... listen for GDL 90 on 4000.",
+                                    );
+                            }
+                            OutputKind::NmeaTcp { port } => {
+                                ui.label("TCP port");
+                                ui.add(egui::DragValue::new(port));
+                            }
+                        }
+                        if ui.small_button("🗑").clicked() {
+                            remove = Some(index);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::DragValue::new(&mut config.rate_hz)
+                                .range(0.2..=10.0)
+                                .speed(0.1)
+                                .suffix(" Hz"),
+                        );
+                        ui.checkbox(&mut config.traffic, "Traffic");
+                    });
+
+                    if let Some(output) = running {
+                        #[cfg(not(target_arch = "wasm32"))]
+                        let clients = output
+                            .transport
+                            .as_ref()
+                            .and_then(|t| t.clients())
+                            .map_or(String::new(), |n| format!(" · {n} clients"));
+                        #[cfg(target_arch = "wasm32")]
+                        let clients = String::new();
+                        ui.weak(format!("{} · {} sent{clients}", output.status, output.sent));
+                    }
+                    ui.separator();
+                }
+
+                if let Some(index) = remove {
+                    self.data.outputs.remove(index);
+                }
+            });
+        self.live.outputs_window_open = open;
+    }
+}
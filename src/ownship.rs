@@ -0,0 +1,244 @@
+//! Ownship history (breadcrumb trail) and short-term track prediction.
+
+use std::collections::VecDeque;
+
+use crate::app::LivechartApp;
+use crate::geo::{self, Georeference};
+use crate::structs::{Fix, RealCoordinate};
+use crate::utc;
+
+/// Look-ahead times the predicted track is marked at.
+pub const PREDICTION_MARKS_S: [f64; 3] = [60.0, 120.0, 300.0];
+
+/// Anything beyond twice a standard rate turn is treated as noise.
+const MAX_TURN_RATE_DEG_S: f64 = 6.0;
+
+/// How far back to look when estimating the turn and vertical rate.
+const TURN_RATE_WINDOW_S: f64 = 5.0;
+
+/// Dead reckoning stops after this long without a new fix, so a lost feed doesn't fly on.
+const MAX_EXTRAPOLATION_S: f64 = 3.0;
+/// Smooth motion moves the ownship symbol at most this far per frame, in screen points.
+const MAX_STEP_POINTS: f64 = 1.0;
+/// Repaints for smooth motion are capped at about 60 fps.
+const MIN_REPAINT_INTERVAL_S: f64 = 1.0 / 60.0;
+
+#[derive(Debug, Default)]
+pub struct Trail {
+    fixes: VecDeque<Fix>,
+}
+
+impl Trail {
+    /// Adds a fix and drops everything older than `max_age_s` relative to it.
+    pub fn push(&mut self, fix: Fix, max_age_s: f64) {
+        // Time went backwards (e.g. seeking in a replay), the old trail no longer applies
+        if self.fixes.back().is_some_and(|last| fix.time < last.time) {
+            self.fixes.clear();
+        }
+
+        let cutoff = fix.time - max_age_s;
+        self.fixes.push_back(fix);
+        while self
+            .fixes
+            .front()
+            .is_some_and(|oldest| oldest.time < cutoff)
+        {
+            self.fixes.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.fixes.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Fix> {
+        self.fixes.iter()
+    }
+
+    /// Rate of change of track in degrees per second, positive to the right.
+    pub fn turn_rate_deg_s(&self) -> f64 {
+        let Some(latest) = self.fixes.back() else {
+            return 0.0;
+        };
+        let Some(latest_track) = latest.track_deg else {
+            return 0.0;
+        };
+
+        // Oldest fix with a track inside the window
+        let Some((earlier_time, earlier_track)) = self
+            .fixes
+            .iter()
+            .filter(|fix| latest.time - fix.time <= TURN_RATE_WINDOW_S && fix.time < latest.time)
+            .find_map(|fix| fix.track_deg.map(|track| (fix.time, track)))
+        else {
+            return 0.0;
+        };
+
+        let change = f64::from(latest_track - earlier_track + 540.0).rem_euclid(360.0) - 180.0;
+        (change / (latest.time - earlier_time)).clamp(-MAX_TURN_RATE_DEG_S, MAX_TURN_RATE_DEG_S)
+    }
+
+    /// Climb (positive) or descent rate in feet per minute, over the same window as the turn rate.
+    pub fn vertical_rate_fpm(&self) -> f64 {
+        let Some((latest_time, latest_altitude)) = self
+            .fixes
+            .iter()
+            .rev()
+            .find_map(|fix| fix.altitude_ft.map(|altitude| (fix.time, altitude)))
+        else {
+            return 0.0;
+        };
+        let Some((earlier_time, earlier_altitude)) = self
+            .fixes
+            .iter()
+            .filter(|fix| latest_time - fix.time <= TURN_RATE_WINDOW_S && fix.time < latest_time)
+            .find_map(|fix| fix.altitude_ft.map(|altitude| (fix.time, altitude)))
+        else {
+            return 0.0;
+        };
+
+        f64::from(latest_altitude - earlier_altitude) / (latest_time - earlier_time) * 60.0
+    }
+}
+
+/// Dead-reckons along a constant turn, returns `(seconds ahead, position)` every `step_s`.
+pub fn predict_path(
+    fix: &Fix,
+    turn_rate_deg_s: f64,
+    duration_s: f64,
+    step_s: f64,
+) -> Vec<(f64, RealCoordinate)> {
+    let (Some(groundspeed), Some(track)) = (fix.groundspeed_kt, fix.track_deg) else {
+        return Vec::new();
+    };
+    let speed = f64::from(groundspeed) * geo::MPS_PER_KT;
+
+    let mut position = fix.position.clone();
+    let mut track = f64::from(track);
+    let mut elapsed = 0.0;
+    let mut path = vec![(0.0, position.clone())];
+
+    while elapsed < duration_s {
+        let dt = step_s.min(duration_s - elapsed);
+        // Fly the mean track of this step
+        let mean_track = track + turn_rate_deg_s * dt / 2.0;
+        position = geo::destination(&position, mean_track, speed * dt);
+        track += turn_rate_deg_s * dt;
+        elapsed += dt;
+        path.push((elapsed, position.clone()));
+    }
+
+    path
+}
+
+/// Where the aircraft is `elapsed_s` after `fix`, holding speed and turn rate.
+pub fn dead_reckon(fix: &Fix, turn_rate_deg_s: f64, elapsed_s: f64) -> Fix {
+    let mut reckoned = fix.clone();
+    reckoned.time = fix.time + elapsed_s;
+    let (Some(groundspeed), Some(track)) = (fix.groundspeed_kt, fix.track_deg) else {
+        return reckoned;
+    };
+
+    let mean_track = f64::from(track) + turn_rate_deg_s * elapsed_s / 2.0;
+    let distance = f64::from(groundspeed) * geo::MPS_PER_KT * elapsed_s;
+    reckoned.position = geo::destination(&fix.position, mean_track, distance);
+    reckoned.track_deg =
+        Some((f64::from(track) + turn_rate_deg_s * elapsed_s).rem_euclid(360.0) as f32);
+    reckoned
+}
+
+/// Linear interpolation between two fixes, taking the short way round for the track.
+pub fn interpolate(from: &Fix, to: &Fix, time: f64) -> Fix {
+    let span = to.time - from.time;
+    let t = if span > 0.0 {
+        ((time - from.time) / span).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let lerp = |a: f64, b: f64| a + (b - a) * t;
+
+    Fix {
+        time: lerp(from.time, to.time),
+        position: RealCoordinate {
+            lat: lerp(from.position.lat, to.position.lat),
+            lon: lerp(from.position.lon, to.position.lon),
+        },
+        altitude_ft: match (from.altitude_ft, to.altitude_ft) {
+            (Some(a), Some(b)) => Some(lerp(f64::from(a), f64::from(b)) as f32),
+            (a, b) => a.or(b),
+        },
+        altitude_hae: from.altitude_hae,
+        groundspeed_kt: match (from.groundspeed_kt, to.groundspeed_kt) {
+            (Some(a), Some(b)) => Some(lerp(f64::from(a), f64::from(b)) as f32),
+            (a, _) => a,
+        },
+        track_deg: match (from.track_deg, to.track_deg) {
+            (Some(a), Some(b)) => {
+                let change = f64::from(b - a + 540.0).rem_euclid(360.0) - 180.0;
+                Some((f64::from(a) + change * t).rem_euclid(360.0) as f32)
+            }
+            (a, _) => a,
+        },
+        source: from.source.clone(),
+    }
+}
+
+impl LivechartApp {
+    /// The ownship as drawn: between recorded fixes in a replay, dead-reckoned from the last fix
+    /// otherwise.
+    pub fn displayed_ownship(&self) -> Option<Fix> {
+        let fix = self.live.ownship.as_ref()?;
+        if !self.data.ownship_display.smooth_motion {
+            return Some(fix.clone());
+        }
+        if let Some(replay) = &self.live.replay {
+            return Some(replay.interpolated().unwrap_or_else(|| fix.clone()));
+        }
+
+        let Some(received) = self.live.ownship_received else {
+            return Some(fix.clone());
+        };
+        let elapsed = (utc::now() - received).clamp(0.0, MAX_EXTRAPOLATION_S);
+        Some(dead_reckon(fix, self.live.trail.turn_rate_deg_s(), elapsed))
+    }
+
+    /// Asks for the next frame just when the dead-reckoned ownship has moved by about a screen
+    /// point, instead of repainting continuously.
+    pub fn request_motion_repaint(
+        &self,
+        ctx: &egui::Context,
+        image_response: &egui::Response,
+        image_size: (u32, u32),
+        georeference: &Georeference,
+    ) {
+        // Replays repaint every frame while playing anyway
+        if !self.data.ownship_display.smooth_motion || self.live.replay.is_some() {
+            return;
+        }
+        let (Some(fix), Some(received)) = (&self.live.ownship, self.live.ownship_received) else {
+            return;
+        };
+        let Some(groundspeed) = fix.groundspeed_kt else {
+            return;
+        };
+        // Past the cap nothing moves until the next fix, which repaints by itself
+        let remaining = MAX_EXTRAPOLATION_S - (utc::now() - received);
+        if remaining <= 0.0 {
+            return;
+        }
+
+        let screen_per_pixel = f64::from(image_response.rect.width()) / f64::from(image_size.0);
+        let points_per_second = f64::from(groundspeed)
+            * geo::MPS_PER_KT
+            * georeference.pixels_per_metre()
+            * screen_per_pixel;
+        if points_per_second <= 0.0 {
+            return;
+        }
+
+        let interval = (MAX_STEP_POINTS / points_per_second)
+            .max(MIN_REPAINT_INTERVAL_S)
+            .min(remaining);
+        ctx.request_repaint_after(std::time::Duration::from_secs_f64(interval));
+    }
+}
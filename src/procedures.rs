@@ -0,0 +1,800 @@
+//! Approach procedures from the nav database, for georeferencing a plate by clicking the fixes it
+//! shows instead of typing their coordinates, and for drawing the coded procedure over the plate
+//! to check the result.
+
+use crate::app::LivechartApp;
+use crate::geo::{self, Georeference};
+use crate::navdb::{FixKind, FixRef, NavDatabase, ProcedureLeg};
+use crate::structs::RealCoordinate;
+
+/// Control points further off than this, in image pixels, are probably on the wrong symbol.
+const MAX_RESIDUAL_PX: f32 = 6.0;
+/// Scale difference between directions above which a fit is suspicious.
+const MAX_ANISOTROPY: f64 = 1.1;
+
+/// Stands out from the black printed on plates and from the magenta ownship.
+const PROCEDURE_COLOR: egui::Color32 = egui::Color32::from_rgb(0, 200, 160);
+/// Drawn length of legs that end at an altitude, an intercept or a manual termination.
+const OPEN_LEG_M: f64 = 2.0 * geo::METRES_PER_NM;
+/// Holds timed in minutes, at a typical approach speed.
+const HOLD_NM_PER_MIN: f64 = 3.5;
+/// Holds with neither a time nor a distance.
+const DEFAULT_HOLD_M: f64 = 4.0 * geo::METRES_PER_NM;
+/// Standard rate turn at about 200 kt.
+const HOLD_TURN_RADIUS_M: f64 = geo::METRES_PER_NM;
+const ARC_STEP_DEG: f64 = 5.0;
+
+/// `I19C` to "ILS 19C", after the route type letter CIFP puts in front of the runway.
+pub fn procedure_name(ident: &str) -> String {
+    let mut chars = ident.chars();
+    let kind = match chars.next() {
+        Some('I') => "ILS",
+        Some('L') => "LOC",
+        Some('B') => "LOC BC",
+        Some('D') => "VOR/DME",
+        Some('V' | 'S') => "VOR",
+        Some('N') => "NDB",
+        Some('Q') => "NDB/DME",
+        Some('R') => "RNAV (GPS)",
+        Some('H') => "RNAV (RNP)",
+        Some('P') => "GPS",
+        Some('X') => "LDA",
+        Some('U') => "SDF",
+        Some('T') => "TACAN",
+        Some('J') => "GLS",
+        _ => return ident.to_owned(),
+    };
+    format!("{kind} {}", chars.as_str().trim_matches('-'))
+}
+
+/// A fix of a procedure, with what the plate calls it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcedureFix {
+    /// "IAF", "FAF", "MAP", ... or "Navaid" for fixes the legs only refer to.
+    pub role: &'static str,
+    pub ident: String,
+    pub position: RealCoordinate,
+}
+
+fn role(description: &str, fix: &FixRef) -> &'static str {
+    match description.chars().nth(3) {
+        Some('A' | 'C' | 'D') => "IAF",
+        Some('B') => "IF",
+        Some('I') => "FACF",
+        Some('F') => "FAF",
+        Some('M') => "MAP",
+        Some('H') => "Holding fix",
+        _ if fix.section == 'P' && fix.subsection == 'G' => "Runway",
+        _ => "Fix",
+    }
+}
+
+/// The fixes of a procedure in the order the plate flies them: transitions, then the final
+/// approach down to the missed approach point, then the navaids the legs are based on. Each fix
+/// appears once.
+pub fn procedure_fixes(
+    database: &NavDatabase,
+    airport: &str,
+    procedure: &str,
+) -> Vec<ProcedureFix> {
+    let legs = database.legs(airport, procedure);
+    let transitions = legs.iter().filter(|leg| leg.route_type == 'A');
+    // The final approach ends at the missed approach point, the rest is the missed approach
+    let mut past_map = false;
+    let approach = legs
+        .iter()
+        .filter(|leg| leg.route_type != 'A')
+        .take_while(|leg| {
+            let before = !past_map;
+            past_map |= leg.is_missed_approach_point();
+            before
+        });
+
+    let mut fixes: Vec<ProcedureFix> = Vec::new();
+    let mut add = |fix: &FixRef, role: &'static str| {
+        if fixes.iter().any(|known| known.ident == fix.ident) {
+            return;
+        }
+        if let Some(found) = database.resolve(fix, airport) {
+            fixes.push(ProcedureFix {
+                role,
+                ident: found.ident.clone(),
+                position: found.position.clone(),
+            });
+        }
+    };
+
+    let flown: Vec<_> = transitions.chain(approach).collect();
+    for leg in &flown {
+        if let Some(fix) = &leg.fix {
+            add(fix, role(&leg.description, fix));
+        }
+    }
+    for leg in &flown {
+        for navaid in [&leg.recommended_navaid, &leg.center_fix]
+            .into_iter()
+            .flatten()
+        {
+            add(navaid, "Navaid");
+        }
+    }
+    fixes
+}
+
+/// `16L` to `34R`.
+fn reciprocal_runway(designator: &str) -> Option<String> {
+    let digits: String = designator
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    let number: u32 = digits.parse().ok()?;
+    let side = match &designator[digits.len()..] {
+        "L" => "R",
+        "R" => "L",
+        side => side,
+    };
+    let opposite = (number + 17) % 36 + 1;
+    Some(format!("{opposite:02}{side}"))
+}
+
+/// Runway thresholds of an airport, both ends of each runway one after the other, for
+/// georeferencing an airport diagram.
+pub fn runway_thresholds(database: &NavDatabase, airport: &str) -> Vec<ProcedureFix> {
+    let mut runways: Vec<_> = database
+        .fixes
+        .iter()
+        .filter(|fix| {
+            matches!(fix.kind, FixKind::Runway { .. }) && fix.airport.as_deref() == Some(airport)
+        })
+        .collect();
+    runways.sort_by(|a, b| a.ident.cmp(&b.ident));
+
+    let mut ordered: Vec<ProcedureFix> = Vec::new();
+    for runway in &runways {
+        if ordered.iter().any(|known| known.ident == runway.ident) {
+            continue;
+        }
+        let opposite =
+            reciprocal_runway(runway.ident.trim_start_matches("RW")).and_then(|designator| {
+                let ident = format!("RW{designator}");
+                runways.iter().find(|other| other.ident == ident)
+            });
+        for end in std::iter::once(runway).chain(opposite) {
+            ordered.push(ProcedureFix {
+                role: "Threshold",
+                ident: end.ident.clone(),
+                position: end.position.clone(),
+            });
+        }
+    }
+    ordered
+}
+
+/// Clicking through the fixes of a procedure, or the runway thresholds of an airport diagram.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcedureGeoref {
+    pub fixes: Vec<ProcedureFix>,
+    /// Index of the fix the next click places.
+    pub next: usize,
+}
+
+impl LivechartApp {
+    /// The position of the fix a click on the chart should place, if a procedure is being
+    /// clicked through. Moves on to the next fix.
+    pub fn take_procedure_fix(&mut self) -> Option<RealCoordinate> {
+        let georef = self.live.procedure_georef.as_mut()?;
+        let fix = georef.fixes.get(georef.next)?.position.clone();
+        georef.next += 1;
+        if georef.next >= georef.fixes.len() {
+            self.live.procedure_georef = None;
+        }
+        Some(fix)
+    }
+
+    pub fn procedures_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.live.procedures_window_open;
+        egui::Window::new("Procedures")
+            .open(&mut open)
+            .default_width(320.0)
+            .show(ctx, |ui| {
+                let Some(database) = self.live.navdb.clone() else {
+                    ui.label("Load navigation data first.");
+                    return;
+                };
+
+                self.chart_airport_editor(ui);
+                let airport = self.data.chart_airport.trim().to_uppercase();
+                let selected = if self.data.chart_procedure.is_empty() {
+                    "Choose a procedure".to_owned()
+                } else {
+                    procedure_name(&self.data.chart_procedure)
+                };
+                egui::ComboBox::from_label("Procedure")
+                    .selected_text(selected)
+                    .show_ui(ui, |ui| {
+                        for procedure in database.procedures_at(&airport) {
+                            ui.selectable_value(
+                                &mut self.data.chart_procedure,
+                                procedure.to_owned(),
+                                procedure_name(procedure),
+                            );
+                        }
+                    });
+
+                ui.checkbox(&mut self.data.show_procedure, "Draw on the chart")
+                    .on_hover_text("The coded legs, to check the georeference against the plate");
+                ui.checkbox(&mut self.data.show_deviation, "Show deviation")
+                    .on_hover_text("Course and glide path deviation on the final approach");
+
+                ui.separator();
+                match &mut self.live.procedure_georef {
+                    Some(georef) => {
+                        ui.label("Click each fix on the chart:");
+                        for (index, fix) in georef.fixes.iter().enumerate() {
+                            let text = format!("{} {}", fix.role, fix.ident);
+                            if index < georef.next {
+                                ui.weak(format!("✔ {text}"));
+                            } else if index == georef.next {
+                                ui.strong(format!("▶ {text}"));
+                            } else {
+                                ui.label(text);
+                            }
+                        }
+                        ui.horizontal(|ui| {
+                            if ui
+                                .button("Skip")
+                                .on_hover_text("For fixes the plate doesn't show")
+                                .clicked()
+                            {
+                                georef.next += 1;
+                            }
+                            if ui.button("Stop").clicked() {
+                                georef.next = georef.fixes.len();
+                            }
+                        });
+                        if georef.next >= georef.fixes.len() {
+                            self.live.procedure_georef = None;
+                        }
+                    }
+                    None => {
+                        let fixes =
+                            procedure_fixes(&database, &airport, &self.data.chart_procedure);
+                        let thresholds = runway_thresholds(&database, &airport);
+                        ui.horizontal(|ui| {
+                            let start = ui.add_enabled(
+                                !fixes.is_empty(),
+                                egui::Button::new("Place fixes by clicking"),
+                            );
+                            if start.clicked() {
+                                self.live.procedure_georef =
+                                    Some(ProcedureGeoref { fixes, next: 0 });
+                            }
+
+                            let diagram = ui
+                                .add_enabled(
+                                    !thresholds.is_empty(),
+                                    egui::Button::new("Place runway thresholds"),
+                                )
+                                .on_hover_text(
+                                    "For airport diagrams: click the threshold at each end of \
+                                     every runway",
+                                );
+                            if diagram.clicked() {
+                                self.live.procedure_georef = Some(ProcedureGeoref {
+                                    fixes: thresholds,
+                                    next: 0,
+                                });
+                            }
+                        });
+                    }
+                }
+
+                ui.separator();
+                self.georeference_check(ui);
+            });
+        self.live.procedures_window_open = open;
+    }
+
+    /// How well the control points agree with each other.
+    fn georeference_check(&self, ui: &mut egui::Ui) {
+        let placed: Vec<_> = self
+            .data
+            .points
+            .iter()
+            .filter_map(|p| p.real.as_ref())
+            .collect();
+        let Some(georeference) = Georeference::fit(&self.data.points) else {
+            ui.label(format!("{} of 2 control points placed", placed.len()));
+            return;
+        };
+
+        let residuals = georeference.residuals_px(&self.data.points);
+        let count = residuals.len() as f32;
+        let rms = (residuals.iter().map(|r| r * r).sum::<f32>() / count).sqrt();
+        let worst = residuals.iter().copied().fold(0.0f32, f32::max);
+        ui.label(format!(
+            "{} control points, {:.1} px RMS, {:.1} px worst",
+            residuals.len(),
+            rms,
+            worst
+        ));
+        ui.weak(format!(
+            "{}, {:.0} px per NM",
+            if georeference.similarity {
+                "Scale and rotation only"
+            } else {
+                "Affine"
+            },
+            georeference.pixels_per_metre() * geo::METRES_PER_NM
+        ));
+
+        // The fewest points that define the fit always fit exactly
+        let exact = if georeference.similarity { 2 } else { 3 };
+        let mut problems = Vec::new();
+        if placed.len() == exact {
+            problems.push("Add another control point to check the others".to_owned());
+        }
+        if georeference.similarity && placed.len() >= 3 {
+            problems.push(
+                "The control points are in a line. Add one off the final approach course, like \
+                 an IAF or a navaid, unless this is an airport diagram."
+                    .to_owned(),
+            );
+        }
+        if worst > MAX_RESIDUAL_PX {
+            problems.push(format!(
+                "A control point is {worst:.0} px off the fit, check it is on its symbol"
+            ));
+        }
+        if georeference.is_mirrored() {
+            problems.push("The chart comes out mirrored, two points are swapped".to_owned());
+        }
+        let anisotropy = georeference.anisotropy();
+        if anisotropy > MAX_ANISOTROPY {
+            problems.push(format!(
+                "The chart is stretched {:.0}% more one way than the other",
+                (anisotropy - 1.0) * 100.0
+            ));
+        }
+
+        if problems.is_empty() && placed.len() > exact {
+            ui.label("✔ The georeference is consistent");
+        }
+        for problem in problems {
+            ui.colored_label(ui.visuals().warn_fg_color, problem);
+        }
+    }
+
+    /// The coded procedure over the chart, for comparing with the one printed on it.
+    pub fn draw_procedure(
+        &self,
+        ui: &egui::Ui,
+        image_response: &egui::Response,
+        image_size: (u32, u32),
+        georeference: &Georeference,
+    ) {
+        let Some(database) = &self.live.navdb else {
+            return;
+        };
+        let airport = self.data.chart_airport.trim().to_uppercase();
+        let procedure = &self.data.chart_procedure;
+        let painter = ui.painter_at(image_response.rect);
+        let stroke = egui::Stroke::new(2.0, PROCEDURE_COLOR);
+        let to_screen = |real: &RealCoordinate| {
+            self.real_to_screen(real, georeference, image_response, image_size)
+        };
+
+        for path in procedure_paths(database, &airport, procedure) {
+            let points: Vec<egui::Pos2> = path.points.iter().map(to_screen).collect();
+            if path.missed {
+                painter.add(egui::Shape::dashed_line(&points, stroke, 8.0, 5.0));
+            } else {
+                painter.add(egui::Shape::line(points, stroke));
+            }
+        }
+
+        for fix in procedure_fixes(database, &airport, procedure) {
+            let pos = to_screen(&fix.position);
+            painter.circle_stroke(pos, 5.0, stroke);
+            painter.text(
+                pos + egui::vec2(7.0, -7.0),
+                egui::Align2::LEFT_BOTTOM,
+                &fix.ident,
+                egui::FontId::proportional(12.0),
+                PROCEDURE_COLOR,
+            );
+        }
+    }
+}
+
+/// A line of the procedure on the ground.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcedurePath {
+    pub points: Vec<RealCoordinate>,
+    /// Part of the missed approach, drawn dashed like on the plate.
+    pub missed: bool,
+}
+
+/// Points along an arc around `center`, turning right or left from one bearing to the other.
+fn arc(
+    center: &RealCoordinate,
+    from_deg: f64,
+    to_deg: f64,
+    radius_m: f64,
+    right: bool,
+) -> Vec<RealCoordinate> {
+    let sweep = if right {
+        (to_deg - from_deg).rem_euclid(360.0)
+    } else {
+        -(from_deg - to_deg).rem_euclid(360.0)
+    };
+    let steps = (sweep.abs() / ARC_STEP_DEG).ceil().max(1.0) as usize;
+    (0..=steps)
+        .map(|step| {
+            let bearing = from_deg + sweep * step as f64 / steps as f64;
+            geo::destination(center, bearing, radius_m)
+        })
+        .collect()
+}
+
+/// Racetrack of a hold at `fix`, with the inbound leg on `inbound_deg` (true).
+fn hold(fix: &RealCoordinate, inbound_deg: f64, length_m: f64, right: bool) -> Vec<RealCoordinate> {
+    let side = if right { 90.0 } else { -90.0 };
+    let start = geo::destination(fix, inbound_deg + 180.0, length_m);
+    let abeam_start = geo::destination(&start, inbound_deg + side, 2.0 * HOLD_TURN_RADIUS_M);
+
+    // Inbound to the fix, turn outbound, outbound leg, turn back to the start of the inbound leg
+    let turn_at_fix = geo::destination(fix, inbound_deg + side, HOLD_TURN_RADIUS_M);
+    let turn_at_start = geo::destination(&start, inbound_deg + side, HOLD_TURN_RADIUS_M);
+    let mut points = vec![start.clone(), fix.clone()];
+    points.extend(arc(
+        &turn_at_fix,
+        inbound_deg + side + 180.0,
+        inbound_deg + side,
+        HOLD_TURN_RADIUS_M,
+        right,
+    ));
+    points.push(abeam_start);
+    points.extend(arc(
+        &turn_at_start,
+        inbound_deg + side,
+        inbound_deg + side + 180.0,
+        HOLD_TURN_RADIUS_M,
+        right,
+    ));
+    points
+}
+
+/// The legs of each transition and of the final approach, as lines on the ground. Legs are
+/// drawn the way they are coded, without the turn anticipation an aircraft would fly.
+pub fn procedure_paths(
+    database: &NavDatabase,
+    airport: &str,
+    procedure: &str,
+) -> Vec<ProcedurePath> {
+    let variation = match database.airport(airport).map(|fix| &fix.kind) {
+        Some(FixKind::Airport { variation_deg, .. }) => f64::from(variation_deg.unwrap_or(0.0)),
+        _ => 0.0,
+    };
+    let resolve = |fix: &Option<FixRef>| {
+        fix.as_ref()
+            .and_then(|fix| database.resolve(fix, airport))
+            .map(|found| found.position.clone())
+    };
+    let true_course = |leg: &ProcedureLeg| {
+        leg.course_deg.map(|course| {
+            let course = f64::from(course);
+            if leg.course_true {
+                course
+            } else {
+                course + variation
+            }
+        })
+    };
+
+    let legs = database.legs(airport, procedure);
+    let mut paths = Vec::new();
+    // Legs are sorted by transition and route type, so each route is one run
+    for route in
+        legs.chunk_by(|a, b| (a.route_type, &a.transition) == (b.route_type, &b.transition))
+    {
+        let mut current = ProcedurePath {
+            points: Vec::new(),
+            missed: false,
+        };
+        let mut position: Option<RealCoordinate> = None;
+
+        for leg in route {
+            let target = resolve(&leg.fix);
+            let right = leg.turn_direction != Some('L');
+            let mut points = Vec::new();
+
+            match leg.path_terminator.as_str() {
+                "IF" => {
+                    if let Some(target) = target {
+                        points.push(target);
+                    }
+                }
+                "RF" | "AF" => {
+                    let center = if leg.path_terminator == "RF" {
+                        resolve(&leg.center_fix)
+                    } else {
+                        resolve(&leg.recommended_navaid)
+                    };
+                    if let (Some(from), Some(center), Some(target)) = (&position, center, &target) {
+                        points = arc(
+                            &center,
+                            geo::bearing_deg(&center, from),
+                            geo::bearing_deg(&center, target),
+                            geo::distance_m(&center, target),
+                            right,
+                        );
+                    } else if let Some(target) = target {
+                        points.push(target);
+                    }
+                }
+                "HA" | "HF" | "HM" => {
+                    if let (Some(target), Some(course)) = (&target, true_course(leg)) {
+                        let length = leg
+                            .distance_nm
+                            .map(|nm| f64::from(nm) * geo::METRES_PER_NM)
+                            .or(leg
+                                .hold_time_min
+                                .map(|min| f64::from(min) * HOLD_NM_PER_MIN * geo::METRES_PER_NM))
+                            .unwrap_or(DEFAULT_HOLD_M);
+                        paths.push(ProcedurePath {
+                            points: hold(target, course, length, right),
+                            missed: current.missed,
+                        });
+                    }
+                    points.extend(target);
+                }
+                // Course to a fix, without a previous position it starts on the course
+                "CF" if position.is_none() => {
+                    if let (Some(target), Some(course)) = (&target, true_course(leg)) {
+                        let length = leg
+                            .distance_nm
+                            .map_or(OPEN_LEG_M, |nm| f64::from(nm) * geo::METRES_PER_NM);
+                        points.push(geo::destination(target, course + 180.0, length));
+                    }
+                    points.extend(target);
+                }
+                "TF" | "CF" | "DF" => points.extend(target),
+                // From a fix or the current position along a course, ending somewhere the
+                // database doesn't pin down
+                "CA" | "VA" | "CI" | "VI" | "CD" | "VD" | "CR" | "VR" | "VM" | "FA" | "FC"
+                | "FD" | "FM" => {
+                    let from = if leg.path_terminator.starts_with('F') {
+                        target.or(position.clone())
+                    } else {
+                        position.clone()
+                    };
+                    if let (Some(from), Some(course)) = (from, true_course(leg)) {
+                        let length = leg
+                            .distance_nm
+                            .map_or(OPEN_LEG_M, |nm| f64::from(nm) * geo::METRES_PER_NM);
+                        points.push(from.clone());
+                        points.push(geo::destination(&from, course, length));
+                    }
+                }
+                // Procedure turns are left to the plate
+                _ => points.extend(target),
+            }
+
+            if let Some(last) = points.last() {
+                position = Some(last.clone());
+            }
+            current.points.extend(points);
+
+            // The missed approach starts at the missed approach point
+            if leg.is_missed_approach_point() {
+                let map = current.points.last().cloned();
+                paths.push(std::mem::replace(
+                    &mut current,
+                    ProcedurePath {
+                        points: map.into_iter().collect(),
+                        missed: true,
+                    },
+                ));
+            }
+        }
+        paths.push(current);
+    }
+
+    paths.retain(|path| path.points.len() >= 2);
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// KDEN, runway 16R, the BJC VOR and a made-up RNAV (GPS) Y 16R with an RF transition, a DME
+    /// arc transition around BJC, a final approach to the runway and a missed approach to a hold.
+    const CIFP: [&str; 21] = [
+        "SUSAP KDENK2ADEN     0     160Y N39514200W104402300E008005434                                DENVER INTL                   123452510",
+        "SUSAP KDENK2GRW16R   0160001739 N39534370W104414770               05330000055200                                           123452510",
+        "SUSAD        BJC   K2011540VDHW N39543878W105082098BJC N39543878W105082098E012005670         JEFFCO                        123452510",
+        "SUSAP KDENK2CKIPPR K20    C     N40023100W104370200                       E0080                   KIPPR                    123452510",
+        "SUSAP KDENK2CBJCAR K20    C     N39543714W104484844                       E0080                   BJCAR                    123452510",
+        "SUSAP KDENK2CARCEN K20    C     N39445970W104532486                       E0080                   ARCEN                    123452510",
+        "SUSAP KDENK2CCRUPP K20    C     N40073063W104364900                       E0080                   CRUPP                    123452510",
+        "SUSAP KDENK2CCFZIM K20    C     N40073653W104404412                       E0080                   CFZIM                    123452510",
+        "SUSAP KDENK2CZIMMR K20    C     N40103631W104403632                       E0080                   ZIMMR                    123452510",
+        "SUSAP KDENK2CDANDD K20    C     N39454429W104420839                       E0080                   DANDD                    123452510",
+        "SUSAP KDENK2FR16RY ABJC   010BJCARK2PC0E  A    IF                                                                          123452510",
+        "SUSAP KDENK2FR16RY ABJC   020ARCENK2PC0E   R   AF BJC K2                      D                                            123452510",
+        "SUSAP KDENK2FR16RY ABJC   030CRUPPK2PC0E  B    TF                                                                          123452510",
+        "SUSAP KDENK2FR16RY AZIMMR 010ZIMMRK2PC0E  A    IF                                                                          123452510",
+        "SUSAP KDENK2FR16RY AZIMMR 020CRUPPK2PC0E  BR   RF                                                         CFZIM K2PC       123452510",
+        "SUSAP KDENK2FR16RY R      010CRUPPK2PC0E  B    IF                                                                          123452510",
+        "SUSAP KDENK2FR16RY R      020KIPPRK2PC0E  F    TF                                                                          123452510",
+        "SUSAP KDENK2FR16RY R      030RW16RK2PG0GY M    TF                                                                          123452510",
+        "SUSAP KDENK2FR16RY R      040         0        CA                     1739                                                 123452510",
+        "SUSAP KDENK2FR16RY R      050DANDDK2PC0E       DF                                                                          123452510",
+        "SUSAP KDENK2FR16RY R      060DANDDK2PC0E  HR   HM                     3539T010                                             123452510",
+    ];
+
+    fn database() -> NavDatabase {
+        let database = NavDatabase::parse(&CIFP.join("\n"));
+        assert_eq!(database.skipped, 0);
+        database
+    }
+
+    fn position(database: &NavDatabase, ident: &str) -> RealCoordinate {
+        database.lookup(ident).next().unwrap().position.clone()
+    }
+
+    #[test]
+    fn fixes_run_from_the_transitions_to_the_missed_approach_point() {
+        // CRUPP ends both transitions and starts the final, DANDD is only in the missed approach
+        let fixes = procedure_fixes(&database(), "KDEN", "R16RY");
+        let listed: Vec<(&str, &str)> = fixes
+            .iter()
+            .map(|fix| (fix.role, fix.ident.as_str()))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("IAF", "BJCAR"),
+                ("Fix", "ARCEN"),
+                ("IF", "CRUPP"),
+                ("IAF", "ZIMMR"),
+                ("FAF", "KIPPR"),
+                ("MAP", "RW16R"),
+                ("Navaid", "BJC"),
+                ("Navaid", "CFZIM"),
+            ]
+        );
+    }
+
+    #[test]
+    fn fixes_carry_their_database_positions() {
+        let database = database();
+        for fix in procedure_fixes(&database, "KDEN", "R16RY") {
+            assert_eq!(
+                fix.position,
+                position(&database, &fix.ident),
+                "{}",
+                fix.ident
+            );
+        }
+        assert!(procedure_fixes(&database, "KDEN", "I16R").is_empty());
+        assert!(procedure_fixes(&database, "KAPA", "R16RY").is_empty());
+    }
+
+    #[test]
+    fn reciprocal_runways() {
+        assert_eq!(reciprocal_runway("36").as_deref(), Some("18"));
+        assert_eq!(reciprocal_runway("18").as_deref(), Some("36"));
+        assert_eq!(reciprocal_runway("16L").as_deref(), Some("34R"));
+        assert_eq!(reciprocal_runway("34R").as_deref(), Some("16L"));
+        assert_eq!(reciprocal_runway("09C").as_deref(), Some("27C"));
+        assert_eq!(reciprocal_runway("01").as_deref(), Some("19"));
+        assert_eq!(reciprocal_runway("ALL"), None);
+    }
+
+    fn assert_near(actual: &RealCoordinate, expected: &RealCoordinate) {
+        let off = geo::distance_m(actual, expected);
+        assert!(off < 1.0, "{actual:?} is {off:.1} m from {expected:?}");
+    }
+
+    /// Paths in the order they are built: the final approach, the hold, the rest of the missed
+    /// approach, then the BJC and ZIMMR transitions.
+    fn paths() -> (NavDatabase, Vec<ProcedurePath>) {
+        let database = database();
+        let paths = procedure_paths(&database, "KDEN", "R16RY");
+        assert_eq!(
+            paths.iter().map(|path| path.missed).collect::<Vec<_>>(),
+            [false, true, true, false, false]
+        );
+        (database, paths)
+    }
+
+    #[test]
+    fn the_missed_approach_starts_at_the_missed_approach_point() {
+        let (database, paths) = paths();
+        let runway = position(&database, "RW16R");
+
+        let approach = &paths[0].points;
+        assert_eq!(approach.len(), 3);
+        assert_near(&approach[0], &position(&database, "CRUPP"));
+        assert_near(&approach[1], &position(&database, "KIPPR"));
+        assert_near(&approach[2], &runway);
+
+        // Climb on the runway heading (173.9° magnetic, 8° east) for the open leg length, then
+        // direct to the holding fix
+        let missed = &paths[2].points;
+        assert_near(&missed[0], &runway);
+        assert_near(&missed[2], &geo::destination(&runway, 181.9, OPEN_LEG_M));
+        assert_near(missed.last().unwrap(), &position(&database, "DANDD"));
+    }
+
+    #[test]
+    fn holds_are_drawn_as_racetracks() {
+        let (database, paths) = paths();
+        let fix = position(&database, "DANDD");
+        let hold = &paths[1].points;
+
+        // One minute inbound on 353.9° magnetic, right turns put the racetrack east of it
+        let length = HOLD_NM_PER_MIN * geo::METRES_PER_NM;
+        assert_near(&hold[0], &geo::destination(&fix, 181.9, length));
+        assert_near(&hold[1], &fix);
+        assert_near(hold.last().unwrap(), &hold[0]);
+        // Distance right of the inbound course through the fix
+        let inbound = 1.9f64.to_radians();
+        let right_of_course = |point: &RealCoordinate| {
+            let (east, north) = geo::project(&fix, point);
+            east * inbound.cos() - north * inbound.sin()
+        };
+        for point in hold {
+            assert!(
+                right_of_course(point) > -1.0,
+                "{point:?} is left of the inbound leg"
+            );
+        }
+        let widest = hold.iter().map(right_of_course).fold(0.0, f64::max);
+        assert!((widest - 2.0 * HOLD_TURN_RADIUS_M).abs() < 5.0);
+    }
+
+    #[test]
+    fn dme_arcs_keep_their_distance_from_the_navaid() {
+        let (database, paths) = paths();
+        let navaid = position(&database, "BJC");
+        let arc = &paths[3].points;
+
+        assert_near(&arc[0], &position(&database, "BJCAR"));
+        assert_near(arc.last().unwrap(), &position(&database, "CRUPP"));
+        // BJCAR is due east of BJC, ARCEN on the 130° radial, both at 15 NM
+        let along_arc = &arc[1..arc.len() - 1];
+        assert_near(along_arc.last().unwrap(), &position(&database, "ARCEN"));
+        let mut previous = 0.0;
+        for point in along_arc {
+            let radius = geo::distance_m(&navaid, point) / geo::METRES_PER_NM;
+            assert!((radius - 15.0).abs() < 0.01, "{radius} NM");
+            let bearing = geo::bearing_deg(&navaid, point);
+            assert!(bearing > previous, "clockwise");
+            previous = bearing;
+        }
+    }
+
+    #[test]
+    fn radius_to_fix_legs_turn_around_their_center() {
+        let (database, paths) = paths();
+        let center = position(&database, "CFZIM");
+        let arc = &paths[4].points;
+
+        assert_near(&arc[0], &position(&database, "ZIMMR"));
+        assert_near(arc.last().unwrap(), &position(&database, "CRUPP"));
+        // A quarter turn to the right at 3 NM, in 5° steps after the IF
+        assert_eq!(arc.len(), 1 + 19);
+        let mut previous = 0.0;
+        for point in &arc[1..] {
+            let radius = geo::distance_m(&center, point) / geo::METRES_PER_NM;
+            assert!((radius - 3.0).abs() < 0.01, "{radius} NM");
+            let bearing = geo::bearing_deg(&center, point);
+            assert!(bearing > previous, "clockwise");
+            previous = bearing;
+        }
+    }
+}
@@ -0,0 +1,625 @@
+//! Recording ownship fixes into session files and exporting them to GPX and KML.
+//!
+//! A session file is JSON Lines: a [`SessionHeader`] on the first line, then one [`Fix`] per
+//! line. Fixes are appended as they arrive so a crash loses at most the last fix.
+
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::app::LivechartApp;
+use crate::geo;
+use crate::replay::{Replay, TrackLog};
+use crate::structs::{CoordinatePair, Fix};
+use crate::utc;
+
+/// A gap in the fix stream longer than this means the feed went away and came back, which ends
+/// a manual pause of auto-record.
+const RECONNECT_GAP_S: f64 = 30.0;
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+pub struct SessionHeader {
+    /// Unix time at which recording started.
+    pub started: f64,
+    /// Chart that was on screen when recording started.
+    pub chart: String,
+    /// Control points of that chart, so the recording can be shown on it again later.
+    pub points: Vec<CoordinatePair>,
+}
+
+/// An open session file that fixes are appended to.
+pub struct Recorder {
+    pub path: PathBuf,
+    pub fix_count: usize,
+    file: std::fs::File,
+}
+
+impl Recorder {
+    pub fn start(directory: &Path, header: &SessionHeader) -> Result<Self, String> {
+        std::fs::create_dir_all(directory)
+            .map_err(|e| format!("Could not create {}: {e}", directory.display()))?;
+
+        let (year, month, day) = utc::civil_from_days((header.started / 86_400.0).floor() as i64);
+        let clock = utc::format_clock(header.started).replace(':', "");
+        let name = format!("{year:04}{month:02}{day:02}-{clock}");
+
+        // Sessions started within the same second get a suffix instead of replacing each other
+        let mut attempt = 1;
+        let (path, mut file) = loop {
+            let path = if attempt == 1 {
+                directory.join(format!("{name}.jsonl"))
+            } else {
+                directory.join(format!("{name}-{attempt}.jsonl"))
+            };
+            match std::fs::File::options()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(file) => break (path, file),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => attempt += 1,
+                Err(e) => return Err(format!("Could not create {}: {e}", path.display())),
+            }
+        };
+        let line = serde_json::to_string(header).map_err(|e| e.to_string())?;
+        writeln!(file, "{line}").map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            path,
+            fix_count: 0,
+            file,
+        })
+    }
+
+    pub fn record(&mut self, fix: &Fix) -> Result<(), String> {
+        let line = serde_json::to_string(fix).map_err(|e| e.to_string())?;
+        writeln!(self.file, "{line}").map_err(|e| e.to_string())?;
+        self.fix_count += 1;
+        Ok(())
+    }
+}
+
+/// A finished (or interrupted) recording read back from disk.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub path: PathBuf,
+    pub header: SessionHeader,
+    pub fixes: Vec<Fix>,
+}
+
+impl Session {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+        let mut lines = text.lines();
+
+        let header = lines
+            .next()
+            .ok_or_else(|| format!("{} is empty", path.display()))?;
+        let header: SessionHeader = serde_json::from_str(header)
+            .map_err(|e| format!("{} is not a session file: {e}", path.display()))?;
+
+        // A torn last line after a crash is not worth failing the whole session for
+        let fixes = lines
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+
+        Ok(Self {
+            path: path.to_owned(),
+            header,
+            fixes,
+        })
+    }
+
+    /// Every session in `directory`, newest first.
+    pub fn list(directory: &Path) -> Vec<Self> {
+        let Ok(entries) = std::fs::read_dir(directory) else {
+            return Vec::new();
+        };
+
+        let mut sessions: Vec<Self> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|e| e == "jsonl"))
+            .filter_map(|path| Self::load(&path).ok())
+            .collect();
+        sessions.sort_by(|a, b| b.header.started.total_cmp(&a.header.started));
+        sessions
+    }
+
+    pub fn duration(&self) -> f64 {
+        match (self.fixes.first(), self.fixes.last()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.0,
+        }
+    }
+
+    fn title(&self) -> String {
+        format!(
+            "LiveChart {} ({})",
+            utc::format_iso8601(self.header.started),
+            self.header.chart
+        )
+    }
+
+    pub fn to_gpx(&self) -> String {
+        let mut gpx = String::from(concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            "\n",
+            r#"<gpx version="1.1" creator="LiveChart" xmlns="http://www.topografix.com/GPX/1/1" "#,
+            r#"xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v2">"#,
+            "\n"
+        ));
+        gpx += &format!(
+            "  <metadata><name>{}</name><time>{}</time></metadata>\n",
+            escape_xml(&self.title()),
+            utc::format_iso8601(self.header.started)
+        );
+        gpx += &format!(
+            "  <trk>\n    <name>{}</name>\n    <trkseg>\n",
+            escape_xml(&self.title())
+        );
+
+        for fix in &self.fixes {
+            gpx += &format!(
+                r#"      <trkpt lat="{:.7}" lon="{:.7}">"#,
+                fix.position.lat, fix.position.lon
+            );
+            if let Some(altitude) = fix.altitude_ft {
+                gpx += &format!("<ele>{:.1}</ele>", f64::from(altitude) * geo::METRES_PER_FT);
+            }
+            gpx += &format!("<time>{}</time>", utc::format_iso8601(fix.time));
+            gpx += &format!("<src>{}</src>", escape_xml(&fix.source));
+            if fix.groundspeed_kt.is_some() || fix.track_deg.is_some() {
+                gpx += "<extensions><gpxtpx:TrackPointExtension>";
+                if let Some(groundspeed) = fix.groundspeed_kt {
+                    gpx += &format!(
+                        "<gpxtpx:speed>{:.2}</gpxtpx:speed>",
+                        f64::from(groundspeed) * geo::MPS_PER_KT
+                    );
+                }
+                if let Some(track) = fix.track_deg {
+                    gpx += &format!("<gpxtpx:course>{track:.1}</gpxtpx:course>");
+                }
+                gpx += "</gpxtpx:TrackPointExtension></extensions>";
+            }
+            gpx += "</trkpt>\n";
+        }
+
+        gpx += "    </trkseg>\n  </trk>\n</gpx>\n";
+        gpx
+    }
+
+    pub fn to_kml(&self) -> String {
+        let mut kml = String::from(concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            "\n",
+            r#"<kml xmlns="http://www.opengis.net/kml/2.2" xmlns:gx="http://www.google.com/kml/ext/2.2">"#,
+            "\n<Document>\n"
+        ));
+        kml += &format!("  <name>{}</name>\n", escape_xml(&self.title()));
+        kml += "  <Style id=\"track\"><LineStyle><color>ffff00ff</color><width>3</width></LineStyle></Style>\n";
+        kml += &format!(
+            "  <Placemark>\n    <name>{}</name>\n    <styleUrl>#track</styleUrl>\n",
+            escape_xml(&self.header.chart)
+        );
+        kml += "    <gx:Track>\n      <altitudeMode>absolute</altitudeMode>\n";
+
+        for fix in &self.fixes {
+            kml += &format!("      <when>{}</when>\n", utc::format_iso8601(fix.time));
+        }
+        for fix in &self.fixes {
+            let altitude = fix
+                .altitude_ft
+                .map_or(0.0, |a| f64::from(a) * geo::METRES_PER_FT);
+            kml += &format!(
+                "      <gx:coord>{:.7} {:.7} {altitude:.1}</gx:coord>\n",
+                fix.position.lon, fix.position.lat
+            );
+        }
+
+        kml += "    </gx:Track>\n  </Placemark>\n</Document>\n</kml>\n";
+        kml
+    }
+}
+
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Where session files live.
+pub fn sessions_dir() -> Option<PathBuf> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        eframe::storage_dir("livechart").map(|dir| dir.join("sessions"))
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        None
+    }
+}
+
+impl LivechartApp {
+    pub fn start_recording(&mut self) {
+        let Some(directory) = sessions_dir() else {
+            self.live.recording_status = Some("Recording is not supported here".to_owned());
+            return;
+        };
+
+        let header = SessionHeader {
+            started: utc::now(),
+            chart: self.active_chart_path().to_owned(),
+            points: self.data.points.clone(),
+        };
+        match Recorder::start(&directory, &header) {
+            Ok(recorder) => {
+                self.live.recording_status = None;
+                self.live.recorder = Some(recorder);
+            }
+            Err(error) => self.live.recording_status = Some(error),
+        }
+    }
+
+    pub fn stop_recording(&mut self) {
+        self.live.recorder = None;
+        self.refresh_sessions();
+    }
+
+    /// Appends a fix to the running recording, starting one first if auto-record is on.
+    ///
+    /// Called before `ownship_received` is updated, so it still holds the previous fix's arrival.
+    pub fn record_fix(&mut self, fix: &Fix) {
+        let reconnected = self
+            .live
+            .ownship_received
+            .map_or(true, |received| utc::now() - received > RECONNECT_GAP_S);
+        if reconnected {
+            self.live.auto_record_paused = false;
+        }
+
+        if self.live.recorder.is_none() && self.data.auto_record && !self.live.auto_record_paused {
+            self.start_recording();
+        }
+
+        if let Some(recorder) = &mut self.live.recorder {
+            if let Err(error) = recorder.record(fix) {
+                self.live.recording_status = Some(error);
+                self.live.recorder = None;
+            }
+        }
+    }
+
+    pub fn refresh_sessions(&mut self) {
+        self.live.sessions = sessions_dir()
+            .map(|directory| Session::list(&directory))
+            .unwrap_or_default();
+    }
+
+    pub fn recording_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.live.recording_window_open;
+
+        egui::Window::new("Recording")
+            .open(&mut open)
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if let Some(recorder) = &self.live.recorder {
+                        ui.colored_label(egui::Color32::RED, "⏺");
+                        ui.label(format!("Recording, {} fixes", recorder.fix_count));
+                        if ui.button("Stop").clicked() {
+                            self.live.auto_record_paused = true;
+                            self.stop_recording();
+                        }
+                    } else if ui.button("⏺ Start recording").clicked() {
+                        self.live.auto_record_paused = false;
+                        self.start_recording();
+                    }
+                });
+                ui.checkbox(&mut self.data.auto_record, "Record automatically")
+                    .on_hover_text("Start a new session as soon as a live fix arrives");
+                if self.data.auto_record && self.live.auto_record_paused {
+                    ui.weak("Paused after Stop until the feed reconnects");
+                }
+
+                if let Some(status) = &self.live.recording_status {
+                    ui.label(status);
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.heading("Sessions");
+                    if ui.button("⟳").on_hover_text("Refresh").clicked() {
+                        self.refresh_sessions();
+                    }
+                });
+
+                if self.live.sessions.is_empty() {
+                    ui.label("No recorded sessions yet.");
+                }
+
+                if let Some(path) = self.live.session_to_delete.clone() {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    ui.horizontal(|ui| {
+                        ui.label(format!("Delete {name}?"));
+                        if ui.button("Delete").clicked() {
+                            if let Err(e) = std::fs::remove_file(&path) {
+                                self.live.recording_status = Some(format!("Could not delete: {e}"));
+                            }
+                            self.live.session_to_delete = None;
+                            self.refresh_sessions();
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.live.session_to_delete = None;
+                        }
+                    });
+                    ui.separator();
+                }
+
+                let mut action = None;
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for (index, session) in self.live.sessions.iter().enumerate() {
+                        ui.label(format!(
+                            "{} · {} · {} fixes · {}",
+                            utc::format_iso8601(session.header.started),
+                            session.header.chart,
+                            session.fixes.len(),
+                            utc::format_clock(session.duration())
+                        ));
+                        ui.horizontal(|ui| {
+                            for candidate in [
+                                SessionAction::Replay,
+                                SessionAction::ExportGpx,
+                                SessionAction::ExportKml,
+                                SessionAction::Delete,
+                            ] {
+                                if ui.small_button(candidate.label()).clicked() {
+                                    action = Some((candidate, index));
+                                }
+                            }
+                        });
+                        ui.separator();
+                    }
+                });
+
+                if let Some((action, index)) = action {
+                    self.apply_session_action(action, index);
+                }
+            });
+        self.live.recording_window_open = open;
+    }
+
+    fn apply_session_action(&mut self, action: SessionAction, index: usize) {
+        let Some(session) = self.live.sessions.get(index) else {
+            return;
+        };
+
+        let export = |extension: &str, contents: String| {
+            let path = session.path.with_extension(extension);
+            match std::fs::write(&path, contents) {
+                Ok(()) => format!("Exported to {}", path.display()),
+                Err(e) => format!("Could not write {}: {e}", path.display()),
+            }
+        };
+
+        match action {
+            SessionAction::Replay => {
+                let name = session
+                    .path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let log = TrackLog::from_fixes(session.fixes.clone());
+                let header = session.header.clone();
+                self.live.replay = Some(Replay::new(name, log));
+                self.live.replay_window_open = true;
+
+                // Show the track on the chart it was recorded over
+                if std::path::Path::new(&header.chart).exists() {
+                    self.show_chart(&header.chart, &header.points);
+                } else {
+                    self.live.recording_status = Some(format!(
+                        "{} is missing, replaying on the current chart",
+                        header.chart
+                    ));
+                }
+            }
+            SessionAction::ExportGpx => {
+                self.live.recording_status = Some(export("gpx", session.to_gpx()));
+            }
+            SessionAction::ExportKml => {
+                self.live.recording_status = Some(export("kml", session.to_kml()));
+            }
+            SessionAction::Delete => {
+                self.live.session_to_delete = Some(session.path.clone());
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SessionAction {
+    Replay,
+    ExportGpx,
+    ExportKml,
+    Delete,
+}
+
+impl SessionAction {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Replay => "Replay",
+            Self::ExportGpx => "GPX",
+            Self::ExportKml => "KML",
+            Self::Delete => "🗑",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::{PixelCoordinate, RealCoordinate};
+
+    // 2024-05-01 14:30:05 UTC
+    const STARTED: f64 = 1_714_573_805.0;
+
+    fn fix(time: f64, altitude_ft: Option<f32>, groundspeed_kt: Option<f32>) -> Fix {
+        Fix {
+            time,
+            position: RealCoordinate {
+                lat: 39.8617,
+                lon: -104.6731,
+            },
+            altitude_ft,
+            altitude_hae: false,
+            groundspeed_kt,
+            track_deg: groundspeed_kt.map(|_| 163.0),
+            source: "GDL 90 <ownship>".to_owned(),
+        }
+    }
+
+    fn header(chart: &str) -> SessionHeader {
+        SessionHeader {
+            started: STARTED,
+            chart: chart.to_owned(),
+            points: vec![CoordinatePair {
+                pixels: PixelCoordinate { x: 120.0, y: 340.0 },
+                real: Some(RealCoordinate {
+                    lat: 39.86,
+                    lon: -104.67,
+                }),
+            }],
+        }
+    }
+
+    /// A fresh directory per test, so tests running in parallel don't see each other's files.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("livechart-recording-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn session(fixes: Vec<Fix>) -> Session {
+        Session {
+            path: PathBuf::from("20240501-143005.jsonl"),
+            header: header("KDEN ILS 16R & <plate>"),
+            fixes,
+        }
+    }
+
+    #[test]
+    fn recorded_fixes_load_back_with_the_header() {
+        let directory = scratch_dir("round-trip");
+        let fixes = vec![
+            fix(STARTED + 1.0, Some(5_434.0), Some(142.5)),
+            fix(STARTED + 2.0, None, None),
+        ];
+
+        let mut recorder = Recorder::start(&directory, &header("kden.png")).unwrap();
+        for fix in &fixes {
+            recorder.record(fix).unwrap();
+        }
+        assert_eq!(recorder.fix_count, 2);
+        assert_eq!(
+            recorder.path.file_name().unwrap().to_str(),
+            Some("20240501-143005.jsonl")
+        );
+        drop(recorder);
+
+        let session = Session::load(&directory.join("20240501-143005.jsonl")).unwrap();
+        assert_eq!(session.header.chart, "kden.png");
+        assert_eq!(session.header.points, header("kden.png").points);
+        assert_eq!(session.fixes, fixes);
+        assert_eq!(session.duration(), 1.0);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn sessions_in_the_same_second_get_a_suffix_and_list_newest_first() {
+        let directory = scratch_dir("suffix");
+        let first = Recorder::start(&directory, &header("a.png")).unwrap();
+        let second = Recorder::start(&directory, &header("b.png")).unwrap();
+        assert_ne!(first.path, second.path);
+        assert!(second.path.ends_with("20240501-143005-2.jsonl"));
+
+        let mut later = header("c.png");
+        later.started += 3_600.0;
+        Recorder::start(&directory, &later).unwrap();
+        // Not a session file, and must not break the listing
+        std::fs::write(directory.join("notes.jsonl"), "not json\n").unwrap();
+
+        let sessions = Session::list(&directory);
+        assert_eq!(sessions.len(), 3);
+        assert_eq!(sessions[0].header.chart, "c.png");
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn a_torn_last_line_is_skipped() {
+        let directory = scratch_dir("torn");
+        let mut recorder = Recorder::start(&directory, &header("kden.png")).unwrap();
+        recorder.record(&fix(STARTED, None, None)).unwrap();
+        let path = recorder.path.clone();
+        drop(recorder);
+        let mut file = std::fs::File::options().append(true).open(&path).unwrap();
+        write!(file, r#"{{"time":1714573806.0,"posi"#).unwrap();
+
+        assert_eq!(Session::load(&path).unwrap().fixes.len(), 1);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn gpx_export_reads_back_as_a_track_log() {
+        let fixes = vec![
+            fix(STARTED, Some(5_434.0), Some(142.5)),
+            fix(STARTED + 1.0, None, None),
+        ];
+        let gpx = session(fixes.clone()).to_gpx();
+        assert!(gpx.contains("<src>GDL 90 &lt;ownship&gt;</src>"));
+        assert!(gpx.contains("KDEN ILS 16R &amp; &lt;plate&gt;"));
+
+        let log = TrackLog::from_gpx(&gpx, "export.gpx").unwrap();
+        assert_eq!(log.fixes.len(), 2);
+        let first = &log.fixes[0];
+        assert_eq!(first.time, STARTED);
+        assert!((first.position.lat - fixes[0].position.lat).abs() < 1e-7);
+        assert!((first.position.lon - fixes[0].position.lon).abs() < 1e-7);
+        assert!((first.altitude_ft.unwrap() - 5_434.0).abs() < 0.5);
+        assert!((first.groundspeed_kt.unwrap() - 142.5).abs() < 0.05);
+        assert_eq!(first.track_deg, Some(163.0));
+        assert_eq!(log.fixes[1].altitude_ft, None);
+        // Only the first point has a velocity to write out
+        assert_eq!(gpx.matches("<gpxtpx:speed>").count(), 1);
+    }
+
+    #[test]
+    fn kml_export_pairs_every_time_with_a_coordinate() {
+        let kml = session(vec![
+            fix(STARTED, Some(1_000.0), None),
+            fix(STARTED + 1.0, None, None),
+        ])
+        .to_kml();
+        assert!(roxmltree::Document::parse(&kml).is_ok());
+        assert_eq!(kml.matches("<when>").count(), 2);
+        assert_eq!(kml.matches("<gx:coord>").count(), 2);
+        assert!(kml.contains("<when>2024-05-01T14:30:05Z</when>"));
+        assert!(kml.contains("<gx:coord>-104.6731000 39.8617000 304.8</gx:coord>"));
+        assert!(kml.contains("<gx:coord>-104.6731000 39.8617000 0.0</gx:coord>"));
+        assert!(kml.contains("<name>KDEN ILS 16R &amp; &lt;plate&gt;</name>"));
+    }
+
+    #[test]
+    fn escapes_xml_special_characters() {
+        assert_eq!(
+            escape_xml(r#"<a href="x">&</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+        );
+    }
+}
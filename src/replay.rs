@@ -0,0 +1,610 @@
+//! Replaying recorded tracks (GPX, IGC, NMEA, GDL 90 captures and our own sessions) as if they
+//! were a live feed.
+
+use crate::app::LivechartApp;
+use crate::gdl90;
+use crate::geo;
+use crate::nmea;
+use crate::ownship;
+use crate::structs::{Fix, RealCoordinate};
+use crate::utc;
+
+pub const SPEEDS: [f32; 7] = [0.5, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0];
+
+/// Fixes further apart than this are not interpolated between.
+const MAX_INTERPOLATION_GAP_S: f64 = 10.0;
+
+/// A recorded track, sorted by time.
+#[derive(Debug, Clone, Default)]
+pub struct TrackLog {
+    pub fixes: Vec<Fix>,
+}
+
+impl TrackLog {
+    /// Loads a log, picking the format from the file extension.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &std::path::Path) -> Result<Self, String> {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let bytes = std::fs::read(path).map_err(|e| format!("Could not read {name}: {e}"))?;
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        let text = || String::from_utf8_lossy(&bytes).into_owned();
+        let log = match extension.as_str() {
+            "gpx" => Self::from_gpx(&text(), &name)?,
+            "igc" => Self::from_igc(&text(), &name),
+            "gdl90" | "bin" | "cap" => Self::from_gdl90(&bytes, &name),
+            "jsonl" => Self::from_fixes(crate::recording::Session::load(path)?.fixes),
+            _ => Self::from_nmea(&text(), &name),
+        };
+
+        if log.fixes.is_empty() {
+            return Err(format!("No position fixes found in {name}"));
+        }
+        Ok(log)
+    }
+
+    pub fn from_gpx(text: &str, source: &str) -> Result<Self, String> {
+        let document = roxmltree::Document::parse(text).map_err(|e| format!("Invalid GPX: {e}"))?;
+
+        let fixes = document
+            .descendants()
+            .filter(|node| node.tag_name().name() == "trkpt")
+            .filter_map(|point| {
+                let child_text = |name: &str| {
+                    point
+                        .children()
+                        .find(|child| child.tag_name().name() == name)
+                        .and_then(|child| child.text())
+                };
+                // Garmin's TrackPointExtension, as written by our own GPX export
+                let extension = |name: &str| {
+                    point
+                        .descendants()
+                        .find(|child| child.tag_name().name() == name)
+                        .and_then(|child| child.text())
+                        .and_then(|text| text.trim().parse::<f64>().ok())
+                };
+
+                Some(Fix {
+                    time: utc::parse_iso8601(child_text("time")?)?,
+                    position: RealCoordinate {
+                        lat: point.attribute("lat")?.parse().ok()?,
+                        lon: point.attribute("lon")?.parse().ok()?,
+                    },
+                    altitude_ft: child_text("ele")
+                        .and_then(|ele| ele.trim().parse::<f64>().ok())
+                        .map(|m| (m / geo::METRES_PER_FT) as f32),
+                    altitude_hae: false,
+                    groundspeed_kt: extension("speed").map(|mps| (mps / geo::MPS_PER_KT) as f32),
+                    track_deg: extension("course").map(|course| course as f32),
+                    source: source.to_owned(),
+                })
+            })
+            .collect();
+
+        Ok(Self::from_fixes(fixes))
+    }
+
+    /// IGC flight recorder files: the `HFDTE` header for the date and `B` records for fixes.
+    pub fn from_igc(text: &str, source: &str) -> Self {
+        let mut date = 0;
+        let mut fixes = Vec::new();
+
+        for line in text.lines() {
+            let line = line.trim_end();
+            if let Some(header) = line.strip_prefix("HFDTE") {
+                // Either "HFDTEDDMMYY" or "HFDTEDATE:DDMMYY,NN"
+                let digits = header.strip_prefix("DATE:").unwrap_or(header);
+                let parse = |range: std::ops::Range<usize>| -> Option<u32> {
+                    digits.get(range)?.parse().ok()
+                };
+                if let (Some(day), Some(month), Some(year)) =
+                    (parse(0..2), parse(2..4), parse(4..6))
+                {
+                    date = utc::days_from_civil(2000 + year as i32, month, day);
+                }
+            } else if line.starts_with('B') && line.len() >= 35 {
+                if let Some(fix) = parse_igc_fix(line, date, source) {
+                    fixes.push(fix);
+                }
+            }
+        }
+
+        Self::from_fixes(fixes)
+    }
+
+    pub fn from_nmea(text: &str, source: &str) -> Self {
+        let mut decoder = nmea::Decoder::new(source);
+        Self::from_fixes(
+            text.lines()
+                .filter_map(|line| decoder.push_line(line))
+                .collect(),
+        )
+    }
+
+    /// Raw GDL 90 capture. Fixes are timestamped with the most recent heartbeat.
+    pub fn from_gdl90(bytes: &[u8], source: &str) -> Self {
+        let mut deframer = gdl90::Deframer::default();
+        let mut seconds_of_day = None;
+        let mut geometric_altitude = None;
+        // Height above the ellipsoid unless the device says otherwise
+        let mut geometric_msl = false;
+        let mut fixes = Vec::new();
+
+        for message in deframer.push(bytes) {
+            match gdl90::decode(&message) {
+                Some(gdl90::Message::Heartbeat(heartbeat)) => {
+                    seconds_of_day = Some(f64::from(heartbeat.seconds_of_day));
+                }
+                Some(gdl90::Message::DeviceId { geometric_msl: msl }) => {
+                    geometric_msl = msl;
+                }
+                Some(gdl90::Message::OwnshipGeometricAltitude(altitude)) => {
+                    geometric_altitude = Some(altitude as f32);
+                }
+                Some(gdl90::Message::Ownship(report)) => {
+                    let (Some(time), Some(position)) = (seconds_of_day, report.position) else {
+                        continue;
+                    };
+                    fixes.push(Fix {
+                        time,
+                        position,
+                        altitude_ft: geometric_altitude.or(report.altitude_ft.map(|a| a as f32)),
+                        altitude_hae: geometric_altitude.is_some() && !geometric_msl,
+                        groundspeed_kt: report.groundspeed_kt.map(f32::from),
+                        track_deg: report.track_deg,
+                        source: source.to_owned(),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        Self::from_fixes(fixes)
+    }
+
+    /// Sorts and cleans up fixes and derives missing velocities from neighbouring fixes.
+    pub fn from_fixes(mut fixes: Vec<Fix>) -> Self {
+        // Logs without a date (GGA-only NMEA, GDL 90) wrap around at midnight
+        let mut day_offset = 0.0;
+        for i in 1..fixes.len() {
+            fixes[i].time += day_offset;
+            if fixes[i].time < fixes[i - 1].time - 43_200.0 {
+                day_offset += 86_400.0;
+                fixes[i].time += 86_400.0;
+            }
+        }
+
+        fixes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        // Several reports for one timestamp: keep the latest
+        fixes.dedup_by(|later, earlier| {
+            if later.time == earlier.time {
+                std::mem::swap(later, earlier);
+                true
+            } else {
+                false
+            }
+        });
+
+        for i in 1..fixes.len() {
+            let (previous, current) = (&fixes[i - 1], &fixes[i]);
+            let dt = current.time - previous.time;
+            let distance = geo::distance_m(&previous.position, &current.position);
+            let groundspeed = (distance / dt / geo::MPS_PER_KT) as f32;
+            let track = geo::bearing_deg(&previous.position, &current.position) as f32;
+
+            let current = &mut fixes[i];
+            current.groundspeed_kt.get_or_insert(groundspeed);
+            if distance > 1.0 {
+                current.track_deg.get_or_insert(track);
+            }
+        }
+
+        Self { fixes }
+    }
+
+    pub fn start(&self) -> f64 {
+        self.fixes.first().map_or(0.0, |fix| fix.time)
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.fixes.last().map_or(0.0, |fix| fix.time) - self.start()
+    }
+
+    /// Index of the last fix at or before `time`.
+    pub fn index_at(&self, time: f64) -> Option<usize> {
+        self.fixes
+            .partition_point(|fix| fix.time <= time)
+            .checked_sub(1)
+    }
+    /// The position at `time`, between the recorded fixes around it.
+    pub fn interpolated(&self, time: f64) -> Option<Fix> {
+        let index = self.index_at(time)?;
+        let from = self.fixes.get(index)?;
+        match self.fixes.get(index + 1) {
+            // Don't make up a straight line across gaps in the log
+            Some(to) if to.time - from.time <= MAX_INTERPOLATION_GAP_S => {
+                Some(ownship::interpolate(from, to, time))
+            }
+            _ => Some(from.clone()),
+        }
+    }
+}
+
+fn parse_igc_fix(line: &str, date: i64, source: &str) -> Option<Fix> {
+    let number = |range: std::ops::Range<usize>| -> Option<f64> { line.get(range)?.parse().ok() };
+
+    let seconds_of_day = number(1..3)? * 3600.0 + number(3..5)? * 60.0 + number(5..7)?;
+    let mut lat = number(7..9)? + number(9..14)? / 60_000.0;
+    if line.get(14..15)? == "S" {
+        lat = -lat;
+    }
+    let mut lon = number(15..18)? + number(18..23)? / 60_000.0;
+    if line.get(23..24)? == "W" {
+        lon = -lon;
+    }
+
+    // Prefer GNSS altitude, fall back to pressure altitude if the logger has no GNSS altitude
+    let pressure_m = number(25..30);
+    let gnss_m = number(30..35).filter(|&m| m != 0.0);
+    let altitude_m = gnss_m.or(pressure_m);
+
+    Some(Fix {
+        time: date as f64 * 86_400.0 + seconds_of_day,
+        position: RealCoordinate { lat, lon },
+        altitude_ft: altitude_m.map(|m| (m / geo::METRES_PER_FT) as f32),
+        altitude_hae: false,
+        groundspeed_kt: None,
+        track_deg: None,
+        source: source.to_owned(),
+    })
+}
+
+/// A loaded track log being played back.
+pub struct Replay {
+    pub name: String,
+    pub log: TrackLog,
+    /// Seconds since the start of the log.
+    pub cursor: f64,
+    pub playing: bool,
+    pub speed: f32,
+    /// Index of the fix that was handed out last, so every fix is only emitted once.
+    last_emitted: Option<usize>,
+}
+
+impl Replay {
+    pub fn new(name: String, log: TrackLog) -> Self {
+        Self {
+            name,
+            log,
+            cursor: 0.0,
+            playing: false,
+            speed: 1.0,
+            last_emitted: None,
+        }
+    }
+
+    /// Moves the cursor forward by `dt` seconds of wall-clock time.
+    pub fn advance(&mut self, dt: f64) {
+        if !self.playing {
+            return;
+        }
+
+        self.cursor += dt * f64::from(self.speed);
+        if self.cursor >= self.log.duration() {
+            self.cursor = self.log.duration();
+            self.playing = false;
+        }
+    }
+
+    /// Jumps to a point on the timeline. The fix at that point is emitted again on the next poll.
+    pub fn seek(&mut self, cursor: f64) {
+        self.cursor = cursor.clamp(0.0, self.log.duration());
+        self.last_emitted = None;
+    }
+
+    /// Returns the recorded fix that is current at the cursor, if it hasn't been emitted yet.
+    pub fn poll(&mut self) -> Option<Fix> {
+        let index = self.log.index_at(self.log.start() + self.cursor)?;
+        if self.last_emitted == Some(index) {
+            return None;
+        }
+        self.last_emitted = Some(index);
+        self.log.fixes.get(index).cloned()
+    }
+
+    /// The position at the cursor, between the recorded fixes around it.
+    pub fn interpolated(&self) -> Option<Fix> {
+        self.log.interpolated(self.log.start() + self.cursor)
+    }
+}
+
+impl LivechartApp {
+    /// Advances the replay, if any, and feeds its fixes in like a live source.
+    pub fn tick_replay(&mut self, ctx: &egui::Context) {
+        let Some(replay) = &mut self.live.replay else {
+            return;
+        };
+
+        replay.advance(f64::from(ctx.input(|i| i.stable_dt)));
+        if let Some(fix) = replay.poll() {
+            self.ingest_fix(fix);
+        }
+
+        if self
+            .live
+            .replay
+            .as_ref()
+            .is_some_and(|replay| replay.playing)
+        {
+            ctx.request_repaint();
+        }
+    }
+
+    pub fn replay_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.live.replay_window_open;
+        egui::Window::new("Replay")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Track log:");
+                    ui.text_edit_singleline(&mut self.data.replay_path)
+                        .on_hover_text("GPX, IGC, NMEA text or raw GDL 90 capture");
+                    if ui.button("Load").clicked() {
+                        self.load_replay();
+                    }
+                });
+
+                if let Some(error) = &self.live.replay_error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+
+                let Some(replay) = &mut self.live.replay else {
+                    return;
+                };
+
+                ui.separator();
+                ui.label(format!(
+                    "{}: {} fixes, {}",
+                    replay.name,
+                    replay.log.fixes.len(),
+                    utc::format_clock(replay.log.duration())
+                ));
+
+                ui.horizontal(|ui| {
+                    if ui.button(if replay.playing { "⏸" } else { "▶" }).clicked() {
+                        if !replay.playing && replay.cursor >= replay.log.duration() {
+                            replay.seek(0.0);
+                        }
+                        replay.playing = !replay.playing;
+                    }
+
+                    egui::ComboBox::from_id_salt("replay_speed")
+                        .selected_text(format!("{}×", replay.speed))
+                        .show_ui(ui, |ui| {
+                            for speed in SPEEDS {
+                                ui.selectable_value(&mut replay.speed, speed, format!("{speed}×"));
+                            }
+                        });
+
+                    ui.label(utc::format_clock(replay.log.start() + replay.cursor));
+                });
+
+                let mut cursor = replay.cursor;
+                let timeline = ui.add(
+                    egui::Slider::new(&mut cursor, 0.0..=replay.log.duration())
+                        .show_value(false)
+                        .text("Timeline"),
+                );
+                if timeline.changed() {
+                    replay.seek(cursor);
+                }
+
+                if ui.button("Unload").clicked() {
+                    self.live.replay = None;
+                    self.live.ownship = None;
+                    self.live.trail.clear();
+                }
+            });
+        self.live.replay_window_open = open;
+    }
+
+    fn load_replay(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let path = std::path::Path::new(self.data.replay_path.trim());
+            match TrackLog::load(path) {
+                Ok(log) => {
+                    let name = path
+                        .file_name()
+                        .map(|n| n.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    self.live.replay = Some(Replay::new(name, log));
+                    self.live.replay_error = None;
+                }
+                Err(error) => self.live.replay_error = Some(error),
+            }
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.live.replay_error = Some("Loading files is not supported on the web".to_owned());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fix(time: f64, lat: f64, lon: f64) -> Fix {
+        Fix {
+            time,
+            position: RealCoordinate { lat, lon },
+            altitude_ft: None,
+            altitude_hae: false,
+            groundspeed_kt: None,
+            track_deg: None,
+            source: "test".to_owned(),
+        }
+    }
+
+    #[test]
+    fn reads_igc_b_records_on_the_header_date() {
+        let log = TrackLog::from_igc(
+            "AXXXABC Flight recorder\r\n\
+             HFDTE160701\r\n\
+             B1101355206343N00006198WA0058700558\r\n\
+             B1101455206952N00006081WA0058800560\r\n\
+             B1101555207000S00006000EA0059000000\r\n\
+             LXXX comment\r\n",
+            "flight.igc",
+        );
+        assert_eq!(log.fixes.len(), 3);
+
+        let first = &log.fixes[0];
+        assert_eq!(
+            first.time,
+            utc::from_civil(2001, 7, 16, 11.0 * 3600.0 + 60.0 + 35.0)
+        );
+        assert!((first.position.lat - (52.0 + 6.343 / 60.0)).abs() < 1e-9);
+        assert!((first.position.lon + 6.198 / 60.0).abs() < 1e-9);
+        // GNSS altitude rather than pressure altitude
+        assert!((first.altitude_ft.unwrap() - (558.0 / geo::METRES_PER_FT) as f32).abs() < 0.1);
+        assert_eq!(first.source, "flight.igc");
+
+        // Without a GNSS altitude the pressure altitude is used
+        let last = &log.fixes[2];
+        assert!(last.position.lat < 0.0 && last.position.lon > 0.0);
+        assert!((last.altitude_ft.unwrap() - (590.0 / geo::METRES_PER_FT) as f32).abs() < 0.1);
+        assert!(log.fixes[1].groundspeed_kt.is_some());
+    }
+
+    #[test]
+    fn reads_gpx_track_points_with_speed_and_course() {
+        let log = TrackLog::from_gpx(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1"
+     xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v2">
+  <trk><name>Test</name><trkseg>
+    <trkpt lat="47.458056" lon="8.548056">
+      <ele>432.0</ele>
+      <time>2024-05-01T10:00:00Z</time>
+      <extensions><gpxtpx:TrackPointExtension>
+        <gpxtpx:speed>51.4</gpxtpx:speed><gpxtpx:course>137.0</gpxtpx:course>
+      </gpxtpx:TrackPointExtension></extensions>
+    </trkpt>
+    <trkpt lat="47.46" lon="8.55"><time>2024-05-01T10:00:05Z</time></trkpt>
+    <trkpt lat="47.47" lon="8.56"></trkpt>
+  </trkseg></trk>
+</gpx>"#,
+            "track.gpx",
+        )
+        .unwrap();
+
+        // The point without a time is skipped
+        assert_eq!(log.fixes.len(), 2);
+        let first = &log.fixes[0];
+        assert_eq!(first.time, utc::from_civil(2024, 5, 1, 36_000.0));
+        assert!((first.altitude_ft.unwrap() - 1417.3).abs() < 0.1);
+        assert!((first.groundspeed_kt.unwrap() - 99.9).abs() < 0.1);
+        assert_eq!(first.track_deg, Some(137.0));
+        assert_eq!(log.duration(), 5.0);
+        assert!(TrackLog::from_gpx("<gpx>", "broken.gpx").is_err());
+    }
+
+    #[test]
+    fn wraps_time_of_day_logs_over_midnight() {
+        let log = TrackLog::from_fixes(vec![
+            fix(86_390.0, 47.0, 8.0),
+            fix(86_399.0, 47.001, 8.0),
+            fix(4.0, 47.002, 8.0),
+            fix(9.0, 47.003, 8.0),
+        ]);
+        let times: Vec<f64> = log.fixes.iter().map(|fix| fix.time).collect();
+        assert_eq!(times, [86_390.0, 86_399.0, 86_404.0, 86_409.0]);
+        // Speed and track from the neighbours, due north
+        assert!(log.fixes[2].track_deg.unwrap().abs() < 0.1);
+        assert!(log.fixes[2].groundspeed_kt.unwrap() > 0.0);
+    }
+
+    #[test]
+    fn keeps_the_latest_of_fixes_with_the_same_time() {
+        let log = TrackLog::from_fixes(vec![
+            fix(10.0, 47.0, 8.0),
+            fix(11.0, 47.1, 8.0),
+            fix(11.0, 47.2, 8.0),
+        ]);
+        assert_eq!(log.fixes.len(), 2);
+        assert_eq!(log.fixes[1].position.lat, 47.2);
+    }
+
+    #[test]
+    fn checks_the_gdl90_crc() {
+        // The heartbeat example of the GDL 90 ICD
+        let heartbeat = [0x00, 0x81, 0x41, 0xDB, 0xD0, 0x08, 0x02];
+        assert_eq!(gdl90::crc(&heartbeat), 0x8BB3);
+
+        let mut deframer = gdl90::Deframer::default();
+        let bytes = [
+            0x7E, 0x00, 0x81, 0x41, 0xDB, 0xD0, 0x08, 0x02, 0xB3, 0x8B, 0x7E,
+        ];
+        assert_eq!(deframer.push(&bytes), [heartbeat.to_vec()]);
+
+        let mut corrupted = bytes;
+        corrupted[3] ^= 0x01;
+        assert!(deframer.push(&corrupted).is_empty());
+        assert_eq!(deframer.errors, 1);
+    }
+
+    #[test]
+    fn deframes_escaped_bytes_split_across_reads() {
+        let message = [0x14, 0x7E, 0x7D, 0x00, 0x7E];
+        let framed = gdl90::frame(&message);
+        assert!(framed.len() > message.len() + 4);
+
+        let mut deframer = gdl90::Deframer::default();
+        let (first, second) = framed.split_at(3);
+        assert!(deframer.push(first).is_empty());
+        assert_eq!(deframer.push(second), [message.to_vec()]);
+        assert_eq!(deframer.errors, 0);
+    }
+
+    #[test]
+    fn reads_gdl90_captures_on_the_heartbeat_time() {
+        let position = fix(0.0, 47.5, 8.5);
+        let report = gdl90::Report {
+            altitude_ft: Some(3000),
+            ..gdl90::Report::ownship(&position, None, "TEST")
+        };
+        let capture: Vec<u8> = [
+            // Before the first heartbeat there is no time for the fix
+            gdl90::encode_report(10, &report),
+            gdl90::encode_heartbeat(&gdl90::Heartbeat {
+                gps_valid: true,
+                seconds_of_day: 45_000,
+            }),
+            gdl90::encode_geometric_altitude(3200),
+            gdl90::encode_report(10, &report),
+        ]
+        .iter()
+        .flat_map(|message| gdl90::frame(message))
+        .collect();
+
+        let log = TrackLog::from_gdl90(&capture, "capture.gdl90");
+        assert_eq!(log.fixes.len(), 1);
+        let fix = &log.fixes[0];
+        assert_eq!(fix.time, 45_000.0);
+        assert!((fix.position.lat - 47.5).abs() < 1e-4);
+        assert!((fix.position.lon - 8.5).abs() < 1e-4);
+        // The geometric altitude wins over the pressure altitude
+        assert_eq!(fix.altitude_ft, Some(3200.0));
+    }
+}
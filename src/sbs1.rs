@@ -0,0 +1,106 @@
+//! SBS-1 BaseStation text format, as served by dump1090 and friends on TCP port 30003.
+//!
+//! `MSG,type,session,aircraft,hex,flight,date,time,date,time,callsign,altitude,speed,track,lat,
+//! lon,vertical rate,squawk,alert,emergency,spi,on ground`
+
+use crate::structs::RealCoordinate;
+use crate::traffic::TrafficUpdate;
+
+pub const DEFAULT_PORT: u16 = 30003;
+
+/// Parses one line. Returns `None` for anything that isn't a `MSG` line with an ICAO address.
+pub fn parse(line: &str, source: &str) -> Option<TrafficUpdate> {
+    let fields: Vec<&str> = line.trim().split(',').collect();
+    if fields.first() != Some(&"MSG") || fields.len() < 11 {
+        return None;
+    }
+
+    let field = |i: usize| fields.get(i).map(|f| f.trim()).filter(|f| !f.is_empty());
+    let id = u32::from_str_radix(field(4)?, 16).ok()?;
+
+    let position = match (
+        field(14).and_then(|f| f.parse().ok()),
+        field(15).and_then(|f| f.parse().ok()),
+    ) {
+        (Some(lat), Some(lon)) => Some(RealCoordinate { lat, lon }),
+        _ => None,
+    };
+
+    Some(TrafficUpdate {
+        id,
+        callsign: field(10).map(str::to_owned),
+        position,
+        position_age_s: 0.0,
+        altitude_ft: field(11).and_then(|f| f.parse().ok()),
+        altitude_geometric: false,
+        groundspeed_kt: field(12).and_then(|f| f.parse().ok()),
+        track_deg: field(13).and_then(|f| f.parse().ok()),
+        vertical_rate_fpm: field(16).and_then(|f| f.parse().ok()),
+        // Flags are "-1" for set and "0" for clear
+        on_ground: field(21).map(|f| f == "-1" || f == "1"),
+        alert: None,
+        source: source.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_identification_position_and_velocity_messages() {
+        let identification = parse(
+            "MSG,1,111,11111,A1B2C3,111111,2024/05/01,14:30:05.123,2024/05/01,14:30:05.123,UAL1234,,,,,,,,,,,",
+            "sbs",
+        )
+        .unwrap();
+        assert_eq!(identification.id, 0xA1B2C3);
+        assert_eq!(identification.callsign.as_deref(), Some("UAL1234"));
+        assert_eq!(identification.position, None);
+        assert_eq!(identification.altitude_ft, None);
+
+        let position = parse(
+            "MSG,3,111,11111,A1B2C3,111111,2024/05/01,14:30:05.456,2024/05/01,14:30:05.456,,12025,,,39.85120,-104.66230,,,0,0,0,0",
+            "sbs",
+        )
+        .unwrap();
+        assert_eq!(position.callsign, None);
+        assert_eq!(position.altitude_ft, Some(12025));
+        let coordinate = position.position.unwrap();
+        assert_eq!((coordinate.lat, coordinate.lon), (39.8512, -104.6623));
+        assert_eq!(position.on_ground, Some(false));
+
+        let velocity = parse(
+            "MSG,4,111,11111,A1B2C3,111111,2024/05/01,14:30:05.789,2024/05/01,14:30:05.789,,,312,163,,,-1216,,,,,",
+            "sbs",
+        )
+        .unwrap();
+        assert_eq!(velocity.groundspeed_kt, Some(312.0));
+        assert_eq!(velocity.track_deg, Some(163.0));
+        assert_eq!(velocity.vertical_rate_fpm, Some(-1216));
+        assert_eq!(velocity.on_ground, None);
+    }
+
+    #[test]
+    fn reads_the_on_ground_flag() {
+        let update = parse(
+            "MSG,2,111,11111,4CA2B1,111111,2024/05/01,14:30:05.000,2024/05/01,14:30:05.000,,,14,273,39.86,-104.67,,,,,,-1",
+            "sbs",
+        )
+        .unwrap();
+        assert_eq!(update.on_ground, Some(true));
+    }
+
+    #[test]
+    fn ignores_other_lines() {
+        assert_eq!(parse("AIR,,111,1,A1B2C3,101", "sbs"), None);
+        assert_eq!(parse("MSG,8,111,11111,A1B2C3", "sbs"), None);
+        assert_eq!(
+            parse(
+                "MSG,8,111,11111,NOTHEX,111111,2024/05/01,14:30:05,2024/05/01,14:30:05,,",
+                "sbs"
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,455 @@
+//! A synthetic ownship that flies a scripted path, for demos and for exercising the live features
+//! without a receiver.
+//!
+//! The path is a list of waypoints flown at constant groundspeed, optionally with position noise
+//! and dropped fixes. Everything is deterministic for a given seed, so tests can replay the exact
+//! same flight.
+
+use crate::geo;
+use crate::structs::{Fix, RealCoordinate};
+
+/// Glide path of generated final approaches.
+const GLIDE_PATH_DEG: f64 = 3.0;
+/// Height over the threshold at the end of a generated final approach.
+const THRESHOLD_CROSSING_FT: f64 = 50.0;
+/// Generated final approaches start this far before the first point.
+const APPROACH_LEAD_IN_M: f64 = 3.0 * geo::METRES_PER_NM;
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SimulatorConfig {
+    /// One `lat, lon, altitude in feet` per line, `#` starts a comment. The altitude is optional.
+    pub waypoints: String,
+    pub speed_kt: f32,
+    /// Standard deviation of the horizontal position error in metres.
+    pub noise_m: f32,
+    /// Share of fixes that are dropped, in percent.
+    pub dropout_percent: f32,
+    /// Start over at the first waypoint after the last one.
+    pub repeat: bool,
+}
+
+impl Default for SimulatorConfig {
+    fn default() -> Self {
+        Self {
+            waypoints: String::new(),
+            speed_kt: 90.0,
+            noise_m: 0.0,
+            dropout_percent: 0.0,
+            repeat: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waypoint {
+    pub lat: f64,
+    pub lon: f64,
+    pub altitude_ft: Option<f32>,
+}
+
+impl Waypoint {
+    fn position(&self) -> RealCoordinate {
+        RealCoordinate {
+            lat: self.lat,
+            lon: self.lon,
+        }
+    }
+}
+
+pub fn parse_waypoints(text: &str) -> Result<Vec<Waypoint>, String> {
+    let mut waypoints = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|field| !field.is_empty())
+            .collect();
+        let value = |i: usize| fields.get(i).and_then(|field| field.parse::<f64>().ok());
+        let (Some(lat), Some(lon)) = (value(0), value(1)) else {
+            return Err(format!(
+                "Line {}: expected latitude and longitude",
+                number + 1
+            ));
+        };
+        if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+            return Err(format!("Line {}: position out of range", number + 1));
+        }
+
+        waypoints.push(Waypoint {
+            lat,
+            lon,
+            altitude_ft: value(2).map(|altitude| altitude as f32),
+        });
+    }
+    Ok(waypoints)
+}
+
+pub fn format_waypoints(waypoints: &[Waypoint]) -> String {
+    waypoints
+        .iter()
+        .map(|waypoint| match waypoint.altitude_ft {
+            Some(altitude) => format!("{:.6}, {:.6}, {altitude:.0}\n", waypoint.lat, waypoint.lon),
+            None => format!("{:.6}, {:.6}\n", waypoint.lat, waypoint.lon),
+        })
+        .collect()
+}
+
+/// A straight-in final approach through `from` (e.g. the FAF) to `threshold`, on a 3° glide
+/// path that crosses the threshold at 50 ft.
+pub fn final_approach(
+    from: &RealCoordinate,
+    threshold: &RealCoordinate,
+    threshold_elevation_ft: f32,
+) -> Vec<Waypoint> {
+    let course = geo::bearing_deg(from, threshold);
+    let start = geo::destination(from, course + 180.0, APPROACH_LEAD_IN_M);
+    let height_at = |distance_m: f64| {
+        let height_m = distance_m * GLIDE_PATH_DEG.to_radians().tan();
+        (f64::from(threshold_elevation_ft) + THRESHOLD_CROSSING_FT + height_m / geo::METRES_PER_FT)
+            as f32
+    };
+
+    [start, from.clone(), threshold.clone()]
+        .into_iter()
+        .map(|position| Waypoint {
+            altitude_ft: Some(height_at(geo::distance_m(&position, threshold))),
+            lat: position.lat,
+            lon: position.lon,
+        })
+        .collect()
+}
+
+/// What the simulated receiver reports at one moment.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sample {
+    Fix(Fix),
+    /// A fix that was due but dropped.
+    Dropout,
+    /// The last waypoint was reached and the path doesn't repeat.
+    Finished,
+}
+
+pub struct Simulator {
+    waypoints: Vec<Waypoint>,
+    /// Distance along the path at each waypoint.
+    distances: Vec<f64>,
+    speed_mps: f64,
+    noise_m: f64,
+    dropout: f64,
+    repeat: bool,
+    source: String,
+    rng: u64,
+}
+
+impl Simulator {
+    pub fn new(config: &SimulatorConfig, source: &str, seed: u64) -> Result<Self, String> {
+        let waypoints = parse_waypoints(&config.waypoints)?;
+        if waypoints.len() < 2 {
+            return Err("The simulator needs at least two waypoints".to_owned());
+        }
+        if config.speed_kt <= 0.0 {
+            return Err("The simulator needs a positive speed".to_owned());
+        }
+
+        let mut distances = vec![0.0];
+        for pair in waypoints.windows(2) {
+            let leg = geo::distance_m(&pair[0].position(), &pair[1].position());
+            distances.push(distances.last().copied().unwrap_or(0.0) + leg);
+        }
+
+        Ok(Self {
+            waypoints,
+            distances,
+            speed_mps: f64::from(config.speed_kt) * geo::MPS_PER_KT,
+            noise_m: f64::from(config.noise_m.max(0.0)),
+            dropout: f64::from(config.dropout_percent.clamp(0.0, 100.0)) / 100.0,
+            repeat: config.repeat,
+            source: source.to_owned(),
+            // xorshift gets stuck at zero
+            rng: seed ^ 0x9E37_79B9_7F4A_7C15,
+        })
+    }
+
+    /// Length of the path in metres.
+    pub fn length_m(&self) -> f64 {
+        self.distances.last().copied().unwrap_or(0.0)
+    }
+
+    /// The exact position `elapsed_s` after the start, without noise. `None` once a path that
+    /// doesn't repeat is finished.
+    pub fn fix_at(&self, elapsed_s: f64, time: f64) -> Option<Fix> {
+        let mut distance = self.speed_mps * elapsed_s.max(0.0);
+        let length = self.length_m();
+        if self.repeat && length > 0.0 {
+            distance %= length;
+        } else if distance > length {
+            return None;
+        }
+
+        // The leg the distance falls on, skipping legs of zero length
+        let leg = self
+            .distances
+            .windows(2)
+            .position(|pair| distance <= pair[1] && pair[1] > pair[0])
+            .unwrap_or(self.waypoints.len() - 2);
+        let (from, to) = (&self.waypoints[leg], &self.waypoints[leg + 1]);
+        let along = distance - self.distances[leg];
+        let leg_length = self.distances[leg + 1] - self.distances[leg];
+        let fraction = if leg_length > 0.0 {
+            along / leg_length
+        } else {
+            0.0
+        };
+
+        let track = geo::bearing_deg(&from.position(), &to.position());
+        Some(Fix {
+            time,
+            position: geo::destination(&from.position(), track, along),
+            altitude_ft: match (from.altitude_ft, to.altitude_ft) {
+                (Some(a), Some(b)) => Some(a + (b - a) * fraction as f32),
+                (a, b) => a.or(b),
+            },
+            altitude_hae: false,
+            groundspeed_kt: Some((self.speed_mps / geo::MPS_PER_KT) as f32),
+            track_deg: Some(track as f32),
+            source: self.source.clone(),
+        })
+    }
+
+    /// The fix a receiver would report at `time`, `elapsed_s` after the start, with noise and
+    /// dropouts applied.
+    pub fn sample(&mut self, elapsed_s: f64, time: f64) -> Sample {
+        let Some(mut fix) = self.fix_at(elapsed_s, time) else {
+            return Sample::Finished;
+        };
+        if self.uniform() < self.dropout {
+            return Sample::Dropout;
+        }
+
+        if self.noise_m > 0.0 {
+            let east = self.gaussian() * self.noise_m;
+            let north = self.gaussian() * self.noise_m;
+            fix.position = geo::unproject(&fix.position, east, north);
+            // GNSS altitude is usually about one and a half times worse than the position
+            let vertical_ft = self.gaussian() * self.noise_m * 1.5 / geo::METRES_PER_FT;
+            fix.altitude_ft = fix
+                .altitude_ft
+                .map(|altitude| altitude + vertical_ft as f32);
+        }
+        Sample::Fix(fix)
+    }
+
+    /// Uniform in `[0, 1)`, from xorshift64*.
+    fn uniform(&mut self) -> f64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        let value = self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        (value >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal, Box-Muller.
+    fn gaussian(&mut self) -> f64 {
+        let u = 1.0 - self.uniform();
+        let v = self.uniform();
+        (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two legs east along the equator, climbing on the first and level on the second.
+    fn config(repeat: bool) -> SimulatorConfig {
+        SimulatorConfig {
+            waypoints: "0, 0, 1000\n0, 0.1, 2000\n0, 0.2, 2000\n".to_owned(),
+            speed_kt: 120.0,
+            repeat,
+            ..Default::default()
+        }
+    }
+
+    fn seconds_to(simulator: &Simulator, distance_m: f64) -> f64 {
+        distance_m / simulator.speed_mps
+    }
+
+    #[test]
+    fn fix_at_interpolates_along_the_legs() {
+        let simulator = Simulator::new(&config(false), "sim", 1).unwrap();
+        let leg = simulator.distances[1];
+
+        let fix = simulator
+            .fix_at(seconds_to(&simulator, leg / 2.0), 100.0)
+            .unwrap();
+        assert!((fix.position.lon - 0.05).abs() < 1e-6);
+        assert!(fix.position.lat.abs() < 1e-6);
+        assert!((fix.altitude_ft.unwrap() - 1500.0).abs() < 0.5);
+        assert!((fix.track_deg.unwrap() - 90.0).abs() < 0.01);
+        assert!((fix.groundspeed_kt.unwrap() - 120.0).abs() < 0.01);
+        assert_eq!(fix.time, 100.0);
+        assert_eq!(fix.source, "sim");
+
+        let fix = simulator
+            .fix_at(seconds_to(&simulator, leg * 1.5), 0.0)
+            .unwrap();
+        assert!((fix.position.lon - 0.15).abs() < 1e-6);
+        assert_eq!(fix.altitude_ft, Some(2000.0));
+    }
+
+    #[test]
+    fn fix_at_finishes_or_repeats() {
+        let once = Simulator::new(&config(false), "sim", 1).unwrap();
+        let end = seconds_to(&once, once.length_m());
+        let last = once.fix_at(end, 0.0).unwrap();
+        assert!((last.position.lon - 0.2).abs() < 1e-6);
+        assert_eq!(once.fix_at(end + 1.0, 0.0), None);
+
+        let mut repeating = Simulator::new(&config(true), "sim", 1).unwrap();
+        let quarter = seconds_to(&repeating, repeating.length_m() / 4.0);
+        let again = repeating.fix_at(end + quarter, 0.0).unwrap();
+        assert!((again.position.lon - 0.05).abs() < 1e-6);
+
+        let mut once = once;
+        assert_eq!(once.sample(end + 1.0, 0.0), Sample::Finished);
+        assert!(matches!(repeating.sample(end + 1.0, 0.0), Sample::Fix(_)));
+    }
+
+    #[test]
+    fn samples_are_deterministic_for_a_seed() {
+        let noisy = SimulatorConfig {
+            noise_m: 20.0,
+            dropout_percent: 30.0,
+            ..config(true)
+        };
+        let run = |seed| {
+            let mut simulator = Simulator::new(&noisy, "sim", seed).unwrap();
+            (0..50)
+                .map(|second| simulator.sample(f64::from(second), f64::from(second)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+
+    #[test]
+    fn noise_and_dropouts_match_the_config() {
+        let noisy = SimulatorConfig {
+            noise_m: 10.0,
+            dropout_percent: 25.0,
+            ..config(true)
+        };
+        let mut simulator = Simulator::new(&noisy, "sim", 42).unwrap();
+        let count = 4000;
+        let mut dropouts = 0;
+        let mut squared_error = 0.0;
+        let mut fixes = 0;
+        for second in 0..count {
+            let elapsed = f64::from(second);
+            let exact = simulator.fix_at(elapsed, elapsed).unwrap();
+            match simulator.sample(elapsed, elapsed) {
+                Sample::Fix(fix) => {
+                    let (east, north) = geo::project(&exact.position, &fix.position);
+                    squared_error += east * east + north * north;
+                    fixes += 1;
+                }
+                Sample::Dropout => dropouts += 1,
+                Sample::Finished => unreachable!("the path repeats"),
+            }
+        }
+
+        let dropout_rate = f64::from(dropouts) / f64::from(count);
+        assert!((dropout_rate - 0.25).abs() < 0.03, "{dropout_rate}");
+        // Per axis, the two axes together have twice the variance
+        let sigma = (squared_error / f64::from(fixes) / 2.0).sqrt();
+        assert!((sigma - 10.0).abs() < 1.0, "{sigma}");
+
+        let mut exact = Simulator::new(&config(true), "sim", 42).unwrap();
+        assert_eq!(
+            exact.sample(10.0, 10.0),
+            Sample::Fix(exact.fix_at(10.0, 10.0).unwrap())
+        );
+    }
+
+    #[test]
+    fn parse_waypoints_accepts_comments_and_optional_altitudes() {
+        let waypoints =
+            parse_waypoints("# departure\n47.5, 8.5, 1500\n\n47.6 8.7  # no altitude\n").unwrap();
+        assert_eq!(
+            waypoints,
+            vec![
+                Waypoint {
+                    lat: 47.5,
+                    lon: 8.5,
+                    altitude_ft: Some(1500.0),
+                },
+                Waypoint {
+                    lat: 47.6,
+                    lon: 8.7,
+                    altitude_ft: None,
+                },
+            ]
+        );
+        assert_eq!(
+            parse_waypoints(&format_waypoints(&waypoints)),
+            Ok(waypoints)
+        );
+    }
+
+    #[test]
+    fn parse_waypoints_reports_the_line() {
+        assert_eq!(
+            parse_waypoints("47.5, 8.5\nLSZH\n"),
+            Err("Line 2: expected latitude and longitude".to_owned())
+        );
+        assert_eq!(
+            parse_waypoints("# comment\n91, 8.5\n"),
+            Err("Line 2: position out of range".to_owned())
+        );
+        assert_eq!(
+            parse_waypoints("47.5, 181\n"),
+            Err("Line 1: position out of range".to_owned())
+        );
+        assert!(Simulator::new(
+            &SimulatorConfig {
+                waypoints: "47.5, 8.5\n".to_owned(),
+                ..Default::default()
+            },
+            "sim",
+            1
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn final_approach_descends_on_three_degrees_to_fifty_feet() {
+        let threshold = RealCoordinate { lat: 0.0, lon: 0.0 };
+        let faf = geo::destination(&threshold, 90.0, 5.0 * geo::METRES_PER_NM);
+        let waypoints = final_approach(&faf, &threshold, 400.0);
+        assert_eq!(waypoints.len(), 3);
+
+        let height_at = |distance_m: f64| {
+            400.0 + 50.0 + distance_m * 3f64.to_radians().tan() / geo::METRES_PER_FT
+        };
+        let expected = [
+            height_at(8.0 * geo::METRES_PER_NM),
+            height_at(5.0 * geo::METRES_PER_NM),
+            450.0,
+        ];
+        for (waypoint, expected) in waypoints.iter().zip(expected) {
+            let altitude = f64::from(waypoint.altitude_ft.unwrap());
+            assert!((altitude - expected).abs() < 1.0, "{altitude} {expected}");
+        }
+
+        // The lead-in continues the final course outwards
+        let start = waypoints[0].position();
+        assert!((geo::distance_m(&start, &faf) - 3.0 * geo::METRES_PER_NM).abs() < 1.0);
+        assert!((geo::bearing_deg(&start, &threshold) - 270.0).abs() < 0.01);
+        assert_eq!(waypoints[2].position(), threshold);
+    }
+}
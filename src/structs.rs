@@ -9,6 +9,155 @@ pub struct LiveChartAppData {
     pub points: Vec<CoordinatePair>,
     #[serde(skip)]
     pub view_state: Option<ViewState>,
+    /// Last track log opened in the replay window.
+    pub replay_path: String,
+    /// Start a recording as soon as a live fix arrives.
+    pub auto_record: bool,
+    pub ownship_display: OwnshipDisplay,
+    /// Chart on screen, empty for the built-in chart.
+    pub chart_path: String,
+    pub library: Vec<crate::library::LibraryChart>,
+    /// Open the chart covering the ownship when it leaves the active one.
+    pub auto_select_chart: bool,
+    pub feeds: Vec<crate::feeds::FeedConfig>,
+    pub traffic_display: crate::traffic::TrafficDisplay,
+    pub conflict_thresholds: crate::conflict::ConflictThresholds,
+    pub fleet_display: crate::fleet::FleetDisplay,
+    pub outputs: Vec<crate::outputs::OutputConfig>,
+    /// ARINC 424 navigation data, loaded at startup.
+    pub navdb_path: String,
+    /// Folder with the OurAirports CSV files, loaded along with `navdb_path`.
+    pub ourairports_path: String,
+    /// Airport identifier of the chart on screen, stored with its library entry.
+    pub chart_airport: String,
+    /// CIFP identifier of the approach on the chart, e.g. `I19C`.
+    pub chart_procedure: String,
+    /// Draw the coded procedure over the chart.
+    pub show_procedure: bool,
+    /// Show course and glide path deviation for the chart's approach.
+    pub show_deviation: bool,
+    /// Last flight plan opened in the route window.
+    pub flight_plan_path: String,
+    /// OpenAir airspace file, loaded at startup.
+    pub airspace_path: String,
+    pub show_airspace: bool,
+}
+
+/// What to draw around the ownship symbol.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct OwnshipDisplay {
+    pub show_trail: bool,
+    /// Length of the breadcrumb trail.
+    pub trail_minutes: f32,
+    /// Predicted track line with 1, 2 and 5 minute marks.
+    pub show_prediction: bool,
+    /// How far the follow anchor sits behind the view centre, as a fraction of half the view.
+    pub look_ahead: f32,
+    /// Circle of the 95% horizontal accuracy around the ownship.
+    pub show_accuracy: bool,
+    /// Move the ownship between fixes instead of jumping at each one.
+    pub smooth_motion: bool,
+}
+
+impl Default for OwnshipDisplay {
+    fn default() -> Self {
+        Self {
+            show_trail: true,
+            trail_minutes: 5.0,
+            show_prediction: true,
+            look_ahead: 0.0,
+            show_accuracy: true,
+            smooth_motion: true,
+        }
+    }
+}
+
+/// State that only exists while the app is running and is never persisted.
+#[derive(Default)]
+pub struct LiveState {
+    /// Most recent ownship fix from whichever source is active.
+    pub ownship: Option<Fix>,
+    pub trail: crate::ownship::Trail,
+    pub replay: Option<crate::replay::Replay>,
+    pub replay_window_open: bool,
+    pub replay_error: Option<String>,
+    pub recorder: Option<crate::recording::Recorder>,
+    pub recording_window_open: bool,
+    pub recording_status: Option<String>,
+    pub sessions: Vec<crate::recording::Session>,
+    /// Set by a manual stop so auto-record doesn't start again until the feed reconnects.
+    pub auto_record_paused: bool,
+    /// Session waiting for the user to confirm its deletion.
+    pub session_to_delete: Option<std::path::PathBuf>,
+    /// Image sizes by chart path, so we don't decode headers every frame.
+    pub chart_sizes: std::collections::HashMap<String, (u32, u32)>,
+    pub library_window_open: bool,
+    pub library_new_path: String,
+    pub library_error: Option<String>,
+    pub feeds: Vec<crate::feeds::Feed>,
+    pub feeds_window_open: bool,
+    pub traffic: crate::traffic::TrafficTable,
+    /// Geometric minus pressure altitude of the ownship, when a GDL 90 receiver reports both.
+    pub pressure_offset_ft: Option<f32>,
+    /// Targets at advisory level or above, recomputed every frame.
+    pub threats: std::collections::HashMap<
+        crate::traffic::TargetId,
+        (crate::conflict::ThreatLevel, crate::conflict::Cpa),
+    >,
+    pub fleet: crate::fleet::Fleet,
+    /// Fleet aircraft followed instead of the ownship.
+    pub fleet_focus: Option<String>,
+    /// Unix time the current ownship fix arrived, which differs from its own time in replays.
+    pub ownship_received: Option<f64>,
+    pub outputs: Vec<crate::outputs::Output>,
+    pub outputs_window_open: bool,
+    pub diagnostics_window_open: bool,
+    /// Index into `feeds` of the raw view.
+    pub diagnostics_feed: usize,
+    pub diagnostics_paused: bool,
+    pub diagnostics_status: Option<String>,
+    /// Last exported capture, for opening in the replay.
+    pub diagnostics_capture: Option<std::path::PathBuf>,
+    /// Fix quality reported by the ownship source.
+    pub gnss: Option<crate::gnss::GnssQuality>,
+    /// Unix time `gnss` arrived.
+    pub gnss_received: Option<f64>,
+    pub gnss_window_open: bool,
+    pub navdb: Option<std::sync::Arc<crate::navdb::NavDatabase>>,
+    /// CIFP and OurAirports paths of the database that is loaded or loading, so they are only
+    /// read once.
+    pub navdb_source: Option<(String, String)>,
+    pub navdb_loading: Option<std::sync::mpsc::Receiver<Result<crate::navdb::NavDatabase, String>>>,
+    pub navdb_status: Option<String>,
+    pub navdb_new_path: String,
+    pub ourairports_new_path: String,
+    pub navdb_window_open: bool,
+    pub fix_lookup: Option<crate::navdb::FixLookup>,
+    pub procedures_window_open: bool,
+    pub procedure_georef: Option<crate::procedures::ProcedureGeoref>,
+    pub fix_search: String,
+    /// Put the cursor in the search field the next time the menu shows it.
+    pub fix_search_focus: bool,
+    pub found_fix: Option<crate::fix_search::FoundFix>,
+    pub flight_plan: Option<crate::flightplan::FlightPlan>,
+    /// Path of the flight plan that is loaded, so it is only read once.
+    pub flight_plan_source: Option<String>,
+    pub flight_plan_error: Option<String>,
+    pub flight_plan_new_path: String,
+    /// Index of the leg being flown, from waypoint `i` to `i + 1`.
+    pub active_leg: Option<usize>,
+    pub route_window_open: bool,
+    pub airspaces: Vec<crate::airspace::Airspace>,
+    /// Path of the airspace file that is loaded, so it is only read once.
+    pub airspace_source: Option<String>,
+    pub airspace_loading:
+        Option<std::sync::mpsc::Receiver<Result<Vec<crate::airspace::Airspace>, String>>>,
+    /// `airspaces` in image pixels for the current georeference.
+    pub airspace_outlines: Option<crate::airspace::PixelOutlines>,
+    pub airspace_status: Option<String>,
+    pub airspace_new_path: String,
+    pub airspace_window_open: bool,
 }
 #[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
 pub struct CoordinatePair {
@@ -28,6 +177,23 @@ pub struct RealCoordinate {
     pub lon: f64,
 }
 
+/// A single ownship position report, regardless of which feed it came from.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
+pub struct Fix {
+    /// Seconds since the Unix epoch (UTC).
+    pub time: f64,
+    pub position: RealCoordinate,
+    pub altitude_ft: Option<f32>,
+    /// The altitude is GNSS height above the WGS 84 ellipsoid rather than above MSL, as GDL 90
+    /// receivers report it unless they say otherwise. The two differ by up to 100 m.
+    #[serde(default)]
+    pub altitude_hae: bool,
+    pub groundspeed_kt: Option<f32>,
+    /// True track in degrees.
+    pub track_deg: Option<f32>,
+    pub source: String,
+}
+
 impl std::convert::From<egui::Pos2> for PixelCoordinate {
     fn from(value: egui::Pos2) -> Self {
         PixelCoordinate {
@@ -55,7 +221,28 @@ impl Default for crate::app::LivechartApp {
             data: LiveChartAppData {
                 points: Vec::new(),
                 view_state: None,
+                replay_path: String::new(),
+                auto_record: false,
+                ownship_display: OwnshipDisplay::default(),
+                chart_path: String::new(),
+                library: Vec::new(),
+                auto_select_chart: false,
+                feeds: Vec::new(),
+                traffic_display: crate::traffic::TrafficDisplay::default(),
+                conflict_thresholds: crate::conflict::ConflictThresholds::default(),
+                fleet_display: crate::fleet::FleetDisplay::default(),
+                outputs: Vec::new(),
+                navdb_path: String::new(),
+                ourairports_path: String::new(),
+                chart_airport: String::new(),
+                chart_procedure: String::new(),
+                show_procedure: false,
+                show_deviation: false,
+                flight_plan_path: String::new(),
+                airspace_path: String::new(),
+                show_airspace: false,
             },
+            live: LiveState::default(),
         }
     }
 }
@@ -65,6 +252,14 @@ pub struct ViewState {
     pub scale: f32,
     pub offset: Vec2,
     pub ps_sidebar_shown: bool,
+    /// Keep the ownship at the follow anchor. Manual panning turns this off.
+    pub follow_ownship: bool,
+    /// Rotate the chart so the ownship's track points up.
+    pub track_up: bool,
+    /// Clockwise rotation of the chart in radians, around `pivot`.
+    pub rotation: f32,
+    /// Rotation centre in normalized image coordinates (0..1).
+    pub pivot: Vec2,
 }
 
 impl Default for ViewState {
@@ -73,6 +268,10 @@ impl Default for ViewState {
             scale: 1.0,
             offset: Vec2 { x: 0.0, y: 0.0 },
             ps_sidebar_shown: true,
+            follow_ownship: false,
+            track_up: false,
+            rotation: 0.0,
+            pivot: Vec2::splat(0.5),
         }
     }
 }
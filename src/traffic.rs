@@ -0,0 +1,406 @@
+//! Traffic targets merged from every feed, and how they are drawn on the chart.
+
+use std::collections::HashMap;
+
+use crate::app::LivechartApp;
+use crate::conflict::ThreatLevel;
+use crate::gdl90;
+use crate::geo::{self, Georeference};
+use crate::structs::RealCoordinate;
+
+/// Vertical rates below this are drawn without a trend arrow.
+const TREND_THRESHOLD_FPM: i32 = 500;
+
+/// Targets from different feeds closer than this are taken to be the same aircraft. Two gliders
+/// sharing a thermal can come closer, but they are normally both seen by the same feed.
+const MERGE_DISTANCE_M: f64 = 100.0;
+const MERGE_ALTITUDE_FT: i32 = 200;
+/// Only positions this fresh are compared, so a target doesn't swallow another that passes
+/// through its stale position.
+const MERGE_MAX_AGE_S: f64 = 5.0;
+
+/// Identifies a target across feeds. ICAO addresses are used as-is, anything else (GDL 90
+/// self-assigned addresses, ...) is moved out of the 24 bit ICAO range.
+pub type TargetId = u32;
+
+pub const NON_ICAO_FLAG: TargetId = 1 << 24;
+
+/// Whatever a feed knows about a target in one message. `None` fields are left unchanged, since
+/// e.g. SBS-1 spreads a target over several message types.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrafficUpdate {
+    pub id: TargetId,
+    pub callsign: Option<String>,
+    pub position: Option<RealCoordinate>,
+    /// How old `position` already was when the update arrived, for feeds that say so.
+    pub position_age_s: f64,
+    /// Pressure altitude in feet, unless `altitude_geometric` is set.
+    pub altitude_ft: Option<i32>,
+    /// The altitude is GNSS altitude above MSL, as FLARM and OGN report it.
+    pub altitude_geometric: bool,
+    pub groundspeed_kt: Option<f32>,
+    pub track_deg: Option<f32>,
+    pub vertical_rate_fpm: Option<i32>,
+    pub on_ground: Option<bool>,
+    /// Alert raised by the device itself (FLARM alarm, GDL 90 traffic alert).
+    pub alert: Option<ThreatLevel>,
+    pub source: String,
+}
+
+impl TrafficUpdate {
+    pub fn from_gdl90(report: &gdl90::Report, source: &str) -> Self {
+        let id = match report.address_type {
+            // ADS-B or TIS-B with an ICAO address
+            0 | 2 => report.address,
+            _ => report.address | NON_ICAO_FLAG,
+        };
+
+        Self {
+            id,
+            callsign: (!report.callsign.is_empty()).then(|| report.callsign.clone()),
+            position: report.position.clone(),
+            position_age_s: 0.0,
+            altitude_ft: report.altitude_ft,
+            altitude_geometric: false,
+            groundspeed_kt: report.groundspeed_kt.map(f32::from),
+            track_deg: report.track_deg,
+            vertical_rate_fpm: report.vertical_rate_fpm,
+            on_ground: Some(!report.airborne),
+            alert: Some(if report.alert {
+                ThreatLevel::Advisory
+            } else {
+                ThreatLevel::None
+            }),
+            source: source.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Target {
+    pub id: TargetId,
+    pub callsign: Option<String>,
+    pub position: Option<RealCoordinate>,
+    pub altitude_ft: Option<i32>,
+    pub altitude_geometric: bool,
+    pub groundspeed_kt: Option<f32>,
+    pub track_deg: Option<f32>,
+    pub vertical_rate_fpm: Option<i32>,
+    pub on_ground: bool,
+    pub alert: ThreatLevel,
+    /// Unix time of the last update.
+    pub last_seen: f64,
+    /// Unix time of the last position update.
+    pub position_time: f64,
+    pub source: String,
+}
+
+impl Target {
+    fn apply(&mut self, update: TrafficUpdate, now: f64) {
+        if update.callsign.is_some() {
+            self.callsign = update.callsign;
+        }
+        // dump1090 keeps listing the last position for a while after it stops getting new ones
+        let position_time = now - update.position_age_s;
+        if update.position.is_some() && position_time >= self.position_time {
+            self.position = update.position;
+            self.position_time = position_time;
+        }
+        if update.altitude_ft.is_some() {
+            self.altitude_ft = update.altitude_ft;
+            self.altitude_geometric = update.altitude_geometric;
+        }
+        if update.groundspeed_kt.is_some() {
+            self.groundspeed_kt = update.groundspeed_kt;
+        }
+        if update.track_deg.is_some() {
+            self.track_deg = update.track_deg;
+        }
+        if update.vertical_rate_fpm.is_some() {
+            self.vertical_rate_fpm = update.vertical_rate_fpm;
+        }
+        if let Some(on_ground) = update.on_ground {
+            self.on_ground = on_ground;
+        }
+        if let Some(alert) = update.alert {
+            self.alert = alert;
+        }
+        self.last_seen = now;
+        self.source = update.source;
+    }
+
+    /// Label for the target, the callsign if known, otherwise the address.
+    pub fn label(&self) -> String {
+        self.callsign
+            .clone()
+            .unwrap_or_else(|| format!("{:06X}", self.id & (NON_ICAO_FLAG - 1)))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TrafficTable {
+    pub targets: HashMap<TargetId, Target>,
+    /// Ids that turned out to be another feed's view of an existing target, e.g. a FLARM id for an
+    /// aircraft also seen by its ICAO address over ADS-B.
+    aliases: HashMap<TargetId, TargetId>,
+}
+
+impl TrafficTable {
+    pub fn update(&mut self, mut update: TrafficUpdate, now: f64) {
+        if let Some(&id) = self.aliases.get(&update.id) {
+            update.id = id;
+        } else if let Some(id) = self.duplicate_of(&update, now) {
+            self.aliases.insert(update.id, id);
+            // Keep anything only the duplicate knew
+            if let Some(duplicate) = self.targets.remove(&update.id) {
+                update.callsign = update.callsign.or(duplicate.callsign);
+            }
+            update.id = id;
+        }
+
+        let target = self.targets.entry(update.id).or_insert_with(|| Target {
+            id: update.id,
+            ..Default::default()
+        });
+        target.apply(update, now);
+    }
+
+    /// A target from another feed that is at the same place as `update`.
+    fn duplicate_of(&self, update: &TrafficUpdate, now: f64) -> Option<TargetId> {
+        let position = update.position.as_ref()?;
+        self.targets
+            .values()
+            .filter(|target| target.id != update.id && target.source != update.source)
+            .filter(|target| now - target.position_time <= MERGE_MAX_AGE_S)
+            .find(|target| {
+                let close = target
+                    .position
+                    .as_ref()
+                    .is_some_and(|other| geo::distance_m(position, other) <= MERGE_DISTANCE_M);
+                let same_level = update
+                    .altitude_ft
+                    .zip(target.altitude_ft)
+                    .map_or(true, |(a, b)| (a - b).abs() <= MERGE_ALTITUDE_FT);
+                close && same_level
+            })
+            .map(|target| target.id)
+    }
+
+    /// Forgets targets that haven't been heard from for `max_age_s`.
+    pub fn expire(&mut self, now: f64, max_age_s: f64) {
+        self.targets
+            .retain(|_, target| now - target.last_seen <= max_age_s);
+        let targets = &self.targets;
+        self.aliases.retain(|_, id| targets.contains_key(id));
+    }
+}
+
+/// When traffic is considered stale and when it is dropped.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct TrafficDisplay {
+    pub show: bool,
+    /// Targets without a position update for this long are drawn faded.
+    pub stale_after_s: f32,
+    /// Targets not heard from for this long are removed.
+    pub remove_after_s: f32,
+    /// Hide targets further above or below the ownship than this.
+    pub altitude_filter_ft: f32,
+}
+
+impl Default for TrafficDisplay {
+    fn default() -> Self {
+        Self {
+            show: true,
+            stale_after_s: 10.0,
+            remove_after_s: 60.0,
+            altitude_filter_ft: 5000.0,
+        }
+    }
+}
+
+impl LivechartApp {
+    /// Height of `target` above the ownship in feet. Pressure altitudes are compared with the
+    /// ownship's pressure altitude, which is only known from a GDL 90 receiver. Without one the
+    /// GNSS altitude stands in for it, off by the local deviation from standard pressure.
+    pub fn relative_altitude_ft(&self, target: &Target) -> Option<i32> {
+        let ownship = self.live.ownship.as_ref()?.altitude_ft?;
+        let ownship = if target.altitude_geometric {
+            ownship
+        } else {
+            ownship - self.live.pressure_offset_ft.unwrap_or(0.0)
+        };
+        Some(target.altitude_ft? - ownship.round() as i32)
+    }
+
+    pub fn draw_traffic(
+        &self,
+        ui: &egui::Ui,
+        image_response: &egui::Response,
+        image_size: (u32, u32),
+        georeference: &Georeference,
+    ) {
+        let display = &self.data.traffic_display;
+        let now = crate::utc::now();
+        let painter = ui.painter_at(image_response.rect);
+
+        for target in self.live.traffic.targets.values() {
+            let Some(position) = &target.position else {
+                continue;
+            };
+            let relative_altitude = self.relative_altitude_ft(target);
+            if relative_altitude.is_some_and(|r| r.abs() as f32 > display.altitude_filter_ft) {
+                continue;
+            }
+
+            let stale = (now - target.position_time) as f32 > display.stale_after_s;
+            let color = match self.threat_level(target.id) {
+                ThreatLevel::Alert => egui::Color32::from_rgb(230, 0, 0),
+                ThreatLevel::Advisory => egui::Color32::from_rgb(255, 180, 0),
+                ThreatLevel::None if stale => egui::Color32::GRAY,
+                ThreatLevel::None if target.on_ground => egui::Color32::from_rgb(150, 110, 60),
+                ThreatLevel::None => egui::Color32::from_rgb(0, 170, 255),
+            };
+
+            let pos = self.real_to_screen(position, georeference, image_response, image_size);
+            if self.threat_level(target.id) != ThreatLevel::None {
+                painter.circle_stroke(pos, 13.0, egui::Stroke::new(2.0, color));
+            }
+            draw_target_symbol(
+                &painter,
+                pos,
+                self.track_on_screen(target, georeference, image_response, image_size),
+                color,
+            );
+
+            painter.text(
+                pos + egui::vec2(0.0, -12.0),
+                egui::Align2::CENTER_BOTTOM,
+                target.label(),
+                egui::FontId::proportional(11.0),
+                color,
+            );
+
+            if let Some(relative) = relative_altitude {
+                // TCAS style: hundreds of feet, signed, with a trend arrow
+                let trend = match target.vertical_rate_fpm {
+                    Some(rate) if rate >= TREND_THRESHOLD_FPM => " ↑",
+                    Some(rate) if rate <= -TREND_THRESHOLD_FPM => " ↓",
+                    _ => "",
+                };
+                painter.text(
+                    pos + egui::vec2(0.0, 12.0),
+                    egui::Align2::CENTER_TOP,
+                    format!("{:+03}{trend}", relative / 100),
+                    egui::FontId::monospace(11.0),
+                    color,
+                );
+            }
+        }
+    }
+
+    /// Screen direction of a target's track, if it has one.
+    fn track_on_screen(
+        &self,
+        target: &Target,
+        georeference: &Georeference,
+        image_response: &egui::Response,
+        image_size: (u32, u32),
+    ) -> Option<egui::Vec2> {
+        let position = target.position.as_ref()?;
+        let track = target.track_deg?;
+        let ahead = geo::destination(position, f64::from(track), 100.0);
+        let from = self.real_to_screen(position, georeference, image_response, image_size);
+        let to = self.real_to_screen(&ahead, georeference, image_response, image_size);
+        Some((to - from).normalized())
+    }
+
+    pub fn traffic_display_menu(&mut self, ui: &mut egui::Ui) {
+        let display = &mut self.data.traffic_display;
+        ui.checkbox(&mut display.show, "Traffic");
+        ui.horizontal(|ui| {
+            ui.label("Stale after");
+            ui.add(
+                egui::DragValue::new(&mut display.stale_after_s)
+                    .range(1.0..=120.0)
+                    .suffix(" s"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Remove after");
+            ui.add(
+                egui::DragValue::new(&mut display.remove_after_s)
+                    .range(5.0..=600.0)
+                    .suffix(" s"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Altitude band ±");
+            ui.add(
+                egui::DragValue::new(&mut display.altitude_filter_ft)
+                    .range(500.0..=60_000.0)
+                    .speed(100.0)
+                    .suffix(" ft"),
+            );
+        });
+    }
+}
+
+/// Chevron pointing along the track, or a diamond without one.
+pub fn draw_target_symbol(
+    painter: &egui::Painter,
+    pos: egui::Pos2,
+    direction: Option<egui::Vec2>,
+    color: egui::Color32,
+) {
+    let stroke = egui::Stroke::new(1.0, egui::Color32::BLACK);
+    let points = match direction.filter(|d| d.is_finite()) {
+        Some(forward) => {
+            let right = forward.rot90();
+            vec![
+                pos + forward * 9.0,
+                pos - forward * 7.0 + right * 7.0,
+                pos - forward * 3.0,
+                pos - forward * 7.0 - right * 7.0,
+            ]
+        }
+        None => vec![
+            pos + egui::vec2(0.0, -7.0),
+            pos + egui::vec2(7.0, 0.0),
+            pos + egui::vec2(0.0, 7.0),
+            pos + egui::vec2(-7.0, 0.0),
+        ],
+    };
+    painter.add(egui::Shape::convex_polygon(points, color, stroke));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(lat: f64, position_age_s: f64) -> TrafficUpdate {
+        TrafficUpdate {
+            id: 0xA1B2C3,
+            position: Some(RealCoordinate { lat, lon: -104.66 }),
+            position_age_s,
+            source: "dump1090".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn positions_are_timed_by_their_age() {
+        let mut table = TrafficTable::default();
+        table.update(update(39.85, 12.0), 1000.0);
+        let target = &table.targets[&0xA1B2C3];
+        assert_eq!(target.position_time, 988.0);
+        assert_eq!(target.last_seen, 1000.0);
+
+        // An older position arriving later doesn't replace a newer one
+        table.update(update(39.80, 30.0), 1001.0);
+        let target = &table.targets[&0xA1B2C3];
+        assert_eq!(target.position.as_ref().unwrap().lat, 39.85);
+        assert_eq!(target.position_time, 988.0);
+        assert_eq!(target.last_seen, 1001.0);
+    }
+}
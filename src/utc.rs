@@ -0,0 +1,89 @@
+//! Small UTC helpers so we don't need a full date/time crate for log timestamps.
+
+/// Current wall-clock time in seconds since the Unix epoch.
+pub fn now() -> f64 {
+    web_time::SystemTime::now()
+        .duration_since(web_time::UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0)
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's algorithm).
+pub fn days_from_civil(year: i32, month: u32, day: u32) -> i64 {
+    let y = i64::from(year) - i64::from(month <= 2);
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = (i64::from(month) + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Inverse of [`days_from_civil`], returns `(year, month, day)`.
+pub fn civil_from_days(days: i64) -> (i32, u32, u32) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = (yoe + era * 400 + i64::from(month <= 2)) as i32;
+    (year, month, day)
+}
+
+/// Unix time for a calendar date plus seconds into that day.
+pub fn from_civil(year: i32, month: u32, day: u32, seconds_of_day: f64) -> f64 {
+    days_from_civil(year, month, day) as f64 * 86_400.0 + seconds_of_day
+}
+
+/// Parses `YYYY-MM-DDTHH:MM:SS[.fff][Z|±HH:MM]` as used by GPX and KML.
+pub fn parse_iso8601(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let (date, time) = text.split_once(['T', ' '])?;
+
+    let mut date_parts = date.splitn(3, '-');
+    let year = date_parts.next()?.parse().ok()?;
+    let month = date_parts.next()?.parse().ok()?;
+    let day = date_parts.next()?.parse().ok()?;
+
+    // Split off the zone designator, if any
+    let (clock, offset) = if let Some(clock) = time.strip_suffix('Z') {
+        (clock, 0.0)
+    } else if let Some(idx) = time.rfind(['+', '-']) {
+        let (clock, zone) = time.split_at(idx);
+        let sign = if zone.starts_with('-') { -1.0 } else { 1.0 };
+        let (hours, minutes) = zone[1..].split_once(':').unwrap_or((&zone[1..], "0"));
+        let hours: f64 = hours.parse().ok()?;
+        let minutes: f64 = minutes.parse().ok()?;
+        (clock, sign * (hours * 3600.0 + minutes * 60.0))
+    } else {
+        (time, 0.0)
+    };
+
+    let mut clock_parts = clock.splitn(3, ':');
+    let hours: f64 = clock_parts.next()?.parse().ok()?;
+    let minutes: f64 = clock_parts.next()?.parse().ok()?;
+    let seconds: f64 = clock_parts.next().unwrap_or("0").parse().ok()?;
+
+    Some(from_civil(year, month, day, hours * 3600.0 + minutes * 60.0 + seconds) - offset)
+}
+
+/// Formats a Unix time as `YYYY-MM-DDTHH:MM:SSZ`.
+pub fn format_iso8601(time: f64) -> String {
+    let days = (time / 86_400.0).floor();
+    let (year, month, day) = civil_from_days(days as i64);
+    format!("{year:04}-{month:02}-{day:02}T{}Z", format_clock(time))
+}
+
+/// Formats the time-of-day part of a Unix time as `HH:MM:SS`.
+pub fn format_clock(time: f64) -> String {
+    let seconds = time.rem_euclid(86_400.0) as u32;
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
@@ -0,0 +1,119 @@
+//! X-Plane's UDP `DATA` output, the rows ticked under Settings → Data Output → "Network via UDP".
+//!
+//! A packet is `DATA` plus one byte, then any number of 36 byte rows: a little-endian `i32` row
+//! index and eight `f32` values. We use rows 3 (speeds), 17 (attitude and heading) and 20
+//! (position).
+
+use crate::structs::{Fix, RealCoordinate};
+
+/// Where LiveChart listens for X-Plane by default. X-Plane itself listens on 49000.
+pub const DEFAULT_PORT: u16 = 49003;
+
+const HEADER: &[u8; 4] = b"DATA";
+const ROW_LENGTH: usize = 36;
+const SPEEDS: i32 = 3;
+const ATTITUDE: i32 = 17;
+const POSITION: i32 = 20;
+
+/// The rows of one packet we know about. X-Plane sends whichever rows are ticked, so any of them
+/// may be missing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Data {
+    pub position: Option<RealCoordinate>,
+    pub altitude_ft: Option<f32>,
+    pub groundspeed_kt: Option<f32>,
+    /// True heading. X-Plane doesn't output the track, but without wind the two are the same.
+    pub heading_deg: Option<f32>,
+}
+
+pub fn decode(packet: &[u8]) -> Option<Data> {
+    if packet.get(..4)? != HEADER {
+        return None;
+    }
+
+    let mut data = Data::default();
+    for row in packet.get(5..)?.chunks_exact(ROW_LENGTH) {
+        let index = i32::from_le_bytes([row[0], row[1], row[2], row[3]]);
+        let value = |i: usize| {
+            let offset = 4 + i * 4;
+            f32::from_le_bytes([
+                row[offset],
+                row[offset + 1],
+                row[offset + 2],
+                row[offset + 3],
+            ])
+        };
+        match index {
+            SPEEDS => data.groundspeed_kt = Some(value(3)),
+            ATTITUDE => data.heading_deg = Some(value(2).rem_euclid(360.0)),
+            POSITION => {
+                data.position = Some(RealCoordinate {
+                    lat: f64::from(value(0)),
+                    lon: f64::from(value(1)),
+                });
+                data.altitude_ft = Some(value(2));
+            }
+            _ => {}
+        }
+    }
+    Some(data)
+}
+
+/// A packet with the speeds, attitude and position rows for a fix, as X-Plane would send it.
+pub fn encode(fix: &Fix) -> Vec<u8> {
+    let mut packet = HEADER.to_vec();
+    packet.push(b'*');
+    let mut row = |index: i32, values: [f32; 8]| {
+        packet.extend(index.to_le_bytes());
+        for value in values {
+            packet.extend(value.to_le_bytes());
+        }
+    };
+
+    let groundspeed = fix.groundspeed_kt.unwrap_or(0.0);
+    let heading = fix.track_deg.unwrap_or(0.0);
+    let altitude = fix.altitude_ft.unwrap_or(0.0);
+    row(
+        SPEEDS,
+        [
+            groundspeed,
+            groundspeed,
+            groundspeed,
+            groundspeed,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+        ],
+    );
+    row(ATTITUDE, [0.0, 0.0, heading, heading, 0.0, 0.0, 0.0, 0.0]);
+    row(
+        POSITION,
+        [
+            fix.position.lat as f32,
+            fix.position.lon as f32,
+            altitude,
+            0.0,
+            0.0,
+            altitude,
+            0.0,
+            0.0,
+        ],
+    );
+    packet
+}
+
+impl Data {
+    /// X-Plane packets carry no time, so the fix gets the time of arrival.
+    pub fn to_fix(&self, time: f64, source: &str) -> Option<Fix> {
+        Some(Fix {
+            time,
+            position: self.position.clone()?,
+            altitude_ft: self.altitude_ft,
+            altitude_hae: false,
+            groundspeed_kt: self.groundspeed_kt,
+            track_deg: self.heading_deg,
+            source: source.to_owned(),
+        })
+    }
+}